@@ -0,0 +1,116 @@
+//! Benchmarks for scanning the data directories, with and without the cache,
+//! on a synthetic tree of desktop entries. Run with `cargo bench`.
+
+use std::collections::HashMap;
+use std::env::VarError;
+use std::fs;
+use std::path::PathBuf;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+use i3_dmenu_desktop_rs::desktop_entry::DesktopEntry;
+use i3_dmenu_desktop_rs::{Persistence, XDGManager};
+
+/// The number of desktop entry files in the synthetic tree.
+const NUM_ENTRIES: usize = 1000;
+
+const LOCALES: [&str; 12] = ["ar", "cs", "de", "es", "fr", "it", "ja", "nl", "pl", "pt_BR", "ru", "zh_CN"];
+
+/// A temporary data directory with NUM_ENTRIES desktop entries, which is
+/// deleted when dropped.
+struct Tree {
+    root: PathBuf,
+    paths: Vec<String>,
+}
+
+impl Tree {
+    fn new() -> Self {
+        let root = std::env::temp_dir().join(format!("i3-dmenu-desktop-rs-bench-{}", std::process::id()));
+        let app_dir = root.join("data/applications");
+        fs::create_dir_all(&app_dir).unwrap();
+        let paths = (0..NUM_ENTRIES)
+            .map(|i| {
+                let path = app_dir.join(format!("org.example.App{}.desktop", i));
+                fs::write(&path, Self::entry(i)).unwrap();
+                path.to_str().unwrap().to_string()
+            })
+            .collect();
+        Self { root, paths }
+    }
+
+    /// Returns a desktop entry which resembles those of real applications,
+    /// with translations and an action.
+    fn entry(i: usize) -> String {
+        let mut contents = format!("[Desktop Entry]\nType=Application\nName=App {i}\nComment=Does things\n\
+                                    Exec=app{i} --new-window %U\nIcon=app{i}\nCategories=Utility;\n\
+                                    Keywords=app;example;\nActions=new-window;\n");
+        for locale in LOCALES {
+            contents.push_str(&format!("Name[{locale}]=App {i} ({locale})\nComment[{locale}]=Does things ({locale})\n"));
+        }
+        contents.push_str(&format!("\n[Desktop Action new-window]\nName=New Window\nExec=app{i} --new-window\n"));
+        contents
+    }
+
+    fn path(&self, relative: &str) -> String {
+        self.root.join(relative).to_str().unwrap().to_string()
+    }
+
+    fn manager(&self, cache: bool) -> XDGManager<impl Fn(&str) -> Result<String, VarError>> {
+        let env = HashMap::from([
+            ("HOME".to_string(), self.path("home")),
+            ("XDG_DATA_HOME".to_string(), self.path("home/.local/share")),
+            ("XDG_DATA_DIRS".to_string(), self.path("data")),
+            ("XDG_CACHE_HOME".to_string(), self.path("cache")),
+            ("PATH".to_string(), "/usr/bin:/bin".to_string()),
+            ("LANG".to_string(), "de_DE.UTF-8".to_string()),
+        ]);
+        let mut mgr = XDGManager::new(move |key| env.get(key).cloned().ok_or(VarError::NotPresent));
+        mgr.set_persistence(Persistence { cache, history: false });
+        mgr
+    }
+
+    fn remove_cache(&self) {
+        let _ = fs::remove_dir_all(self.path("cache"));
+        fs::create_dir_all(self.path("cache")).unwrap();
+    }
+}
+
+impl Drop for Tree {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+fn bench_scan(c: &mut Criterion) {
+    let tree = Tree::new();
+    let locale_keys = ["de_DE".to_string(), "de".to_string()];
+
+    c.bench_function("parse", |b| b.iter(|| {
+        for path in &tree.paths {
+            DesktopEntry::parse(path, &locale_keys).unwrap();
+        }
+    }));
+
+    // Scanning without a cache, e.g. with --no-cache
+    tree.remove_cache();
+    let mgr = tree.manager(false);
+    c.bench_function("get_desktop_entry_map/uncached", |b| b.iter(|| mgr.get_desktop_entry_map()));
+
+    // Scanning when the cache is missing, which parses everything and
+    // writes the cache
+    let mgr = tree.manager(true);
+    c.bench_function("get_desktop_entry_map/cache_write", |b| {
+        b.iter_batched(|| tree.remove_cache(), |_| mgr.get_desktop_entry_map(), BatchSize::PerIteration)
+    });
+
+    // Scanning when all entries are up to date in the cache
+    mgr.get_desktop_entry_map();
+    c.bench_function("get_desktop_entry_map/cached", |b| b.iter(|| mgr.get_desktop_entry_map()));
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(20);
+    targets = bench_scan
+}
+criterion_main!(benches);
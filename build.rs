@@ -0,0 +1,33 @@
+//! Compiles the message catalogs in po/ with msgfmt when the nls feature is
+//! enabled (see the i18n module).
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-changed=po");
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap()).join("locale");
+    println!("cargo:rustc-env=I3_DMENU_DESKTOP_RS_CATALOGS={}", out_dir.display());
+    if env::var_os("CARGO_FEATURE_NLS").is_none() {
+        return;
+    }
+    let mut paths: Vec<_> = fs::read_dir("po").unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "po"))
+        .collect();
+    paths.sort();
+    for path in paths {
+        let lang = path.file_stem().unwrap().to_str().unwrap();
+        let dir = out_dir.join(lang).join("LC_MESSAGES");
+        fs::create_dir_all(&dir).unwrap();
+        let status = Command::new("msgfmt")
+            .arg("--check")
+            .arg("-o").arg(dir.join("i3-dmenu-desktop-rs.mo"))
+            .arg(&path)
+            .status()
+            .unwrap_or_else(|err| panic!("could not run msgfmt, which the nls feature needs: {}", err));
+        assert!(status.success(), "msgfmt failed for {}", path.display());
+    }
+}
@@ -0,0 +1,27 @@
+#![no_main]
+
+use std::time::SystemTime;
+
+use libfuzzer_sys::fuzz_target;
+
+use i3_dmenu_desktop_rs::desktop_entry::DesktopEntry;
+
+// Runs a desktop entry file through everything which happens between
+// reading it and launching it, none of which may panic.
+fuzz_target!(|data: &[u8]| {
+    let Ok(contents) = std::str::from_utf8(data) else {
+        return;
+    };
+    let locale_keys = ["de_DE".to_string(), "de".to_string()];
+    let Ok(mut app) = DesktopEntry::parse_contents(contents, "/fuzz/app.desktop", SystemTime::UNIX_EPOCH, &locale_keys) else {
+        return;
+    };
+    app.escape_chars_for_exec_keys();
+    app.replace_field_codes(app.get_exec_str(), &["/tmp/a file", "https://example.com/?a=%20"]);
+    app.is_wine();
+    app.to_json();
+    for action in &app.keys.Actions {
+        let action_app = app.for_action(action);
+        action_app.replace_field_codes(action_app.get_exec_str(), &[]);
+    }
+});
@@ -1,10 +1,45 @@
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
+use std::ffi::OsStr;
 use std::fmt;
-use std::io::{self, Write};
-use std::process::{Command, Stdio};
-use std::str::Utf8Error;
+use std::io::{self, Read, Write};
+use std::iter;
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use clap::ValueEnum;
+use serde::Deserialize;
 
 use super::DesktopEntry;
+use super::desktop_entry::{quote_for_sh, Origin};
+use super::config::Config;
+use super::i18n::{gettext, tr};
+use super::menu::{MenuItem, MenuProgram};
+use super::session::SessionType;
+use super::startup_notify::StartupSequence;
+use super::utils::find_executable;
+
+/// How the command for the selected application gets executed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LaunchBackend {
+    /// Pass the command to i3's exec command via i3-msg. i3 takes care of
+    /// startup notification itself.
+    I3,
+    /// Spawn the command directly with `sh -c`.
+    Exec,
+    /// Run the command with `uwsm app`, which places it in a systemd unit.
+    Uwsm,
+    /// Run the command in a transient `app-<id>@<random>.service` systemd
+    /// user unit, as recommended for systemd-managed sessions.
+    SystemdRun,
+    /// Delegate everything (field codes, D-Bus activation, terminals) to
+    /// glib via `gio launch <desktop file>`.
+    Gio,
+    /// Like `gio`, but via `gtk-launch <desktop file ID>`.
+    GtkLaunch,
+}
 
 /// Returns a transformed string which can be passed to i3's exec command.
 ///
@@ -41,67 +76,1051 @@ pub fn escape_for_i3_exec(cmd: &str) -> String {
     String::from_iter(new_chars)
 }
 
+/// Which environment variables launched applications get, compared to the
+/// environment of the launcher. Names ending with "*" match all variables
+/// with that prefix, e.g. "VIRTUAL_ENV*".
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnvPolicy {
+    /// If set, only the variables matching one of these names are passed on
+    pub allow: Option<Vec<String>>,
+    /// Variables which are not passed on
+    pub deny: Vec<String>,
+    /// Variables which are set, overriding the inherited values
+    pub set: BTreeMap<String, String>,
+}
+
+impl EnvPolicy {
+    fn matches(patterns: &[String], name: &str) -> bool {
+        patterns.iter().any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == pattern,
+        })
+    }
+
+    /// Returns the variables among `names` which must be removed.
+    pub fn removed_vars(&self, names: impl IntoIterator<Item=String>) -> Vec<String> {
+        let mut removed: Vec<_> = names.into_iter()
+            .filter(|name| !self.set.contains_key(name))
+            .filter(|name| {
+                Self::matches(&self.deny, name)
+                    || self.allow.as_ref().is_some_and(|allow| !Self::matches(allow, name))
+            })
+            .collect();
+        removed.sort();
+        removed
+    }
+
+    /// Returns a copy of the policy which also sets `vars`, overriding the
+    /// values from `set`.
+    fn with_vars(&self, vars: &BTreeMap<String, String>) -> Self {
+        let mut policy = self.clone();
+        policy.set.extend(vars.iter().map(|(name, value)| (name.clone(), value.clone())));
+        policy
+    }
+
+    /// Returns the arguments of `env` which set the variables of `set`, for
+    /// commands which i3 starts with its own environment.
+    fn env_args(&self) -> Vec<String> {
+        if self.set.is_empty() {
+            return Vec::new();
+        }
+        iter::once("env".to_string())
+            .chain(self.set.iter().map(|(name, value)| format!("{name}={value}")))
+            .collect()
+    }
+
+    // The names of the variables in the environment of the launcher
+    fn inherited_vars() -> impl Iterator<Item=String> {
+        std::env::vars_os().filter_map(|(name, _)| name.into_string().ok())
+    }
+
+    /// Applies the policy to a command which inherits the environment of
+    /// the launcher.
+    fn apply(&self, command: &mut Command) {
+        for name in self.removed_vars(Self::inherited_vars()) {
+            command.env_remove(name);
+        }
+        command.envs(&self.set);
+    }
+
+    /// Returns the arguments which apply the policy to a unit started by
+    /// systemd-run. The unit gets the environment of the systemd user
+    /// manager rather than the one of the launcher, so the variables to
+    /// remove are still taken from the latter.
+    fn systemd_run_args(&self) -> Vec<String> {
+        let removed = self.removed_vars(Self::inherited_vars());
+        let mut args = Vec::new();
+        if !removed.is_empty() {
+            args.push(format!("--property=UnsetEnvironment={}", removed.join(" ")));
+        }
+        args.extend(self.set.iter().map(|(name, value)| format!("--setenv={name}={value}")));
+        args
+    }
+}
+
+/// The GPU which an application is rendered on, in systems which have more
+/// than one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Gpu {
+    Integrated,
+    Discrete,
+}
+
+impl Gpu {
+    /// Returns the GPU for `app`: the one from the config for its desktop
+    /// file ID or else for one of its categories, or the discrete GPU if
+    /// the entry has PrefersNonDefaultGPU=true. `None` means the default GPU.
+    pub fn for_app(app: &DesktopEntry, config: &Config) -> Option<Self> {
+        config.gpu.get(&app.source.id)
+            .or_else(|| config.gpu.iter().find(|(key, _)| app.keys.Categories.contains(key)).map(|(_, gpu)| gpu))
+            .copied()
+            .or(app.keys.PrefersNonDefaultGPU.then_some(Self::Discrete))
+    }
+
+    /// The value of the %gpu placeholder in wrappers.
+    fn name(gpu: Option<Self>) -> &'static str {
+        match gpu {
+            Some(Self::Integrated) => "integrated",
+            Some(Self::Discrete) => "discrete",
+            None => "default",
+        }
+    }
+}
+
+/// The environment variables which select each GPU. The defaults work with
+/// Mesa drivers; the proprietary NVIDIA driver needs
+/// `__NV_PRIME_RENDER_OFFLOAD=1` and `__GLX_VENDOR_LIBRARY_NAME=nvidia`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GpuEnv {
+    pub integrated: BTreeMap<String, String>,
+    pub discrete: BTreeMap<String, String>,
+}
+
+impl Default for GpuEnv {
+    fn default() -> Self {
+        Self {
+            integrated: BTreeMap::from([("DRI_PRIME".to_string(), "0".to_string())]),
+            discrete: BTreeMap::from([("DRI_PRIME".to_string(), "1".to_string())]),
+        }
+    }
+}
+
+impl GpuEnv {
+    fn vars(&self, gpu: Gpu) -> &BTreeMap<String, String> {
+        match gpu {
+            Gpu::Integrated => &self.integrated,
+            Gpu::Discrete => &self.discrete,
+        }
+    }
+}
+
+/// Returns the environment policy from the config, which also selects `gpu`.
+fn get_env_policy(gpu: Option<Gpu>, config: &Config) -> EnvPolicy {
+    match gpu {
+        Some(gpu) => config.env.with_vars(config.gpu_env.vars(gpu)),
+        None => config.env.clone(),
+    }
+}
+
+/// An I/O scheduling class, see ionice(1).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IoClass {
+    Realtime,
+    BestEffort,
+    Idle,
+}
+
+impl IoClass {
+    /// The name used by ionice and by systemd's IOSchedulingClass=.
+    fn name(self) -> &'static str {
+        match self {
+            Self::Realtime => "realtime",
+            Self::BestEffort => "best-effort",
+            Self::Idle => "idle",
+        }
+    }
+}
+
+/// How the kernel prioritizes a launched application, so that heavy apps
+/// cannot starve the compositor.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProcessPriority {
+    /// The niceness, from -20 (highest priority) to 19 (lowest)
+    pub nice: Option<i32>,
+    pub io_class: Option<IoClass>,
+    /// From -1000 (never killed when out of memory) to 1000 (killed first)
+    pub oom_score_adj: Option<i32>,
+}
+
+impl ProcessPriority {
+    /// Returns the priority for `app` from the config: the settings for its
+    /// categories, overridden by the ones for its desktop file ID.
+    pub fn for_app(app: &DesktopEntry, config: &Config) -> Self {
+        let mut priority = Self::default();
+        let matching = config.priority.iter()
+            .filter(|(key, _)| app.keys.Categories.contains(key))
+            .chain(config.priority.get_key_value(&app.source.id));
+        for (_, other) in matching {
+            priority.nice = other.nice.or(priority.nice);
+            priority.io_class = other.io_class.or(priority.io_class);
+            priority.oom_score_adj = other.oom_score_adj.or(priority.oom_score_adj);
+        }
+        priority
+    }
+
+    /// Returns the commands (from util-linux) which the launched command is
+    /// prefixed with to apply the priority.
+    fn argv_prefix(&self) -> Vec<String> {
+        let mut argv = Vec::new();
+        if let Some(nice) = self.nice {
+            argv.extend(["nice".to_string(), "-n".to_string(), nice.to_string()]);
+        }
+        if let Some(io_class) = self.io_class {
+            argv.extend(["ionice".to_string(), "-c".to_string(), io_class.name().to_string()]);
+        }
+        if let Some(oom_score_adj) = self.oom_score_adj {
+            argv.extend(["choom".to_string(), "-n".to_string(), oom_score_adj.to_string(), "--".to_string()]);
+        }
+        argv
+    }
+
+    /// Returns the arguments which apply the priority to a unit started by
+    /// systemd-run.
+    fn systemd_run_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(nice) = self.nice {
+            args.push(format!("--nice={nice}"));
+        }
+        if let Some(io_class) = self.io_class {
+            args.push(format!("--property=IOSchedulingClass={}", io_class.name()));
+        }
+        if let Some(oom_score_adj) = self.oom_score_adj {
+            args.push(format!("--property=OOMScoreAdjust={oom_score_adj}"));
+        }
+        args
+    }
+}
+
 #[derive(Debug)]
 pub enum ChildProcessError {
     IoError(io::Error),
-    BadOutputError(Utf8Error),
     ProcessFailed(String),
+    /// The user dismissed the menu without choosing anything
+    Cancelled,
+    /// The menu program (or none of the fallbacks) is installed
+    MenuNotInstalled(String),
+    /// The selected entry or command could not be started
+    LaunchFailed(io::Error),
+    /// The menu program did not exit within the configured timeout
+    MenuTimedOut(Duration),
+    /// Another instance is already showing a menu on the same display
+    AlreadyRunning,
 }
 
-impl From<io::Error> for ChildProcessError {
-    fn from(error: io::Error) -> Self { Self::IoError(error) }
+impl ChildProcessError {
+    /// Returns the exit status of the launcher when this error occurs:
+    ///
+    /// * 0: an entry or command was launched (not an error)
+    /// * 1: an internal error, e.g. the menu program crashed
+    /// * 2: the user cancelled the menu
+    /// * 3: the menu program is not installed
+    /// * 4: the selected entry or command could not be launched
+    /// * 5: the menu program timed out
+    /// * 6: another instance is already showing a menu
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::IoError(_) | Self::ProcessFailed(_) => 1,
+            Self::Cancelled => 2,
+            Self::MenuNotInstalled(_) => 3,
+            Self::LaunchFailed(_) => 4,
+            Self::MenuTimedOut(_) => 5,
+            Self::AlreadyRunning => 6,
+        }
+    }
 }
 
-impl From<Utf8Error> for ChildProcessError {
-    fn from(error: Utf8Error) -> Self { Self::BadOutputError(error) }
+impl From<io::Error> for ChildProcessError {
+    fn from(error: io::Error) -> Self { Self::IoError(error) }
 }
 
 impl fmt::Display for ChildProcessError {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match self {
             Self::IoError(err) => err.fmt(f),
-            Self::BadOutputError(err) => err.fmt(f),
             Self::ProcessFailed(ref msg) => write!(f, "{}", msg),
+            Self::Cancelled => write!(f, "{}", gettext("cancelled")),
+            Self::MenuNotInstalled(ref msg) => write!(f, "{}", msg),
+            Self::LaunchFailed(err) => write!(f, "{}", tr("could not launch: {}", &[err])),
+            Self::MenuTimedOut(timeout) => write!(f, "{}", tr("the menu did not exit within {}", &[&format!("{:?}", timeout)])),
+            Self::AlreadyRunning => write!(f, "{}", gettext("another instance is already showing a menu")),
         }
     }
 }
 
 impl Error for ChildProcessError {}
 
-pub fn get_dmenu_choice<S: AsRef<str>>(app_names: &[S]) -> Result<String, ChildProcessError> {
-    let input = app_names.iter().map(AsRef::as_ref).collect::<Vec<_>>().join("\n");
-    let mut child = Command::new("dmenu")
-        .arg("-i")
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()?;
-    let _ = child.stdin.take().unwrap().write_all(input.as_bytes())?;
-    let output = child.wait_with_output()?;
+/// Writes `input` to the stdin of the menu and collects its output like
+/// [`std::process::Child::wait_with_output`], but kills the menu if it has
+/// not exited after `timeout`.
+fn wait_with_timeout(mut child: Child, input: String, timeout: Duration) -> Result<Output, ChildProcessError> {
+    // The pipes are serviced from other threads, so that a menu which does
+    // not read its input or write its output cannot block us
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = child.stdout.take().unwrap();
+    thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let reader = thread::spawn(move || {
+        let mut buf = Vec::new();
+        stdout.read_to_end(&mut buf).map(|_| buf)
+    });
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            child.kill()?;
+            child.wait()?;
+            return Err(ChildProcessError::MenuTimedOut(timeout));
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    let stdout = reader.join().unwrap()?;
+    Ok(Output { status, stdout, stderr: Vec::new() })
+}
+
+/// Shows `items` in the menu and returns the name of the selected item, or
+/// the text which the user typed if it does not correspond to any item.
+/// Returns [`ChildProcessError::Cancelled`] if the menu was dismissed or
+/// nothing was entered.
+pub fn get_dmenu_choice(
+    items: &[MenuItem],
+    menu: MenuProgram,
+    config: &Config,
+    prompt: Option<&str>,
+) -> Result<String, ChildProcessError> {
+    let (input, names_by_output) = menu.format_items(items);
+    let mut child = match menu.command(config, prompt).args(menu.highlight_args(items)).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn() {
+        Ok(child) => child,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Err(ChildProcessError::MenuNotInstalled(
+            tr("{} is not installed", &[&menu.program_name()])
+        )),
+        Err(err) => return Err(err.into()),
+    };
+    let output = match config.menu_timeout {
+        Some(secs) => wait_with_timeout(child, input, Duration::from_secs(secs))?,
+        None => {
+            child.stdin.take().unwrap().write_all(input.as_bytes())?;
+            child.wait_with_output()?
+        },
+    };
+    // All of the supported menus exit with status 1 when they are dismissed
+    if output.status.code() == Some(1) {
+        return Err(ChildProcessError::Cancelled);
+    }
     if !output.status.success() {
-        return Err(ChildProcessError::ProcessFailed("dmenu process failed".to_string()));
+        return Err(ChildProcessError::ProcessFailed(
+            tr("{} process failed", &[&menu.program_name()])
+        ));
     }
-    let output = std::str::from_utf8(&output.stdout)?.trim_end();
-    Ok(output.to_string())
+    let output = output.stdout.trim_ascii_end();
+    if output.is_empty() {
+        return Err(ChildProcessError::Cancelled);
+    }
+    Ok(resolve_menu_output(output, menu, items, &names_by_output))
 }
 
-pub fn launch_i3_cmd_without_desktop_entry(cmd: &str) -> Result<(), io::Error> {
-    let i3_cmd = escape_for_i3_exec(cmd);
-    Command::new("i3-msg").arg("exec").arg(&i3_cmd).spawn().map(|_| ())
+/// Returns the name of the item which the menu printed `output` for, or the
+/// text which the user typed if it does not belong to any item.
+///
+/// The output is not required to be valid UTF-8: some menus and locales
+/// mangle the encoding of what the user typed, which should not prevent the
+/// other items from being matched.
+fn resolve_menu_output(
+    output: &[u8],
+    menu: MenuProgram,
+    items: &[MenuItem],
+    names_by_output: &HashMap<String, &str>,
+) -> String {
+    // This only copies the output if it is not valid UTF-8
+    let output = String::from_utf8_lossy(output);
+    if menu.prints_index() {
+        if let Some((index, text)) = output.split_once(' ') {
+            return match index.parse::<usize>().ok().and_then(|i| items.get(i)) {
+                Some(item) => item.name.to_string(),
+                None => text.to_string(),
+            };
+        }
+    }
+    // Menus which only print the text of the row are matched by string
+    match names_by_output.get(output.as_ref()) {
+        Some(name) => name.to_string(),
+        None => output.into_owned(),
+    }
 }
 
-fn launch_i3_cmd(desktop_entry_exec_str: &str, app: &DesktopEntry) -> Result<(), io::Error> {
-    let i3_cmd = escape_for_i3_exec(desktop_entry_exec_str);
-    let cmd = if app.Terminal {
-        format!("i3-sensible-terminal -e {}", i3_cmd)
+/// Returns a string which can be used as part of a systemd unit name, in the
+/// same way as `systemd-escape` would.
+///
+/// # Examples
+///
+/// ```
+/// use i3_dmenu_desktop_rs::app_launcher::escape_for_systemd_unit;
+///
+/// assert_eq!(escape_for_systemd_unit("org.mozilla.firefox"), "org.mozilla.firefox");
+/// assert_eq!(escape_for_systemd_unit("i3-sensible-terminal"), r"i3\x2dsensible\x2dterminal");
+/// ```
+pub fn escape_for_systemd_unit(s: &str) -> String {
+    let mut escaped = String::new();
+    for (i, byte) in s.bytes().enumerate() {
+        if byte.is_ascii_alphanumeric() || byte == b':' || byte == b'_' || (byte == b'.' && i > 0) {
+            escaped.push(byte as char);
+        } else {
+            escaped.push_str(&format!("\\x{:02x}", byte));
+        }
+    }
+    escaped
+}
+
+fn get_systemd_unit_name(app_id: &str) -> String {
+    // See https://systemd.io/DESKTOP_ENVIRONMENTS/. The random part only
+    // needs to be unique among the running instances of the app.
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.subsec_nanos());
+    let random = nanos ^ std::process::id();
+    format!("app-{}@{:08x}.service", escape_for_systemd_unit(app_id), random)
+}
+
+/// Runs a short-lived helper (e.g. i3-msg or systemd-run) and waits for it,
+/// so that it does not linger as a zombie process and its failures are
+/// reported.
+fn run_helper(command: &mut Command) -> Result<(), io::Error> {
+    let status = command.status()?;
+    check_helper_status(command.get_program(), status)
+}
+
+fn check_helper_status(program: &OsStr, status: ExitStatus) -> Result<(), io::Error> {
+    if !status.success() {
+        let program = program.to_string_lossy();
+        return Err(io::Error::other(format!("{program} failed with {status}")));
+    }
+    Ok(())
+}
+
+/// Returns a helper which starts `command` in the background without
+/// leaving a zombie process behind. An intermediate shell starts the command
+/// and exits right away, so the command gets reparented to init (or the
+/// nearest subreaper) and nobody needs to handle SIGCHLD for it.
+fn detach(command: Command) -> Command {
+    let mut detached = Command::new("sh");
+    detached.arg("-c").arg("\"$@\" &").arg("sh")
+        .arg(command.get_program())
+        .args(command.get_args());
+    for (key, value) in command.get_envs() {
+        match value {
+            Some(value) => detached.env(key, value),
+            None => detached.env_remove(key),
+        };
+    }
+    if let Some(dir) = command.get_current_dir() {
+        detached.current_dir(dir);
+    }
+    detached
+}
+
+/// Returns the launch of a command returned by [`wrap_command`]. `program`
+/// is the one which the command runs in the end (e.g. inside of `sh -c`),
+/// if it is known.
+fn launch_wrapped(command: Command, backend: LaunchBackend, program: Option<String>) -> PreparedLaunch {
+    match backend {
+        // systemd-run returns as soon as the unit was started
+        LaunchBackend::SystemdRun => PreparedLaunch::new(command),
+        // The shell which detaches the command succeeds either way, so the
+        // programs are looked up beforehand
+        _ => {
+            let programs = iter::once(command.get_program().to_string_lossy().into_owned()).chain(program).collect();
+            PreparedLaunch { helper: detach(command), sequence: None, programs }
+        },
+    }
+}
+
+/// A launch which only needs its helper to be run: a short-lived command
+/// (e.g. i3-msg, systemd-run, or a shell which starts the application in
+/// the background), along with the startup notification sequence which is
+/// cancelled if the helper fails. The blocking and the async API share
+/// everything up to here.
+pub(crate) struct PreparedLaunch {
+    helper: Command,
+    sequence: Option<StartupSequence>,
+    /// The programs which have to be installed for the launch to succeed
+    programs: Vec<String>,
+}
+
+impl PreparedLaunch {
+    fn new(helper: Command) -> Self {
+        Self { helper, sequence: None, programs: Vec::new() }
+    }
+
+    /// Returns an error if one of the programs is not in the PATH which the
+    /// helper gets.
+    fn check_programs(&self) -> Result<(), io::Error> {
+        let path = match self.helper.get_envs().find(|(name, _)| *name == "PATH") {
+            Some((_, value)) => value.map(OsStr::to_os_string).unwrap_or_default(),
+            None => std::env::var_os("PATH").unwrap_or_default(),
+        };
+        let env_paths: Vec<String> = path.to_string_lossy().split(':').map(String::from).collect();
+        match self.programs.iter().find(|program| !find_executable(program, &env_paths)) {
+            Some(program) => Err(io::Error::new(io::ErrorKind::NotFound, tr("{} is not installed", &[program]))),
+            None => Ok(()),
+        }
+    }
+
+    fn finish(sequence: Option<StartupSequence>, result: Result<(), io::Error>) -> Result<(), io::Error> {
+        if let (Err(_), Some(sequence)) = (&result, sequence) {
+            sequence.remove();
+        }
+        result
+    }
+
+    pub(crate) fn run(self) -> Result<(), io::Error> {
+        let result = self.check_programs();
+        let Self { mut helper, sequence, .. } = self;
+        Self::finish(sequence, result.and_then(|()| run_helper(&mut helper)))
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) async fn run_async(self) -> Result<(), io::Error> {
+        if let Err(err) = self.check_programs() {
+            return Self::finish(self.sequence, Err(err));
+        }
+        let Self { helper, sequence, .. } = self;
+        let program = helper.get_program().to_os_string();
+        let result = match tokio::process::Command::from(helper).status().await {
+            Ok(status) => check_helper_status(&program, status),
+            Err(err) => Err(err),
+        };
+        Self::finish(sequence, result)
+    }
+}
+
+/// Splits `s` into words like a shell would, without any expansions:
+/// whitespace separates words, except inside of single or double quotes or
+/// after a backslash.
+fn split_words(s: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote: Option<char> = None;
+    let mut chars = s.chars();
+    while let Some(ch) = chars.next() {
+        match (ch, quote) {
+            ('\\', Some('"') | None) => {
+                if let Some(next) = chars.next() {
+                    word.get_or_insert_with(String::new).push(next);
+                }
+            },
+            ('\'' | '"', None) => {
+                quote = Some(ch);
+                word.get_or_insert_with(String::new);
+            },
+            (_, Some(q)) if ch == q => quote = None,
+            (_, None) if ch.is_whitespace() => words.extend(word.take()),
+            _ => word.get_or_insert_with(String::new).push(ch),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+/// Returns the commands which the launched command is wrapped in, outermost
+/// first: the global wrapper, then the wrappers of the categories of `app`.
+/// The %gpu placeholder is replaced by "integrated", "discrete" or "default".
+fn get_wrappers(app: Option<&DesktopEntry>, gpu: Option<Gpu>, config: &Config) -> Vec<String> {
+    let category_wrappers = config.category_wrappers.iter()
+        .filter(|(category, _)| app.is_some_and(|app| app.keys.Categories.contains(category)))
+        .map(|(_, wrapper)| wrapper.as_str());
+    config.wrapper.as_deref().into_iter().chain(category_wrappers)
+        .map(|wrapper| wrapper.replace("%gpu", Gpu::name(gpu)))
+        .collect()
+}
+
+/// Returns a command which runs `argv` in the way specified by `backend`.
+/// `app_id` is the desktop file ID without the ".desktop" suffix, or the
+/// name of the executable when there is no desktop entry.
+fn wrap_command(
+    argv: &[&str],
+    app_id: &str,
+    backend: LaunchBackend,
+    priority: &ProcessPriority,
+    env: &EnvPolicy,
+) -> Command {
+    // systemd-run sets the priority of the unit itself
+    let prefix = if backend == LaunchBackend::SystemdRun { Vec::new() } else { priority.argv_prefix() };
+    let argv: Vec<_> = prefix.iter().map(String::as_str).chain(argv.iter().copied()).collect();
+    let mut command = match backend {
+        LaunchBackend::Uwsm => {
+            let mut command = Command::new("uwsm");
+            command.arg("app").arg("-a").arg(app_id).arg("--");
+            command.args(argv);
+            command
+        },
+        LaunchBackend::SystemdRun => {
+            let mut command = Command::new("systemd-run");
+            command.args(["--user", "--quiet", "--collect", "--slice=app.slice"])
+                .arg("--property=ExitType=cgroup")
+                .arg(format!("--unit={}", get_systemd_unit_name(app_id)))
+                .args(env.systemd_run_args())
+                .args(priority.systemd_run_args())
+                .arg("--");
+            command.args(argv);
+            command
+        },
+        // Commands which did not come from a desktop entry cannot be
+        // delegated to glib, so just run them directly.
+        LaunchBackend::I3 | LaunchBackend::Exec | LaunchBackend::Gio | LaunchBackend::GtkLaunch => {
+            let mut command = Command::new(argv[0]);
+            command.args(&argv[1..]);
+            command
+        },
+    };
+    if backend != LaunchBackend::SystemdRun {
+        env.apply(&mut command);
+    }
+    // Never pass on a startup ID which was meant for the launcher itself,
+    // nor the settings of the launcher (see Cli).
+    command.env_remove("DESKTOP_STARTUP_ID");
+    for (name, _) in std::env::vars_os() {
+        if name.to_str().is_some_and(|name| name.starts_with("I3_DMENU_DESKTOP_")) {
+            command.env_remove(name);
+        }
+    }
+    command
+}
+
+/// Returns the app ID for a typed command: the basename of its program.
+fn get_app_id_for_cmd(cmd: &str) -> String {
+    let program = split_words(cmd).into_iter().next().unwrap_or_default();
+    program.rsplit('/').next().unwrap_or_default().to_string()
+}
+
+pub(crate) fn prepare_cmd_without_desktop_entry(cmd: &str, backend: LaunchBackend, config: &Config) -> PreparedLaunch {
+    let wrappers = get_wrappers(None, None, config);
+    if backend == LaunchBackend::I3 {
+        let env = config.env.env_args().iter().map(|arg| quote_for_sh(arg)).collect::<Vec<_>>();
+        let i3_cmd = env.into_iter().chain(wrappers).chain([cmd.to_string()]).collect::<Vec<_>>().join(" ");
+        let i3_cmd = escape_for_i3_exec(&i3_cmd);
+        let mut helper = Command::new("i3-msg");
+        helper.arg("exec").arg(&i3_cmd).stdout(Stdio::null());
+        return PreparedLaunch::new(helper);
+    }
+    let app_id = get_app_id_for_cmd(cmd);
+    let argv: Vec<String> = wrappers.iter().flat_map(|wrapper| split_words(wrapper))
+        .chain(["sh", "-c", cmd].map(String::from))
+        .collect();
+    let argv: Vec<_> = argv.iter().map(String::as_str).collect();
+    let command = wrap_command(&argv, &app_id, backend, &ProcessPriority::default(), &config.env);
+    // The typed command may start with a shell builtin, so only the
+    // programs outside of the shell are looked up
+    launch_wrapped(command, backend, None)
+}
+
+pub fn launch_cmd_without_desktop_entry(cmd: &str, backend: LaunchBackend, config: &Config) -> Result<(), io::Error> {
+    prepare_cmd_without_desktop_entry(cmd, backend, config).run()
+}
+
+/// Returns whether `app` is known to send startup notification messages.
+/// Otherwise, nothing should wait for them, e.g. i3 would show a busy
+/// cursor for up to a minute.
+fn wants_startup_notify(app: &DesktopEntry, config: &Config) -> bool {
+    app.keys.StartupNotify.unwrap_or(config.startup_notify_default)
+}
+
+/// Returns the i3 command which runs `desktop_entry_exec_str` (a command
+/// line for sh, see [`DesktopEntry::replace_field_codes`]) for `app`.
+fn get_i3_exec_command(desktop_entry_exec_str: &str, app: &DesktopEntry, config: &Config) -> String {
+    let cmd = if app.keys.Terminal {
+        // Like the original i3-dmenu-desktop, the terminal gets the whole
+        // command line as a single argument
+        format!("i3-sensible-terminal -e {}", quote_for_sh(desktop_entry_exec_str))
+    } else {
+        desktop_entry_exec_str.to_string()
+    };
+    // i3 passes the command to sh -c, so the wrappers can be prepended as is.
+    // The whole command has to be quoted for i3, since otherwise it would
+    // end at the first ';' or ','.
+    let gpu = Gpu::for_app(app, config);
+    let wrappers = get_wrappers(Some(app), gpu, config);
+    let prefix = get_env_policy(gpu, config).env_args().into_iter()
+        .chain(ProcessPriority::for_app(app, config).argv_prefix())
+        .map(|arg| quote_for_sh(&arg))
+        .collect::<Vec<_>>();
+    let cmd = prefix.into_iter().chain(wrappers).chain([cmd]).collect::<Vec<_>>().join(" ");
+    let no_startup_notify = if wants_startup_notify(app, config) { "" } else { "--no-startup-id" };
+    format!("exec {} {}", no_startup_notify, escape_for_i3_exec(&cmd))
+}
+
+fn prepare_i3_cmd(desktop_entry_exec_str: &str, app: &DesktopEntry, config: &Config) -> PreparedLaunch {
+    let mut helper = Command::new("i3-msg");
+    // i3-msg prints the (JSON) reply of i3, which is of no interest
+    helper.arg(get_i3_exec_command(desktop_entry_exec_str, app, config)).stdout(Stdio::null());
+    PreparedLaunch::new(helper)
+}
+
+fn prepare_cmd(
+    desktop_entry_exec_str: &str,
+    app: &DesktopEntry,
+    backend: LaunchBackend,
+    config: &Config,
+) -> PreparedLaunch {
+    let command = if app.keys.Terminal {
+        ["i3-sensible-terminal", "-e", desktop_entry_exec_str]
     } else {
-        i3_cmd
+        ["sh", "-c", desktop_entry_exec_str]
     };
-    let no_startup_notify = if app.StartupNotify { "" } else { "--no-startup-id" };
-    let arg = format!("exec {} {}", no_startup_notify, cmd);
-    Command::new("i3-msg").arg(arg).spawn().map(|_| ())
+    let gpu = Gpu::for_app(app, config);
+    let argv: Vec<String> = get_wrappers(Some(app), gpu, config).iter().flat_map(|wrapper| split_words(wrapper))
+        .chain(command.map(String::from))
+        .collect();
+    let argv: Vec<_> = argv.iter().map(String::as_str).collect();
+    let app_id = app.source.id.strip_suffix(".desktop").unwrap_or(&app.source.id);
+    let env = get_env_policy(gpu, config);
+    let mut command = wrap_command(&argv, app_id, backend, &ProcessPriority::for_app(app, config), &env);
+    if let Some(ref dir) = app.keys.Path {
+        command.current_dir(dir);
+    }
+    // Startup notification is an X11 protocol; in systemd-managed (Wayland)
+    // sessions, activation is handled by the compositor instead.
+    let sequence = if wants_startup_notify(app, config) && backend == LaunchBackend::Exec {
+        let sequence = StartupSequence::start(app);
+        command.env("DESKTOP_STARTUP_ID", sequence.id());
+        Some(sequence)
+    } else {
+        None
+    };
+    PreparedLaunch { sequence, ..launch_wrapped(command, backend, get_program(app, config)) }
+}
+
+/// Returns the i3/sway commands which focus a window of `app`, in the order
+/// in which they should be tried. Windows are matched by StartupWMClass or
+/// else by the desktop file ID without the ".desktop" suffix, ignoring case.
+fn get_focus_commands(app: &DesktopEntry, session: SessionType) -> Vec<String> {
+    let name = app.keys.StartupWMClass.as_deref()
+        .unwrap_or_else(|| app.source.id.strip_suffix(".desktop").unwrap_or(&app.source.id));
+    let pattern = format!("(?i)^{}$", regex::escape(name)).replace('"', "\\\"");
+    // Native Wayland windows have an app_id instead of a class
+    let attributes: &[&str] = match session {
+        SessionType::I3 => &["class"],
+        SessionType::Sway => &["app_id", "class"],
+        SessionType::Wayland | SessionType::X11 => &[],
+    };
+    attributes.iter().map(|attribute| format!(r#"[{attribute}="{pattern}"] focus"#)).collect()
+}
+
+/// A reply of i3 or sway to a command.
+#[derive(Deserialize)]
+struct CommandReply {
+    success: bool,
+}
+
+/// Focuses an existing window of `app` via the IPC of i3 or sway. Returns
+/// whether there was one; other sessions have no such IPC, so this always
+/// returns false there.
+pub fn focus_existing_window(app: &DesktopEntry, session: SessionType) -> bool {
+    let program = if session == SessionType::Sway { "swaymsg" } else { "i3-msg" };
+    get_focus_commands(app, session).iter().any(|cmd| {
+        // Both exit with a non-zero status if no window matched, but the
+        // reply is more reliable across versions
+        let output = match Command::new(program).arg(cmd).stderr(Stdio::null()).output() {
+            Ok(output) => output,
+            Err(_) => return false,
+        };
+        serde_json::from_slice::<Vec<CommandReply>>(&output.stdout)
+            .is_ok_and(|replies| !replies.is_empty() && replies.iter().all(|reply| reply.success))
+    })
+}
+
+/// Returns the command line which launches `app`, which is its TryExec
+/// (if it has one) with `launch_try_exec`, like older versions did.
+fn get_exec_str<'a>(app: &'a DesktopEntry, config: &Config) -> &'a str {
+    match app.keys.TryExec {
+        Some(ref try_exec) if config.launch_try_exec => try_exec,
+        _ => app.get_exec_str(),
+    }
 }
 
-pub fn launch_desktop_entry(app: &DesktopEntry, extra_args: &[&str]) -> Result<(), io::Error> {
-    let cmd = app.replace_field_codes(app.get_exec_str(), extra_args);
-    launch_i3_cmd(&cmd, app)
+/// Returns the program which the command line from [`get_exec_str`] runs.
+fn get_program(app: &DesktopEntry, config: &Config) -> Option<String> {
+    match app.keys.TryExec {
+        Some(ref try_exec) if config.launch_try_exec => Some(try_exec.clone()),
+        _ => app.get_program(),
+    }
+}
+
+pub(crate) fn prepare_desktop_entry(
+    app: &DesktopEntry,
+    extra_args: &[&str],
+    backend: LaunchBackend,
+    config: &Config,
+) -> PreparedLaunch {
+    match backend {
+        // glib's command-line tools cannot activate desktop actions, nor find
+        // the entries of AppImages, which may only exist while scanning
+        LaunchBackend::Gio | LaunchBackend::GtkLaunch if app.action.is_some() || app.source.origin == Origin::AppImage => {
+            prepare_cmd(&app.replace_field_codes(get_exec_str(app, config), extra_args), app, LaunchBackend::Exec, config)
+        },
+        LaunchBackend::Gio => {
+            let mut helper = Command::new("gio");
+            helper.arg("launch").arg(&app.source.location).args(extra_args);
+            PreparedLaunch::new(helper)
+        },
+        LaunchBackend::GtkLaunch => {
+            let mut helper = Command::new("gtk-launch");
+            helper.arg(&app.source.id).args(extra_args);
+            PreparedLaunch::new(helper)
+        },
+        LaunchBackend::I3 => {
+            prepare_i3_cmd(&app.replace_field_codes(get_exec_str(app, config), extra_args), app, config)
+        },
+        _ => prepare_cmd(&app.replace_field_codes(get_exec_str(app, config), extra_args), app, backend, config),
+    }
+}
+
+pub fn launch_desktop_entry(
+    app: &DesktopEntry,
+    extra_args: &[&str],
+    backend: LaunchBackend,
+    config: &Config,
+) -> Result<(), io::Error> {
+    prepare_desktop_entry(app, extra_args, backend, config).run()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_menu_output() {
+        let names_by_output = HashMap::from([
+            ("Firefox - Browse the Web".to_string(), "Firefox"),
+            ("Caf\u{fffd}".to_string(), "Caf\u{fffd}"),
+        ]);
+        let resolve = |output| resolve_menu_output(output, MenuProgram::Fuzzel, &[], &names_by_output);
+        assert_eq!(resolve(b"Firefox - Browse the Web"), "Firefox");
+        // A Latin-1 encoded "Café"
+        assert_eq!(resolve(b"Caf\xe9"), "Caf\u{fffd}");
+        assert_eq!(resolve(b"xterm -e \xff"), "xterm -e \u{fffd}");
+    }
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(split_words("systemd-inhibit --what=handle-lid-switch"), ["systemd-inhibit", "--what=handle-lid-switch"]);
+        assert_eq!(split_words(r#"  env 'A=b c' "D=\"e\"" f\ g ''"#), ["env", "A=b c", "D=\"e\"", "f g", ""]);
+    }
+
+    #[test]
+    fn test_process_priority() {
+        let config: Config = toml::from_str(r#"
+            [priority.Graphics]
+            nice = 10
+            io_class = "idle"
+            [priority."org.gimp.GIMP.desktop"]
+            nice = 5
+            oom_score_adj = 500
+        "#).unwrap();
+        let contents = "[Desktop Entry]\nType=Application\nName=GIMP\nExec=gimp\nCategories=Graphics;\n";
+        let mut app = DesktopEntry::parse_contents(contents, "/a/test.desktop", SystemTime::UNIX_EPOCH, &[]).unwrap();
+        let priority = ProcessPriority::for_app(&app, &config);
+        assert_eq!(priority, ProcessPriority { nice: Some(10), io_class: Some(IoClass::Idle), oom_score_adj: None });
+        app.source.id = "org.gimp.GIMP.desktop".to_string();
+        let priority = ProcessPriority::for_app(&app, &config);
+        assert_eq!(priority.argv_prefix(), ["nice", "-n", "5", "ionice", "-c", "idle", "choom", "-n", "500", "--"]);
+        assert_eq!(priority.systemd_run_args(), [
+            "--nice=5", "--property=IOSchedulingClass=idle", "--property=OOMScoreAdjust=500",
+        ]);
+    }
+
+    #[test]
+    fn test_get_focus_commands() {
+        let contents = "[Desktop Entry]\nType=Application\nName=Files\nExec=nautilus\n";
+        let mut app = DesktopEntry::parse_contents(contents, "/a/test.desktop", SystemTime::UNIX_EPOCH, &[]).unwrap();
+        app.source.id = "org.gnome.Nautilus.desktop".to_string();
+        assert_eq!(get_focus_commands(&app, SessionType::Sway), [
+            r#"[app_id="(?i)^org\.gnome\.Nautilus$"] focus"#,
+            r#"[class="(?i)^org\.gnome\.Nautilus$"] focus"#,
+        ]);
+        app.keys.StartupWMClass = Some("Nautilus".to_string());
+        assert_eq!(get_focus_commands(&app, SessionType::I3), [r#"[class="(?i)^Nautilus$"] focus"#]);
+        assert!(get_focus_commands(&app, SessionType::X11).is_empty());
+    }
+
+    #[test]
+    fn test_gpu() {
+        let config: Config = toml::from_str(r#"
+            wrapper = "gpu-wrapper --gpu=%gpu"
+            [gpu]
+            Game = "discrete"
+            "steam.desktop" = "integrated"
+            [gpu_env.discrete]
+            __NV_PRIME_RENDER_OFFLOAD = "1"
+        "#).unwrap();
+        let contents = "[Desktop Entry]\nType=Application\nName=Steam\nExec=steam\nCategories=Game;\n";
+        let mut app = DesktopEntry::parse_contents(contents, "/a/test.desktop", SystemTime::UNIX_EPOCH, &[]).unwrap();
+        assert_eq!(Gpu::for_app(&app, &config), Some(Gpu::Discrete));
+        assert_eq!(config.gpu_env.vars(Gpu::Discrete)["__NV_PRIME_RENDER_OFFLOAD"], "1");
+        assert_eq!(config.gpu_env.vars(Gpu::Integrated)["DRI_PRIME"], "0");
+        app.source.id = "steam.desktop".to_string();
+        assert_eq!(Gpu::for_app(&app, &config), Some(Gpu::Integrated));
+        assert_eq!(get_wrappers(Some(&app), Some(Gpu::Integrated), &config), ["gpu-wrapper --gpu=integrated"]);
+        app.keys.Categories.clear();
+        app.source.id = "blender.desktop".to_string();
+        assert_eq!(Gpu::for_app(&app, &config), None);
+        assert_eq!(get_wrappers(Some(&app), None, &config), ["gpu-wrapper --gpu=default"]);
+        app.keys.PrefersNonDefaultGPU = true;
+        assert_eq!(Gpu::for_app(&app, &config), Some(Gpu::Discrete));
+    }
+
+    #[test]
+    fn test_env_policy() {
+        let vars = || ["PATH", "HOME", "VIRTUAL_ENV", "VIRTUAL_ENV_PROMPT", "LANG"].map(String::from);
+        let policy = EnvPolicy {
+            deny: vec!["VIRTUAL_ENV*".to_string()],
+            set: BTreeMap::from([("LANG".to_string(), "C".to_string())]),
+            ..EnvPolicy::default()
+        };
+        assert_eq!(policy.removed_vars(vars()), ["VIRTUAL_ENV", "VIRTUAL_ENV_PROMPT"]);
+        let policy = EnvPolicy {
+            allow: Some(vec!["PATH".to_string(), "HOME".to_string()]),
+            ..EnvPolicy::default()
+        };
+        assert_eq!(policy.removed_vars(vars()), ["LANG", "VIRTUAL_ENV", "VIRTUAL_ENV_PROMPT"]);
+    }
+
+    #[test]
+    fn test_resolve_menu_output_index() {
+        let items = [
+            MenuItem { prefix: None, name: "Firefox", comment: None, icon: None, badge: None },
+            MenuItem { prefix: None, name: "xterm", comment: None, icon: None, badge: None },
+        ];
+        let resolve = |output| resolve_menu_output(output, MenuProgram::Rofi, &items, &HashMap::new());
+        assert_eq!(resolve(b"1 xterm"), "xterm");
+        assert_eq!(resolve(b"-1 xterm -e htop"), "xterm -e htop");
+    }
+
+    #[test]
+    fn test_get_app_id_for_cmd() {
+        assert_eq!(get_app_id_for_cmd("firefox"), "firefox");
+        assert_eq!(get_app_id_for_cmd("/usr/bin/firefox https://example.com/a"), "firefox");
+        assert_eq!(get_app_id_for_cmd("'/opt/My App/app' --flag"), "app");
+        assert_eq!(get_app_id_for_cmd(""), "");
+    }
+
+    /// Returns `arg` as it would appear in the Exec key of a desktop entry
+    /// file: quoted if needed, with '%' escaped, and with the general escape
+    /// rules for strings applied.
+    fn encode_exec_arg(arg: &str) -> String {
+        let needs_quotes = arg.is_empty()
+            || arg.chars().any(|ch| ch.is_whitespace() || "\"'\\><~|&;$*?#()`".contains(ch));
+        let mut encoded = String::new();
+        for ch in arg.chars() {
+            match ch {
+                '"' | '`' | '$' | '\\' if needs_quotes => encoded.push_str(&format!("\\{ch}")),
+                '%' => encoded.push_str("%%"),
+                _ => encoded.push(ch),
+            }
+        }
+        let encoded = if needs_quotes { format!("\"{encoded}\"") } else { encoded };
+        encoded.replace('\\', r"\\").replace('\n', r"\n").replace('\t', r"\t").replace('\r', r"\r")
+    }
+
+    /// Returns the command which i3 passes to sh -c for `i3_cmd`, like the
+    /// parser of i3 does for quoted strings.
+    fn unquote_i3_exec(i3_cmd: &str) -> String {
+        let quoted = &i3_cmd[i3_cmd.find('"').unwrap() + 1..];
+        let mut cmd = String::new();
+        let mut chars = quoted.chars();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '"' => break,
+                '\\' => match chars.next() {
+                    Some(next @ ('"' | '\\')) => cmd.push(next),
+                    Some(next) => { cmd.push(ch); cmd.push(next); },
+                    None => cmd.push(ch),
+                },
+                _ => cmd.push(ch),
+            }
+        }
+        assert_eq!(chars.as_str(), "", "i3 would not parse {i3_cmd:?} as a single string");
+        cmd
+    }
+
+    #[test]
+    fn test_get_i3_exec_command() {
+        let config = Config { wrapper: Some("env".to_string()), ..Config::default() };
+        let contents = "[Desktop Entry]\nType=Application\nName=Test\nExec=foo \"a;b\" c\nTerminal=true\n";
+        let mut app = DesktopEntry::parse_contents(contents, "/a/test.desktop", SystemTime::UNIX_EPOCH, &[]).unwrap();
+        app.escape_chars_for_exec_keys();
+        let cmd = get_i3_exec_command(&app.replace_field_codes(app.get_exec_str(), &[]), &app, &config);
+        assert_eq!(cmd, r#"exec --no-startup-id "env i3-sensible-terminal -e 'foo '\\''a;b'\\'' c'""#);
+        // Entries without StartupNotify are treated according to the config
+        let config = Config { startup_notify_default: true, ..config };
+        let cmd = get_i3_exec_command(&app.replace_field_codes(app.get_exec_str(), &[]), &app, &config);
+        assert_eq!(cmd, r#"exec  "env i3-sensible-terminal -e 'foo '\\''a;b'\\'' c'""#);
+        let app = DesktopEntry::parse_contents(&format!("{contents}StartupNotify=false\n"), "/a/test.desktop", SystemTime::UNIX_EPOCH, &[])
+            .unwrap();
+        assert!(!wants_startup_notify(&app, &config));
+        // i3 starts the command with its own environment
+        let mut config = Config::default();
+        config.env.set.insert("A".to_string(), "b c".to_string());
+        let cmd = get_i3_exec_command("foo", &app, &config);
+        assert_eq!(cmd, r#"exec --no-startup-id "env 'A=b c' i3-sensible-terminal -e foo""#);
+        config.priority.insert(app.source.id.clone(), ProcessPriority { nice: Some(5), ..Default::default() });
+        let cmd = get_i3_exec_command("foo", &app, &config);
+        assert_eq!(cmd, r#"exec --no-startup-id "env 'A=b c' nice -n 5 i3-sensible-terminal -e foo""#);
+        config.gpu.insert(app.source.id.clone(), Gpu::Integrated);
+        config.gpu_env.integrated = BTreeMap::from([("DRI_PRIME".to_string(), "0".to_string())]);
+        let cmd = get_i3_exec_command("foo", &app, &config);
+        assert_eq!(cmd, r#"exec --no-startup-id "env 'A=b c' DRI_PRIME=0 nice -n 5 i3-sensible-terminal -e foo""#);
+    }
+
+    #[test]
+    fn test_get_exec_str() {
+        let contents = "[Desktop Entry]\nType=Application\nName=Test\nExec=foo --bar %f\nTryExec=foo\n";
+        let app = DesktopEntry::parse_contents(contents, "/a/test.desktop", SystemTime::UNIX_EPOCH, &[]).unwrap();
+        assert_eq!(get_exec_str(&app, &Config::default()), "foo --bar %f");
+        assert_eq!(get_exec_str(&app, &Config { launch_try_exec: true, ..Config::default() }), "foo");
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn test_i3_exec_round_trip(
+            args in proptest::collection::vec(r"[^\x00]*", 1..5),
+            terminal: bool,
+            wrapper: bool,
+        ) {
+            // A stand-in for i3-sensible-terminal which runs the command line
+            // it gets with -e
+            let bin_dir = std::env::temp_dir().join(format!("i3-dmenu-desktop-rs-bin-{}", std::process::id()));
+            std::fs::create_dir_all(&bin_dir).unwrap();
+            let terminal_path = bin_dir.join("i3-sensible-terminal");
+            std::fs::write(&terminal_path, "#!/bin/sh\nexec sh -c \"$2\"\n").unwrap();
+            std::fs::set_permissions(&terminal_path, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+
+            let config = Config { wrapper: wrapper.then(|| "env".to_string()), ..Config::default() };
+            let exec = ["printf", r"%s\0"].into_iter().chain(args.iter().map(String::as_str))
+                .map(encode_exec_arg)
+                .collect::<Vec<_>>()
+                .join(" ");
+            let contents = format!("[Desktop Entry]\nType=Application\nName=Test\nExec={exec}\nTerminal={terminal}\n");
+            let mut app = DesktopEntry::parse_contents(&contents, "/a/test.desktop", SystemTime::UNIX_EPOCH, &[]).unwrap();
+            app.escape_chars_for_exec_keys();
+            let i3_cmd = get_i3_exec_command(&app.replace_field_codes(app.get_exec_str(), &[]), &app, &config);
+            let path = format!("{}:{}", bin_dir.display(), std::env::var("PATH").unwrap_or_default());
+            let output = Command::new("sh").arg("-c").arg(unquote_i3_exec(&i3_cmd)).env("PATH", path).output().unwrap();
+            let printed = String::from_utf8(output.stdout).unwrap();
+            let printed: Vec<_> = printed.strip_suffix('\0').unwrap_or(&printed).split('\0').collect();
+            proptest::prop_assert_eq!(printed, args);
+        }
+    }
 }
@@ -0,0 +1,200 @@
+//! Lists AppImages (https://appimage.org/) which are not integrated into the
+//! desktop, using the desktop entry which is embedded in each of them.
+//!
+//! The embedded entry is extracted with the AppImage's own
+//! `--appimage-extract` option, and cached by the fingerprint of the
+//! AppImage so that this only happens once per version.
+
+use std::fs::{self, File};
+use std::hash::Hasher;
+use std::io::{self, Read};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use super::desktop_entry::{DesktopEntry, Origin};
+use super::i18n::tr;
+use super::overrides::{quote_exec_arg, set_key};
+use super::utils::{is_executable, join_path, log_warn, Fnv1aHasher};
+
+// The number of bytes at the start of the file which go into the fingerprint
+const FINGERPRINT_PREFIX_LEN: u64 = 64 * 1024;
+
+/// Returns a hash of the size, mtime and first bytes of the file, which
+/// changes whenever the AppImage is replaced by another version.
+fn fingerprint(path: &Path) -> io::Result<u64> {
+    let file = File::open(path)?;
+    let metadata = file.metadata()?;
+    let mut hasher = Fnv1aHasher::default();
+    hasher.write(&metadata.len().to_le_bytes());
+    if let Ok(mtime) = metadata.modified() {
+        hasher.write(&mtime.duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_nanos()).to_le_bytes());
+    }
+    let mut prefix = Vec::new();
+    file.take(FINGERPRINT_PREFIX_LEN).read_to_end(&mut prefix)?;
+    hasher.write(&prefix);
+    Ok(hasher.finish())
+}
+
+/// Returns `exec` with its first argument replaced by `program`, keeping the
+/// other arguments and field codes.
+fn replace_arg0(exec: &str, program: &str) -> String {
+    let rest = match exec.strip_prefix('"') {
+        Some(quoted) => quoted.split_once('"').map_or("", |(_, rest)| rest),
+        None => exec.find(char::is_whitespace).map_or("", |i| &exec[i..]),
+    };
+    format!("{}{}", quote_exec_arg(program), rest)
+}
+
+/// Extracts the desktop entry embedded in the AppImage at `path` to
+/// `dest`, with its Exec key pointing at the AppImage.
+fn extract_desktop_entry(path: &Path, dest: &str, tmp_dir: &str) -> io::Result<()> {
+    fs::create_dir_all(tmp_dir)?;
+    let status = Command::new(path)
+        .args(["--appimage-extract", "*.desktop"])
+        .current_dir(tmp_dir)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+    let result = (|| {
+        if !status.success() {
+            return Err(io::Error::other(format!("--appimage-extract failed with {status}")));
+        }
+        // The entry must be at the top level of the AppImage
+        let entry = fs::read_dir(join_path(tmp_dir, "squashfs-root"))?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .find(|path| path.extension().is_some_and(|ext| ext == "desktop"))
+            .ok_or_else(|| io::Error::other("the AppImage does not contain a desktop entry"))?;
+        let contents = fs::read_to_string(entry)?;
+        let exec = contents.lines()
+            .filter_map(|line| line.split_once('='))
+            .find(|(key, _)| key.trim_end() == "Exec")
+            .map_or(String::new(), |(_, value)| value.trim().to_string());
+        let program = path.to_str().ok_or_else(|| io::Error::other("the path is not valid UTF-8"))?;
+        // The TryExec of the embedded entry refers to a path inside of the AppImage
+        let contents: String = contents.lines()
+            .filter(|line| line.split_once('=').is_none_or(|(key, _)| key.trim_end() != "TryExec"))
+            .map(|line| format!("{line}\n"))
+            .collect();
+        fs::write(dest, set_key(&contents, "Exec", &replace_arg0(&exec, program)))
+    })();
+    fs::remove_dir_all(tmp_dir)?;
+    result
+}
+
+/// Parses an extracted desktop entry, logging why it could not be parsed.
+fn parse_entry(path: &str, locale_keys: &[String]) -> Option<DesktopEntry> {
+    match DesktopEntry::parse(path, locale_keys) {
+        Ok(mut app) => {
+            app.escape_chars_for_exec_keys();
+            app.source.origin = Origin::AppImage;
+            Some(app)
+        },
+        Err(err) => {
+            log_warn(&tr("Could not parse {}: {}", &[&path, &err]));
+            None
+        },
+    }
+}
+
+/// Returns the desktop entries of the AppImages in `dirs`.
+///
+/// # Arguments
+///
+/// * `cache_dir`: the $XDG_CACHE_HOME directory, where the extracted entries
+///   are kept
+/// * `persist`: whether newly extracted entries are kept in the cache;
+///   otherwise, they are extracted into a temporary directory which is
+///   removed again once they are parsed
+pub fn get_appimage_entries(
+    dirs: &[String],
+    cache_dir: &str,
+    locale_keys: &[String],
+    persist: bool,
+) -> Vec<DesktopEntry> {
+    let entry_dir = join_path(cache_dir, "i3-dmenu-desktop-rs/appimages");
+    let mut apps = Vec::new();
+    for dir in dirs {
+        let mut paths: Vec<_> = match fs::read_dir(dir) {
+            Ok(entries) => entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect(),
+            Err(_) => continue,
+        };
+        paths.sort();
+        for path in paths {
+            let is_appimage = path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("appimage"));
+            if !is_appimage || !path.to_str().is_some_and(is_executable) {
+                continue;
+            }
+            let hash = match fingerprint(&path) {
+                Ok(hash) => hash,
+                Err(err) => {
+                    log_warn(&tr("Could not read {}: {}", &[&path.display(), &err]));
+                    continue;
+                },
+            };
+            let cached = join_path(&entry_dir, &format!("appimage-{hash:016x}.desktop"));
+            let app = if Path::new(&cached).is_file() {
+                parse_entry(&cached, locale_keys)
+            } else if persist {
+                let tmp_dir = join_path(&entry_dir, &format!("tmp-{}", std::process::id()));
+                extract_desktop_entry(&path, &cached, &tmp_dir)
+                    .map_err(|err| log_warn(&tr("Could not extract the desktop entry of {}: {}", &[&path.display(), &err])))
+                    .ok()
+                    .and_then(|()| parse_entry(&cached, locale_keys))
+            } else {
+                let tmp_root = std::env::temp_dir().join(format!("i3-dmenu-desktop-rs-appimage-{}", std::process::id()));
+                let tmp_root = tmp_root.to_string_lossy();
+                let dest = join_path(&tmp_root, "entry.desktop");
+                let app = extract_desktop_entry(&path, &dest, &join_path(&tmp_root, "tmp"))
+                    .map_err(|err| log_warn(&tr("Could not extract the desktop entry of {}: {}", &[&path.display(), &err])))
+                    .ok()
+                    .and_then(|()| parse_entry(&dest, locale_keys));
+                let _ = fs::remove_dir_all(tmp_root.as_ref());
+                app
+            };
+            if let Some(mut app) = app {
+                app.source.data_dir = dir.clone();
+                apps.push(app);
+            }
+        }
+    }
+    apps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_arg0() {
+        assert_eq!(replace_arg0("AppRun %U", "/home/max/Applications/Foo.AppImage"),
+                   r#""/home/max/Applications/Foo.AppImage" %U"#);
+        assert_eq!(replace_arg0(r#""/opt/my app/run" --flag"#, "/tmp/Foo.AppImage"), r#""/tmp/Foo.AppImage" --flag"#);
+        assert_eq!(replace_arg0("", "/tmp/Foo.AppImage"), r#""/tmp/Foo.AppImage""#);
+    }
+
+    #[test]
+    fn test_get_appimage_entries_without_persistence() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = std::env::temp_dir().join(format!("i3-dmenu-desktop-rs-appimages-{}", std::process::id()));
+        let app_dir = root.join("Applications");
+        fs::create_dir_all(&app_dir).unwrap();
+        let appimage = app_dir.join("Foo.AppImage");
+        fs::write(&appimage, "#!/bin/sh\nmkdir squashfs-root\n\
+                              printf '[Desktop Entry]\\nType=Application\\nName=Foo\\nExec=AppRun %%U\\n' \
+                              > squashfs-root/foo.desktop\n").unwrap();
+        fs::set_permissions(&appimage, fs::Permissions::from_mode(0o755)).unwrap();
+        let cache_dir = root.join("cache");
+        let apps = get_appimage_entries(&[app_dir.to_str().unwrap().to_string()], cache_dir.to_str().unwrap(), &[], false);
+        assert_eq!(apps.len(), 1);
+        assert_eq!(apps[0].keys.Name, "Foo");
+        assert_eq!(apps[0].source.origin, Origin::AppImage);
+        // Nothing is kept, neither in the cache nor in the temporary directory
+        assert!(!cache_dir.exists());
+        assert!(!std::env::temp_dir().join(format!("i3-dmenu-desktop-rs-appimage-{}", std::process::id())).exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
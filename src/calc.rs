@@ -0,0 +1,251 @@
+//! A small calculator for arithmetic typed into the menu after the
+//! calculator prefix, e.g. "= 23*7+1".
+
+use std::fmt;
+use std::io::{self, Write};
+use std::iter::Peekable;
+use std::process::{Command, Stdio};
+use std::str::CharIndices;
+
+use super::i18n::{gettext, tr};
+use super::session::SessionType;
+use super::utils::log_warn;
+
+#[derive(Debug, PartialEq)]
+pub enum CalcError {
+    /// A character which does not fit the grammar, at the given byte offset
+    UnexpectedChar(usize, char),
+    UnexpectedEnd,
+    /// The result is infinite or not a number, e.g. after dividing by zero
+    NotFinite,
+    /// More than [`MAX_DEPTH`] nested parentheses, signs or powers
+    TooDeep,
+}
+
+/// How deeply expressions may be nested, so that the parser does not run
+/// out of stack
+const MAX_DEPTH: usize = 256;
+
+impl fmt::Display for CalcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnexpectedChar(pos, ch) => write!(f, "{}", tr("unexpected {} at position {}", &[&format!("{:?}", ch), &(pos + 1)])),
+            Self::UnexpectedEnd => write!(f, "{}", gettext("unexpected end of expression")),
+            Self::NotFinite => write!(f, "{}", gettext("the result is not a finite number")),
+            Self::TooDeep => write!(f, "{}", gettext("the expression is nested too deeply")),
+        }
+    }
+}
+
+/// A recursive descent parser which evaluates while parsing:
+///
+/// ```text
+/// sum     = product (("+" | "-") product)*
+/// product = unary (("*" | "/" | "%") unary)*
+/// unary   = ("+" | "-") unary | power
+/// power   = primary ("^" unary)?
+/// primary = number | "(" sum ")"
+/// ```
+///
+/// so that `-2^2` is -4 and `2^3^2` is 512, like in mathematical notation.
+struct Parser<'a> {
+    chars: Peekable<CharIndices<'a>>,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    /// Returns the next character which is not whitespace, without
+    /// consuming it.
+    fn peek(&mut self) -> Option<char> {
+        while self.chars.next_if(|(_, ch)| ch.is_whitespace()).is_some() {}
+        self.chars.peek().map(|&(_, ch)| ch)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), CalcError> {
+        self.peek();
+        match self.chars.next() {
+            Some((_, ch)) if ch == expected => Ok(()),
+            Some((pos, ch)) => Err(CalcError::UnexpectedChar(pos, ch)),
+            None => Err(CalcError::UnexpectedEnd),
+        }
+    }
+
+    fn sum(&mut self) -> Result<f64, CalcError> {
+        let mut value = self.product()?;
+        while let Some(op @ ('+' | '-')) = self.peek() {
+            self.chars.next();
+            let rhs = self.product()?;
+            value = if op == '+' { value + rhs } else { value - rhs };
+        }
+        Ok(value)
+    }
+
+    fn product(&mut self) -> Result<f64, CalcError> {
+        let mut value = self.unary()?;
+        while let Some(op @ ('*' | '/' | '%')) = self.peek() {
+            self.chars.next();
+            let rhs = self.unary()?;
+            value = match op {
+                '*' => value * rhs,
+                '/' => value / rhs,
+                _ => value % rhs,
+            };
+        }
+        Ok(value)
+    }
+
+    /// Every nested expression goes through here, so this is where the
+    /// depth is limited.
+    fn unary(&mut self) -> Result<f64, CalcError> {
+        if self.depth == MAX_DEPTH {
+            return Err(CalcError::TooDeep);
+        }
+        self.depth += 1;
+        let value = match self.peek() {
+            Some('-') => {
+                self.chars.next();
+                self.unary().map(|value| -value)
+            },
+            Some('+') => {
+                self.chars.next();
+                self.unary()
+            },
+            _ => self.power(),
+        };
+        self.depth -= 1;
+        value
+    }
+
+    fn power(&mut self) -> Result<f64, CalcError> {
+        let base = self.primary()?;
+        if self.peek() == Some('^') {
+            self.chars.next();
+            return Ok(base.powf(self.unary()?));
+        }
+        Ok(base)
+    }
+
+    fn primary(&mut self) -> Result<f64, CalcError> {
+        match self.peek() {
+            Some('(') => {
+                self.chars.next();
+                let value = self.sum()?;
+                self.expect(')')?;
+                Ok(value)
+            },
+            Some(ch) if ch.is_ascii_digit() || ch == '.' => {
+                let start = self.chars.peek().unwrap().0;
+                let mut number = String::new();
+                while let Some((_, ch)) = self.chars.next_if(|(_, ch)| {
+                    ch.is_ascii_digit() || (*ch == '.' && !number.contains('.'))
+                }) {
+                    number.push(ch);
+                }
+                // Only a lone dot is not a number
+                number.parse().map_err(|_| CalcError::UnexpectedChar(start, '.'))
+            },
+            Some(_) => {
+                let (pos, ch) = self.chars.next().unwrap();
+                Err(CalcError::UnexpectedChar(pos, ch))
+            },
+            None => Err(CalcError::UnexpectedEnd),
+        }
+    }
+}
+
+/// Evaluates an arithmetic expression with `+`, `-`, `*`, `/`, `%`
+/// (remainder), `^` (power) and parentheses.
+pub fn evaluate(expr: &str) -> Result<f64, CalcError> {
+    let mut parser = Parser { chars: expr.char_indices().peekable(), depth: 0 };
+    let value = parser.sum()?;
+    if parser.peek().is_some() {
+        let (pos, ch) = parser.chars.next().unwrap();
+        return Err(CalcError::UnexpectedChar(pos, ch));
+    }
+    if !value.is_finite() {
+        return Err(CalcError::NotFinite);
+    }
+    Ok(value)
+}
+
+/// Formats `value` with at most 12 decimal places, so that rounding errors
+/// (e.g. in 0.1 + 0.2) do not show.
+pub fn format_number(value: f64) -> String {
+    let formatted = format!("{:.12}", value);
+    let formatted = formatted.trim_end_matches('0').trim_end_matches('.');
+    match formatted {
+        "-0" => "0".to_string(),
+        _ => formatted.to_string(),
+    }
+}
+
+/// Shows `message` in a notification via notify-send, after copying
+/// `clipboard` (if any) to the clipboard with wl-copy or xclip, depending on
+/// `session`. Failing to copy is only worth a warning.
+pub fn show_result(message: &str, clipboard: Option<&str>, session: SessionType) -> io::Result<()> {
+    if let Some(text) = clipboard {
+        if let Err(err) = copy_to_clipboard(text, session) {
+            log_warn(&tr("Could not copy {} to the clipboard: {}", &[&format!("{:?}", text), &err]));
+        }
+    }
+    let status = Command::new("notify-send").arg("--app-name=i3-dmenu-desktop-rs").arg(message).status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("notify-send failed with {status}")));
+    }
+    Ok(())
+}
+
+fn copy_to_clipboard(text: &str, session: SessionType) -> io::Result<()> {
+    let mut command = if session.is_wayland() {
+        Command::new("wl-copy")
+    } else {
+        let mut command = Command::new("xclip");
+        command.args(["-selection", "clipboard"]);
+        command
+    };
+    // Both keep serving the clipboard in a background process of their own
+    let mut child = command.stdin(Stdio::piped()).stdout(Stdio::null()).spawn()?;
+    child.stdin.take().unwrap().write_all(text.as_bytes())?;
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("{:?} failed with {status}", command.get_program())));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate() {
+        assert_eq!(evaluate("23*7+1"), Ok(162.0));
+        assert_eq!(evaluate(" 2 + 3 * (4 - 1) "), Ok(11.0));
+        assert_eq!(evaluate("-2^2"), Ok(-4.0));
+        assert_eq!(evaluate("2^3^2"), Ok(512.0));
+        assert_eq!(evaluate("2^-1"), Ok(0.5));
+        assert_eq!(evaluate("7 % 4 / 2"), Ok(1.5));
+        assert_eq!(evaluate(".5 + 1."), Ok(1.5));
+        assert_eq!(evaluate("1 / 0"), Err(CalcError::NotFinite));
+        assert_eq!(evaluate("2 +"), Err(CalcError::UnexpectedEnd));
+        assert_eq!(evaluate("(1 + 2"), Err(CalcError::UnexpectedEnd));
+        assert_eq!(evaluate("1 + 2)"), Err(CalcError::UnexpectedChar(5, ')')));
+        assert_eq!(evaluate("2 x 3"), Err(CalcError::UnexpectedChar(2, 'x')));
+        assert_eq!(evaluate("1.2.3"), Err(CalcError::UnexpectedChar(3, '.')));
+        assert_eq!(evaluate("1 + ."), Err(CalcError::UnexpectedChar(4, '.')));
+        assert_eq!(evaluate(""), Err(CalcError::UnexpectedEnd));
+        assert_eq!(evaluate(&format!("{}1{}", "(".repeat(100), ")".repeat(100))), Ok(1.0));
+        assert_eq!(evaluate(&"(".repeat(100_000)), Err(CalcError::TooDeep));
+        assert_eq!(evaluate(&format!("{}1", "-".repeat(100_000))), Err(CalcError::TooDeep));
+        assert_eq!(evaluate(&"2^".repeat(100_000)), Err(CalcError::TooDeep));
+    }
+
+    #[test]
+    fn test_format_number() {
+        assert_eq!(format_number(162.0), "162");
+        assert_eq!(format_number(0.1 + 0.2), "0.3");
+        assert_eq!(format_number(-1.5), "-1.5");
+        assert_eq!(format_number(-0.0), "0");
+        assert_eq!(format_number(1e20), "100000000000000000000");
+    }
+}
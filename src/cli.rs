@@ -0,0 +1,536 @@
+use clap::builder::BoolishValueParser;
+use clap::{Parser, ValueEnum};
+use clap_complete::Shell;
+
+use super::app_launcher::{escape_for_i3_exec, LaunchBackend};
+use super::config::{ActionMode, Config, Mode, TerminalFilter, UsageBadge, WineMode};
+use super::desktop_entry::quote_for_sh;
+use super::menu::MenuProgram;
+use super::provider::ProviderKind;
+use super::session::SessionType;
+use super::CacheKey;
+
+/// A dmenu-based launcher for XDG desktop entries.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    /// Instead of showing a menu, check all desktop entry files for spec
+    /// violations and print them as JSON lines
+    #[arg(long)]
+    pub lint: bool,
+
+    /// Instead of showing a menu, print the contents of the desktop entry
+    /// cache and whether each entry is up to date
+    #[arg(long)]
+    pub dump_cache: bool,
+
+    /// Instead of showing a menu, print all desktop entries (including
+    /// hidden ones) as JSON lines
+    #[arg(long)]
+    pub list_json: bool,
+
+    /// Instead of showing a menu, print each desktop entry file which the
+    /// menu leaves out, and why, as JSON lines
+    #[arg(long)]
+    pub diagnostics_json: bool,
+
+    /// Instead of showing a menu, print how often and when each entry was
+    /// launched as JSON lines, most frequently launched first
+    #[arg(long)]
+    pub history_json: bool,
+
+    /// Instead of showing a menu, delete the launch history
+    #[arg(long)]
+    pub clear_history: bool,
+
+    /// Instead of showing a menu, print an i3/sway config line which binds
+    /// KEY to running the launcher with the other options given
+    #[arg(long, value_name = "KEY", num_args = 0..=1, default_missing_value = "$mod+d")]
+    pub print_keybinding: Option<String>,
+
+    /// Instead of showing a menu, print a completion script for SHELL
+    #[arg(long, value_name = "SHELL", value_enum)]
+    pub completions: Option<Shell>,
+
+    /// Instead of showing a menu, keep running in the background with the
+    /// entries loaded, and show the menu whenever a client asks for it
+    /// (see --client)
+    #[arg(long)]
+    pub daemon: bool,
+
+    /// Ask the daemon to show the menu with these options, which is faster
+    /// than showing it directly; falls back to that if no daemon is running
+    #[arg(long, env = "I3_DMENU_DESKTOP_CLIENT", value_parser = BoolishValueParser::new())]
+    pub client: bool,
+
+    /// Print where the time was spent (parsing, waiting for the menu...)
+    /// to stderr when done
+    #[arg(long)]
+    pub profile: bool,
+
+    /// Do not write the desktop entry cache
+    #[arg(long, env = "I3_DMENU_DESKTOP_NO_CACHE", value_parser = BoolishValueParser::new(),
+          num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub no_cache: Option<bool>,
+
+    /// What the name of the desktop entry cache file depends on, so that
+    /// machines sharing a home directory keep separate caches
+    #[arg(long, value_enum, env = "I3_DMENU_DESKTOP_CACHE_KEY")]
+    pub cache_key: Option<CacheKey>,
+
+    /// Do not record launches in the history
+    #[arg(long, env = "I3_DMENU_DESKTOP_NO_HISTORY", value_parser = BoolishValueParser::new(),
+          num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub no_history: Option<bool>,
+
+    /// Do not write anything to disk, like --no-cache --no-history
+    #[arg(long, env = "I3_DMENU_DESKTOP_EPHEMERAL", value_parser = BoolishValueParser::new(),
+          num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub ephemeral: Option<bool>,
+
+    /// Read extra items from stdin, one per line: either a name and a shell
+    /// command separated by a tab, or a JSON object like
+    /// {"name": "...", "exec": "...", "comment": "...", "icon": "..."}
+    #[arg(long)]
+    pub stdin: bool,
+
+    /// What to choose from
+    #[arg(long, value_enum, env = "I3_DMENU_DESKTOP_MODE")]
+    pub mode: Option<Mode>,
+
+    /// An additional data directory to search for entries (in its
+    /// applications subdirectory); can be given multiple times
+    #[arg(long, value_name = "DIR", env = "I3_DMENU_DESKTOP_EXTRA_DATA_DIR", value_delimiter = ':')]
+    pub extra_data_dir: Vec<String>,
+
+    /// A data directory which should not be searched for entries, e.g.
+    /// /usr/share to only list flatpaks; can be given multiple times
+    #[arg(long, value_name = "DIR", env = "I3_DMENU_DESKTOP_SKIP_DATA_DIR", value_delimiter = ':')]
+    pub skip_data_dir: Vec<String>,
+
+    /// A directory whose AppImages should be listed, e.g. ~/Applications;
+    /// can be given multiple times
+    #[arg(long, value_name = "DIR", env = "I3_DMENU_DESKTOP_APPIMAGE_DIR", value_delimiter = ':')]
+    pub appimage_dir: Vec<String>,
+
+    /// How the selected application should be launched
+    #[arg(long, value_enum, env = "I3_DMENU_DESKTOP_LAUNCH_BACKEND")]
+    pub launch_backend: Option<LaunchBackend>,
+
+    /// An environment variable which launched applications do not get
+    /// (a trailing "*" matches a prefix); can be given multiple times
+    #[arg(long, value_name = "NAME", env = "I3_DMENU_DESKTOP_UNSET_ENV", value_delimiter = ',')]
+    pub unset_env: Vec<String>,
+
+    /// An environment variable which is set for launched applications; can
+    /// be given multiple times
+    #[arg(long, value_name = "NAME=VALUE", value_parser = parse_env_assignment, env = "I3_DMENU_DESKTOP_SET_ENV")]
+    pub set_env: Vec<(String, String)>,
+
+    /// A command which every launched command is run with, e.g.
+    /// "systemd-inhibit --what=handle-lid-switch"
+    #[arg(long, value_name = "CMD", env = "I3_DMENU_DESKTOP_WRAPPER")]
+    pub wrapper: Option<String>,
+
+    /// Always start a new instance, even of entries with
+    /// SingleMainWindow=true which already have a window
+    #[arg(long, env = "I3_DMENU_DESKTOP_NEW_INSTANCE", value_parser = BoolishValueParser::new(),
+          num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub new_instance: Option<bool>,
+
+    /// Show a menu even while another instance is showing one on the same
+    /// display, instead of exiting
+    #[arg(long, env = "I3_DMENU_DESKTOP_NO_SINGLE_INSTANCE", value_parser = BoolishValueParser::new(),
+          num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub no_single_instance: Option<bool>,
+
+    /// The program used to display the menu
+    #[arg(long, value_enum, env = "I3_DMENU_DESKTOP_MENU")]
+    pub menu: Option<MenuProgram>,
+
+    /// Where the rows of the menu come from: desktop entries (drun),
+    /// executables in PATH (run), open windows (window) or the hosts in
+    /// ~/.ssh/config (ssh); can be given multiple times
+    #[arg(long, value_enum, value_name = "PROVIDER", env = "I3_DMENU_DESKTOP_PROVIDER", value_delimiter = ',')]
+    pub provider: Vec<ProviderKind>,
+
+    /// Prefix rows with the provider which they come from, e.g. "run: htop"
+    #[arg(long, env = "I3_DMENU_DESKTOP_PROVIDER_PREFIXES", value_parser = BoolishValueParser::new(),
+          num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub provider_prefixes: Option<bool>,
+
+    /// Match the typed text case-sensitively, in the menu and when looking
+    /// up the selected entry
+    #[arg(long, env = "I3_DMENU_DESKTOP_CASE_SENSITIVE", value_parser = BoolishValueParser::new(),
+          num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub case_sensitive: Option<bool>,
+
+    /// Ask for confirmation before running text which does not match any
+    /// entry as a command
+    #[arg(long, env = "I3_DMENU_DESKTOP_CONFIRM_ARBITRARY_INPUT", value_parser = BoolishValueParser::new(),
+          num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub confirm_arbitrary_input: Option<bool>,
+
+    /// Launch the entry which typed text fuzzily matches best, if the score
+    /// of the match (from 0 to 1) is at least SCORE, instead of running the
+    /// text as a command
+    #[arg(long, value_name = "SCORE", env = "I3_DMENU_DESKTOP_FUZZY_THRESHOLD")]
+    pub fuzzy_threshold: Option<f64>,
+
+    /// After running a command which is not an entry, ask for a name to save
+    /// it under as a new entry in ~/.local/share/applications
+    #[arg(long, env = "I3_DMENU_DESKTOP_CREATE_ENTRY", value_parser = BoolishValueParser::new(),
+          num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub create_entry: Option<bool>,
+
+    /// Launch entries with NoDisplay=true when their exact name or desktop
+    /// file ID is typed
+    #[arg(long, env = "I3_DMENU_DESKTOP_RESOLVE_NODISPLAY", value_parser = BoolishValueParser::new(),
+          num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub resolve_nodisplay: Option<bool>,
+
+    /// List entries with NoDisplay=true, marked with "[NoDisplay]"
+    #[arg(long, env = "I3_DMENU_DESKTOP_INCLUDE_NODISPLAY", value_parser = BoolishValueParser::new(),
+          num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub include_nodisplay: Option<bool>,
+
+    /// List entries with Hidden=true, marked with "[Hidden]"
+    #[arg(long, env = "I3_DMENU_DESKTOP_INCLUDE_HIDDEN", value_parser = BoolishValueParser::new(),
+          num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub include_hidden: Option<bool>,
+
+    /// Which entries with Terminal=true should be listed
+    #[arg(long, value_enum, env = "I3_DMENU_DESKTOP_TERMINAL_APPS")]
+    pub terminal_apps: Option<TerminalFilter>,
+
+    /// How entries which Wine generated for Windows programs are listed
+    #[arg(long, value_enum, env = "I3_DMENU_DESKTOP_WINE_APPS")]
+    pub wine_apps: Option<WineMode>,
+
+    /// What is shown next to entries which were launched before
+    #[arg(long, value_enum, env = "I3_DMENU_DESKTOP_USAGE_BADGE")]
+    pub usage_badge: Option<UsageBadge>,
+
+    /// List the N most recently launched entries at the top
+    #[arg(long, value_name = "N", env = "I3_DMENU_DESKTOP_RECENT_APPS")]
+    pub recent_apps: Option<usize>,
+
+    /// How the desktop actions of entries (e.g. "New Private Window") are offered
+    #[arg(long, value_enum, env = "I3_DMENU_DESKTOP_ACTIONS")]
+    pub actions: Option<ActionMode>,
+
+    /// The prompt shown by the menu program
+    #[arg(long, env = "I3_DMENU_DESKTOP_PROMPT")]
+    pub prompt: Option<String>,
+
+    /// Show N rows in a vertical list (translated to the right option for
+    /// the menu program)
+    #[arg(long, value_name = "N", env = "I3_DMENU_DESKTOP_LINES")]
+    pub lines: Option<usize>,
+
+    /// Show the menu at the bottom of the screen
+    #[arg(long, env = "I3_DMENU_DESKTOP_BOTTOM", value_parser = BoolishValueParser::new(),
+          num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub bottom: Option<bool>,
+
+    /// Kill the menu program if it has not exited after this many seconds
+    #[arg(long, value_name = "SECS", env = "I3_DMENU_DESKTOP_MENU_TIMEOUT")]
+    pub menu_timeout: Option<u64>,
+
+    /// Extra arguments which are passed verbatim to the menu program,
+    /// e.g. `-- -l 20 -p run:` for dmenu
+    #[arg(last = true)]
+    pub menu_args: Vec<String>,
+}
+
+fn parse_env_assignment(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err("expected NAME=VALUE".to_string()),
+    }
+}
+
+/// Returns the name which `value` is given with on the command line.
+fn value_name(value: impl ValueEnum) -> String {
+    value.to_possible_value().map(|value| value.get_name().to_string()).unwrap_or_default()
+}
+
+impl Cli {
+    /// Selects the providers from the name which the program was invoked
+    /// with (e.g. through a symlink called i3-dmenu-run), unless they were
+    /// given explicitly.
+    pub fn apply_program_name(&mut self, arg0: &str) {
+        let program = arg0.rsplit('/').next().unwrap_or_default();
+        let provider = match program {
+            "i3-dmenu-desktop" => ProviderKind::Drun,
+            "i3-dmenu-run" => ProviderKind::Run,
+            "i3-dmenu-windows" => ProviderKind::Window,
+            _ => return,
+        };
+        if self.provider.is_empty() {
+            self.provider = vec![provider];
+        }
+    }
+
+    /// Returns the command-line arguments which result in the same settings
+    /// as these, leaving out the options which do something other than
+    /// showing the menu (e.g. --lint).
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        let flags = [
+            (self.client, "--client"),
+            (self.stdin, "--stdin"),
+        ];
+        // Flags which can be switched off again, e.g. to override the config file
+        let switches = [
+            (self.no_cache, "--no-cache"),
+            (self.no_history, "--no-history"),
+            (self.ephemeral, "--ephemeral"),
+            (self.new_instance, "--new-instance"),
+            (self.no_single_instance, "--no-single-instance"),
+            (self.provider_prefixes, "--provider-prefixes"),
+            (self.case_sensitive, "--case-sensitive"),
+            (self.confirm_arbitrary_input, "--confirm-arbitrary-input"),
+            (self.create_entry, "--create-entry"),
+            (self.resolve_nodisplay, "--resolve-nodisplay"),
+            (self.include_nodisplay, "--include-nodisplay"),
+            (self.include_hidden, "--include-hidden"),
+            (self.bottom, "--bottom"),
+        ];
+        let options = [
+            ("--mode", self.mode.map(value_name)),
+            ("--cache-key", self.cache_key.map(value_name)),
+            ("--launch-backend", self.launch_backend.map(value_name)),
+            ("--wrapper", self.wrapper.clone()),
+            ("--menu", self.menu.map(value_name)),
+            ("--terminal-apps", self.terminal_apps.map(value_name)),
+            ("--wine-apps", self.wine_apps.map(value_name)),
+            ("--usage-badge", self.usage_badge.map(value_name)),
+            ("--recent-apps", self.recent_apps.map(|n| n.to_string())),
+            ("--actions", self.actions.map(value_name)),
+            ("--prompt", self.prompt.clone()),
+            ("--lines", self.lines.map(|n| n.to_string())),
+            ("--fuzzy-threshold", self.fuzzy_threshold.map(|score| score.to_string())),
+            ("--menu-timeout", self.menu_timeout.map(|secs| secs.to_string())),
+        ];
+        let repeated_options = [
+            ("--extra-data-dir", self.extra_data_dir.clone()),
+            ("--skip-data-dir", self.skip_data_dir.clone()),
+            ("--appimage-dir", self.appimage_dir.clone()),
+            ("--provider", self.provider.iter().map(|provider| value_name(*provider)).collect()),
+            ("--unset-env", self.unset_env.clone()),
+            ("--set-env", self.set_env.iter().map(|(name, value)| format!("{}={}", name, value)).collect()),
+        ];
+        args.extend(flags.into_iter().filter(|(set, _)| *set).map(|(_, flag)| flag.to_string()));
+        args.extend(switches.into_iter().filter_map(|(value, flag)| match value? {
+            true => Some(flag.to_string()),
+            false => Some(format!("{}=false", flag)),
+        }));
+        for (option, value) in options {
+            if let Some(value) = value {
+                args.extend([option.to_string(), value]);
+            }
+        }
+        for (option, values) in repeated_options {
+            for value in values {
+                args.extend([option.to_string(), value]);
+            }
+        }
+        if !self.menu_args.is_empty() {
+            args.push("--".to_string());
+            args.extend(self.menu_args.iter().cloned());
+        }
+        args
+    }
+
+    /// Returns a line for the i3 (or sway) config file which binds `key` to
+    /// running `program` with the same options as these.
+    pub fn keybinding(&self, key: &str, program: &str, session: SessionType) -> String {
+        let cmd = [program.to_string()].into_iter()
+            .chain(self.to_args())
+            .map(|arg| quote_for_sh(&arg))
+            .collect::<Vec<_>>()
+            .join(" ");
+        // Startup notifications are only sent for the launched applications,
+        // not for the launcher itself. sway does not know this option.
+        let no_startup_id = if session == SessionType::Sway { "" } else { "--no-startup-id " };
+        format!("bindsym {} exec {}{}", key, no_startup_id, escape_for_i3_exec(&cmd))
+    }
+
+    /// Overrides the values in `config` with the ones given on the command line.
+    pub fn apply_to(self, config: &mut Config) {
+        if let Some(mode) = self.mode {
+            config.mode = mode;
+        }
+        if let Some(no_cache) = self.no_cache {
+            config.cache = !no_cache;
+        }
+        if let Some(no_history) = self.no_history {
+            config.history = !no_history;
+        }
+        if self.ephemeral == Some(true) {
+            config.cache = false;
+            config.history = false;
+        }
+        if let Some(cache_key) = self.cache_key {
+            config.cache_key = cache_key;
+        }
+        config.extra_data_dirs.extend(self.extra_data_dir);
+        config.skip_data_dirs.extend(self.skip_data_dir);
+        config.appimage_dirs.extend(self.appimage_dir);
+        config.env.deny.extend(self.unset_env);
+        config.env.set.extend(self.set_env);
+        if self.wrapper.is_some() {
+            config.wrapper = self.wrapper;
+        }
+        if let Some(new_instance) = self.new_instance {
+            config.single_main_window = !new_instance;
+        }
+        if let Some(no_single_instance) = self.no_single_instance {
+            config.single_instance = !no_single_instance;
+        }
+        if self.launch_backend.is_some() {
+            config.launch_backend = self.launch_backend;
+        }
+        if self.menu.is_some() {
+            config.menu = self.menu;
+        }
+        if !self.provider.is_empty() {
+            config.providers = self.provider;
+        }
+        if let Some(provider_prefixes) = self.provider_prefixes {
+            config.provider_prefixes = provider_prefixes;
+        }
+        if let Some(case_sensitive) = self.case_sensitive {
+            config.case_sensitive = case_sensitive;
+        }
+        if let Some(confirm_arbitrary_input) = self.confirm_arbitrary_input {
+            config.confirm_arbitrary_input = confirm_arbitrary_input;
+        }
+        if self.fuzzy_threshold.is_some() {
+            config.fuzzy_threshold = self.fuzzy_threshold;
+        }
+        if let Some(create_entry) = self.create_entry {
+            config.create_entry = create_entry;
+        }
+        if let Some(resolve_nodisplay) = self.resolve_nodisplay {
+            config.resolve_nodisplay = resolve_nodisplay;
+        }
+        if let Some(include_nodisplay) = self.include_nodisplay {
+            config.include_nodisplay = include_nodisplay;
+        }
+        if let Some(include_hidden) = self.include_hidden {
+            config.include_hidden = include_hidden;
+        }
+        if let Some(terminal_apps) = self.terminal_apps {
+            config.terminal_apps = terminal_apps;
+        }
+        if let Some(wine_apps) = self.wine_apps {
+            config.wine_apps = wine_apps;
+        }
+        if let Some(usage_badge) = self.usage_badge {
+            config.usage_badge = usage_badge;
+        }
+        if let Some(recent_apps) = self.recent_apps {
+            config.recent_apps = recent_apps;
+        }
+        if let Some(actions) = self.actions {
+            config.actions = actions;
+        }
+        if self.prompt.is_some() {
+            config.prompt = self.prompt;
+        }
+        if self.lines.is_some() {
+            config.lines = self.lines;
+        }
+        if let Some(bottom) = self.bottom {
+            config.bottom = bottom;
+        }
+        if self.menu_timeout.is_some() {
+            config.menu_timeout = self.menu_timeout;
+        }
+        if !self.menu_args.is_empty() {
+            config.menu_args = self.menu_args;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_args() {
+        let args = ["i3-dmenu-desktop-rs", "--print-keybinding", "--mode", "menu", "--set-env", "A=b c",
+                    "--menu", "rofi", "--no-cache", "--extra-data-dir", "/a", "--extra-data-dir", "/b", "--", "-i"];
+        let cli = Cli::parse_from(args);
+        assert_eq!(cli.to_args(), ["--no-cache", "--mode", "menu", "--menu", "rofi", "--extra-data-dir", "/a",
+                                   "--extra-data-dir", "/b", "--set-env", "A=b c", "--", "-i"]);
+        // The arguments result in the same settings
+        let reparsed = Cli::parse_from(["i3-dmenu-desktop-rs".to_string()].into_iter().chain(cli.to_args()));
+        assert_eq!(reparsed.to_args(), cli.to_args());
+    }
+
+    #[test]
+    fn test_apply_switches() {
+        let mut config = Config { case_sensitive: true, cache: false, ..Config::default() };
+        Cli::parse_from(["i3-dmenu-desktop-rs", "--case-sensitive=false", "--no-cache=0", "--bottom"]).apply_to(&mut config);
+        assert!(!config.case_sensitive);
+        assert!(config.cache);
+        assert!(config.bottom);
+        // Switches which are not given leave the config alone
+        Cli::parse_from(["i3-dmenu-desktop-rs"]).apply_to(&mut config);
+        assert!(config.bottom);
+    }
+
+    #[test]
+    fn test_apply_program_name() {
+        let providers = |args: &[&str]| {
+            let mut cli = Cli::parse_from(args);
+            cli.apply_program_name(args[0]);
+            cli.provider
+        };
+        assert_eq!(providers(&["/usr/local/bin/i3-dmenu-run"]), [ProviderKind::Run]);
+        assert_eq!(providers(&["i3-dmenu-windows", "--lines", "10"]), [ProviderKind::Window]);
+        assert_eq!(providers(&["i3-dmenu-desktop"]), [ProviderKind::Drun]);
+        assert_eq!(providers(&["i3-dmenu-run", "--provider", "run,ssh"]), [ProviderKind::Run, ProviderKind::Ssh]);
+        assert!(providers(&["i3-dmenu-desktop-rs"]).is_empty());
+    }
+
+    #[test]
+    fn test_keybinding() {
+        let cli = Cli::parse_from(["i3-dmenu-desktop-rs", "--prompt", "run; now:", "--", "-l", "20"]);
+        assert_eq!(cli.keybinding("$mod+d", "/usr/bin/i3-dmenu-desktop-rs", SessionType::I3),
+                   r#"bindsym $mod+d exec --no-startup-id "/usr/bin/i3-dmenu-desktop-rs --prompt 'run; now:' -- -l 20""#);
+        let cli = Cli::parse_from(["i3-dmenu-desktop-rs", "--menu", "fuzzel"]);
+        assert_eq!(cli.keybinding("Mod4+space", "i3-dmenu-desktop-rs", SessionType::Sway),
+                   r#"bindsym Mod4+space exec "i3-dmenu-desktop-rs --menu fuzzel""#);
+    }
+
+    #[test]
+    fn test_completions() {
+        use clap::CommandFactory;
+        let mut script = Vec::new();
+        clap_complete::generate(Shell::Bash, &mut Cli::command(), "i3-dmenu-desktop-rs", &mut script);
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains("--print-keybinding"));
+        assert!(script.contains("dmenu rofi bemenu"));
+    }
+
+    #[test]
+    fn test_env_vars() {
+        use clap::CommandFactory;
+        // Every option which changes how the menu behaves has an environment
+        // variable named after it
+        let actions = ["lint", "dump-cache", "list-json", "diagnostics-json", "history-json", "clear-history",
+                       "print-keybinding", "completions", "daemon", "profile", "stdin"];
+        for arg in Cli::command().get_arguments() {
+            let Some(long) = arg.get_long().filter(|long| !actions.contains(long)) else {
+                continue;
+            };
+            let env = arg.get_env().and_then(|env| env.to_str());
+            let expected = format!("I3_DMENU_DESKTOP_{}", long.to_uppercase().replace('-', "_"));
+            assert_eq!(env, Some(expected.as_str()), "--{}", long);
+        }
+    }
+}
@@ -0,0 +1,392 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use super::app_launcher::{EnvPolicy, Gpu, GpuEnv, LaunchBackend, ProcessPriority};
+use super::menu::MenuProgram;
+use super::provider::{ProviderKind, Script};
+use super::desktop_entry::Origin;
+use super::i18n::gettext;
+use super::{CacheKey, Persistence};
+
+#[derive(Debug)]
+pub enum ConfigError {
+    IoError(io::Error),
+    ParseError(toml::de::Error),
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(error: io::Error) -> Self { Self::IoError(error) }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(error: toml::de::Error) -> Self { Self::ParseError(error) }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::IoError(err) => write!(f, "{err}"),
+            Self::ParseError(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+/// Which entries with Terminal=true should be listed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TerminalFilter {
+    /// List terminal apps along with all other apps
+    Include,
+    /// Do not list terminal apps
+    Exclude,
+    /// Only list terminal apps
+    Only,
+}
+
+impl TerminalFilter {
+    pub fn matches(self, terminal: bool) -> bool {
+        match self {
+            Self::Include => true,
+            Self::Exclude => !terminal,
+            Self::Only => terminal,
+        }
+    }
+}
+
+/// How entries which Wine generated for Windows programs are listed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WineMode {
+    /// List them along with all other apps
+    Show,
+    /// List them with a "Wine: " prefix
+    Prefix,
+    /// List a single "Wine" row which opens a submenu with them
+    Group,
+    /// Do not list them
+    Exclude,
+}
+
+/// What is shown next to entries which were launched before, e.g. so that
+/// rofi themes can highlight frequently used apps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UsageBadge {
+    /// Show nothing
+    None,
+    /// Show how often the entry was launched
+    Count,
+    /// Mark entries which were launched within the last week
+    Recent,
+}
+
+/// What the user gets to choose from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mode {
+    /// A flat list of all applications
+    Apps,
+    /// The hierarchical menu from applications.menu, one level at a time
+    Menu,
+    /// The entries which are started with the session, which can be
+    /// launched or enabled/disabled
+    Autostart,
+}
+
+/// How the desktop actions of an entry (e.g. "New Private Window") are offered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ActionMode {
+    /// Do not offer desktop actions
+    None,
+    /// List each action as a separate entry, e.g. "Firefox - New Window"
+    Flatten,
+    /// After selecting an entry with actions, choose one of them in a second menu
+    Submenu,
+}
+
+/// Settings which control how the launcher behaves.
+///
+/// These are read from `$XDG_CONFIG_HOME/i3-dmenu-desktop-rs/config.toml` and
+/// can be overridden from the command line. Settings which are `None` are
+/// chosen automatically based on the current session (see
+/// [`crate::session::SessionType`]).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub mode: Mode,
+    /// Data directories which are searched for entries (in their
+    /// applications subdirectory) after the standard XDG ones.
+    pub extra_data_dirs: Vec<String>,
+    /// Data directories which are not searched for entries, even if they
+    /// are listed in XDG_DATA_DIRS.
+    pub skip_data_dirs: Vec<String>,
+    /// The origins (e.g. "snap") whose data directories are not searched,
+    /// like skip_data_dirs.
+    pub skip_origins: Vec<Origin>,
+    /// The locales whose translations of names and comments are used, in
+    /// order of preference. If empty, LANGUAGE and the locale decide.
+    pub locales: Vec<String>,
+    /// Directories whose AppImages are listed alongside the entries, using
+    /// the desktop entry embedded in each of them.
+    pub appimage_dirs: Vec<String>,
+    pub launch_backend: Option<LaunchBackend>,
+    /// The environment variables which are removed or set for launched
+    /// applications (with the exec, uwsm and systemd-run backends).
+    pub env: EnvPolicy,
+    /// A command which every launched command is run with, e.g.
+    /// "systemd-inhibit --what=handle-lid-switch". Not supported by the gio
+    /// and gtk-launch backends.
+    pub wrapper: Option<String>,
+    /// Commands which the entries in certain categories are run with, e.g.
+    /// "gamemoderun" for "Game". These go inside of `wrapper`.
+    pub category_wrappers: BTreeMap<String, String>,
+    /// The scheduling priority of launched applications, by desktop file ID
+    /// (e.g. "org.gimp.GIMP.desktop") or category (e.g. "Game"). Settings
+    /// for the desktop file ID take precedence.
+    pub priority: BTreeMap<String, ProcessPriority>,
+    /// The GPU which applications are rendered on, by desktop file ID or
+    /// category, like `priority`. Entries with PrefersNonDefaultGPU=true
+    /// which are not listed here get the discrete GPU.
+    pub gpu: BTreeMap<String, Gpu>,
+    /// The environment variables which select each GPU.
+    pub gpu_env: GpuEnv,
+    /// Whether entries with SingleMainWindow=true focus their existing
+    /// window (in i3 and sway) instead of being started again.
+    pub single_main_window: bool,
+    /// Whether the launcher exits (with status 6) instead of showing a
+    /// second menu while another instance is showing one on the same
+    /// display.
+    pub single_instance: bool,
+    /// Whether entries whose TryExec program is not installed are left out,
+    /// as the spec says.
+    pub check_try_exec: bool,
+    /// Whether entries whose Exec program cannot be found are left out, e.g.
+    /// leftovers of removed packages in ~/.local/share/applications.
+    pub verify_exec: bool,
+    /// Whether entries are launched with their TryExec command instead of
+    /// Exec if they have one, like older versions did. The spec only uses
+    /// TryExec to check whether the program is installed.
+    pub launch_try_exec: bool,
+    /// Whether entries without the StartupNotify key are launched with
+    /// startup notification, as if they had StartupNotify=true.
+    pub startup_notify_default: bool,
+    pub menu: Option<MenuProgram>,
+    /// The menu programs to try, in order, if `menu` is not set. The first
+    /// one which is installed gets used.
+    pub menu_fallbacks: Option<Vec<MenuProgram>>,
+    /// Where the rows of the menu come from (in the apps mode). Items passed
+    /// in with `--stdin` are always listed.
+    pub providers: Vec<ProviderKind>,
+    /// External programs which list more rows, by name.
+    pub scripts: BTreeMap<String, Script>,
+    /// Whether rows are prefixed with the provider which they come from
+    /// (e.g. "run: htop"), if there are several.
+    pub provider_prefixes: bool,
+    /// Whether text which does not match any entry should be run as a shell
+    /// command. Menu programs which can restrict the input to the listed
+    /// entries (rofi, tofi) are told to do so when this is disabled.
+    pub allow_arbitrary_input: bool,
+    /// Whether a menu with a single row asks for confirmation before text
+    /// which does not match any entry is run as a shell command, in case it
+    /// was mistyped.
+    pub confirm_arbitrary_input: bool,
+    /// Whether typed text only matches names with the same case, both in
+    /// the menu and when looking up the selected entry.
+    pub case_sensitive: bool,
+    /// If set, text which does not match any entry launches the entry
+    /// whose name, keywords or executable it fuzzily matches best, if the
+    /// score of that match (from 0 to 1, see [`crate::fuzzy::score`]) is
+    /// at least this. Otherwise, the text is run as a command.
+    pub fuzzy_threshold: Option<f64>,
+    /// Names which launch the entry with the given desktop file ID when
+    /// typed, e.g. "ff" for "firefox.desktop".
+    pub aliases: BTreeMap<String, String>,
+    /// Whether the aliases are listed as rows of the menu too.
+    pub list_aliases: bool,
+    /// Whether the user is asked for a name after running a command which is
+    /// not an entry, so that it can be saved as a new entry.
+    pub create_entry: bool,
+    /// Whether typing the exact name or desktop file ID of an entry with
+    /// NoDisplay=true launches that entry.
+    pub resolve_nodisplay: bool,
+    /// Whether entries with NoDisplay=true are listed (with a marker).
+    pub include_nodisplay: bool,
+    /// Whether entries with Hidden=true are listed (with a marker).
+    pub include_hidden: bool,
+    pub terminal_apps: TerminalFilter,
+    pub wine_apps: WineMode,
+    pub usage_badge: UsageBadge,
+    /// The number of most recently launched entries which are listed at the
+    /// top, above a divider. The other entries stay sorted by name.
+    pub recent_apps: usize,
+    /// The maximum number of entries in the launch history; the least
+    /// recently launched ones are forgotten first. Zero means no limit.
+    pub history_max_entries: usize,
+    /// The number of days after which an entry which was not launched again
+    /// is forgotten. Zero means no limit.
+    pub history_max_age_days: u64,
+    /// Whether the desktop entry cache is written (it is still read).
+    pub cache: bool,
+    pub cache_key: CacheKey,
+    /// Whether launches are recorded in the history.
+    pub history: bool,
+    pub actions: ActionMode,
+    /// The prompt shown in the action submenu.
+    pub action_prompt: String,
+    /// Typing this prefix followed by the name of an entry hides the entry
+    /// for the current user, by writing a copy of it with Hidden=true to
+    /// ~/.local/share/applications. An empty prefix disables this.
+    pub hide_prefix: String,
+    /// Typing this prefix followed by an arithmetic expression (e.g.
+    /// "= 23*7+1") shows the result in a notification and copies it to the
+    /// clipboard. An empty prefix disables this.
+    pub calc_prefix: String,
+    /// Typing this prefix followed by a query opens `search_url` for it in
+    /// the default browser. An empty prefix disables this.
+    pub search_prefix: String,
+    /// The URL which searches open, with "%s" replaced by the query, e.g.
+    /// "https://duckduckgo.com/?q=%s". Searching is disabled if unset.
+    pub search_url: Option<String>,
+    /// The prompt shown by the menu program.
+    pub prompt: Option<String>,
+    /// The number of rows which the menu shows in a vertical list.
+    pub lines: Option<usize>,
+    /// Whether the menu is shown at the bottom of the screen.
+    pub bottom: bool,
+    /// Extra arguments which are passed verbatim to the menu program.
+    pub menu_args: Vec<String>,
+    /// The number of seconds after which the menu program is killed, in
+    /// case it hangs.
+    pub menu_timeout: Option<u64>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            mode: Mode::Apps,
+            extra_data_dirs: Vec::new(),
+            skip_data_dirs: Vec::new(),
+            skip_origins: Vec::new(),
+            locales: Vec::new(),
+            appimage_dirs: Vec::new(),
+            launch_backend: None,
+            env: EnvPolicy::default(),
+            wrapper: None,
+            category_wrappers: BTreeMap::new(),
+            priority: BTreeMap::new(),
+            gpu: BTreeMap::new(),
+            gpu_env: GpuEnv::default(),
+            single_main_window: true,
+            single_instance: true,
+            startup_notify_default: false,
+            check_try_exec: true,
+            verify_exec: false,
+            launch_try_exec: false,
+            menu: None,
+            menu_fallbacks: None,
+            providers: vec![ProviderKind::Drun],
+            scripts: BTreeMap::new(),
+            provider_prefixes: false,
+            allow_arbitrary_input: true,
+            confirm_arbitrary_input: false,
+            case_sensitive: false,
+            fuzzy_threshold: None,
+            aliases: BTreeMap::new(),
+            list_aliases: false,
+            create_entry: false,
+            resolve_nodisplay: false,
+            include_nodisplay: false,
+            include_hidden: false,
+            terminal_apps: TerminalFilter::Include,
+            wine_apps: WineMode::Show,
+            usage_badge: UsageBadge::None,
+            recent_apps: 0,
+            history_max_entries: 1000,
+            history_max_age_days: 365,
+            cache: true,
+            cache_key: CacheKey::Host,
+            history: true,
+            actions: ActionMode::None,
+            action_prompt: gettext("actions:"),
+            hide_prefix: "!hide ".to_string(),
+            calc_prefix: "=".to_string(),
+            search_prefix: "?".to_string(),
+            search_url: None,
+            prompt: None,
+            lines: None,
+            bottom: false,
+            menu_args: Vec::new(),
+            menu_timeout: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn persistence(&self) -> Persistence {
+        Persistence { cache: self.cache, history: self.history }
+    }
+
+    /// Reads the config file at `path`. A missing file is not an error.
+    pub fn load(path: &str) -> Result<Self, ConfigError> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(toml::from_str(&contents)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config: Config = toml::from_str(r#"
+            launch_backend = "systemd-run"
+            menu = "tofi"
+            allow_arbitrary_input = false
+            menu_args = ["-l", "20"]
+        "#).unwrap();
+        assert_eq!(config.launch_backend, Some(LaunchBackend::SystemdRun));
+        assert_eq!(config.menu, Some(MenuProgram::Tofi));
+        assert!(!config.allow_arbitrary_input);
+        assert_eq!(config.menu_args, ["-l", "20"]);
+    }
+
+    #[test]
+    fn test_parse_env() {
+        let config: Config = toml::from_str(r#"
+            menu = "dmenu"
+            [env]
+            deny = ["VIRTUAL_ENV*"]
+            set = { MOZ_ENABLE_WAYLAND = "1" }
+        "#).unwrap();
+        assert_eq!(config.env.allow, None);
+        assert_eq!(config.env.deny, ["VIRTUAL_ENV*"]);
+        assert_eq!(config.env.set["MOZ_ENABLE_WAYLAND"], "1");
+    }
+
+    #[test]
+    fn test_parse_aliases() {
+        let config: Config = toml::from_str(r#"
+            aliases = { ff = "firefox.desktop", files = "org.gnome.Nautilus.desktop" }
+            list_aliases = true
+        "#).unwrap();
+        assert_eq!(config.aliases["ff"], "firefox.desktop");
+        assert_eq!(config.aliases.len(), 2);
+        assert!(config.list_aliases);
+    }
+
+    #[test]
+    fn test_parse_unknown_key() {
+        assert!(toml::from_str::<Config>("no_such_key = 1").is_err());
+    }
+}
@@ -0,0 +1,242 @@
+//! The daemon mode, which keeps the launcher loaded so that showing the menu
+//! does not have to wait for the desktop entries to be read, and the
+//! protocol which clients use to talk to it.
+//!
+//! # Protocol
+//!
+//! The daemon listens on the unix socket at
+//! `$XDG_RUNTIME_DIR/i3-dmenu-desktop-rs.sock`. A client connects, sends one
+//! request and reads one response, after which the daemon closes the
+//! connection. Requests are handled one at a time, so the response only
+//! arrives once the menu was closed and the selection launched.
+//!
+//! Both messages are JSON objects, each preceded by its length in bytes as
+//! a 32-bit big-endian integer. A request holds the command-line options of
+//! the launcher, which are applied on top of the daemon's configuration:
+//!
+//! ```json
+//! {"version": 1, "args": ["--provider", "run", "--lines", "20"]}
+//! ```
+//!
+//! The response holds the exit status which the launcher would have exited
+//! with (see [`ChildProcessError::exit_code`]), and an error message if
+//! something went wrong:
+//!
+//! ```json
+//! {"version": 1, "status": 2, "error": null}
+//! ```
+//!
+//! `version` is [`PROTOCOL_VERSION`], which changes whenever either message
+//! changes incompatibly. The daemon answers requests with another version
+//! with status 1 and an error.
+
+use std::env::VarError;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::iter;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use clap::Parser;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use signal_hook::consts::SIGUSR1;
+use signal_hook::iterator::Signals;
+
+use super::app_launcher::ChildProcessError;
+use super::cli::Cli;
+use super::config::Config;
+use super::i18n::tr;
+use super::utils::log_warn;
+use super::XDGManager;
+
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Messages longer than this are rejected, so that a broken client cannot
+/// make the daemon allocate arbitrary amounts of memory.
+const MAX_MESSAGE_LEN: u32 = 1 << 20;
+
+/// A client which does not send its whole request within this time is
+/// answered with an error, so that it cannot block the other clients.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Request {
+    pub version: u32,
+    /// The command-line options, without the program name
+    pub args: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    pub version: u32,
+    /// The exit status of the launcher, 0 if something was launched
+    pub status: i32,
+    pub error: Option<String>,
+}
+
+impl Response {
+    fn from_result(result: Result<(), ChildProcessError>) -> Self {
+        match result {
+            Ok(()) => Self { version: PROTOCOL_VERSION, status: 0, error: None },
+            Err(err) => Self {
+                version: PROTOCOL_VERSION,
+                status: err.exit_code(),
+                error: (!matches!(err, ChildProcessError::Cancelled)).then(|| err.to_string()),
+            },
+        }
+    }
+
+    fn error(message: String) -> Self {
+        Self { version: PROTOCOL_VERSION, status: 1, error: Some(message) }
+    }
+}
+
+/// Writes `message` as JSON, preceded by its length.
+pub fn write_message(writer: &mut impl Write, message: &impl Serialize) -> io::Result<()> {
+    let json = serde_json::to_vec(message)?;
+    let len = u32::try_from(json.len()).ok().filter(|len| *len <= MAX_MESSAGE_LEN)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "the message is too long"))?;
+    writer.write_all(&len.to_be_bytes())?;
+    writer.write_all(&json)?;
+    writer.flush()
+}
+
+/// Reads a message written by [`write_message`].
+pub fn read_message<T: DeserializeOwned>(reader: &mut impl Read) -> io::Result<T> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len);
+    if len > MAX_MESSAGE_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("the message is too long ({} bytes)", len)));
+    }
+    let mut json = vec![0; len as usize];
+    reader.read_exact(&mut json)?;
+    Ok(serde_json::from_slice(&json)?)
+}
+
+/// Sends `request` to the daemon listening on `socket_path` and waits for
+/// its response.
+pub fn send_request(socket_path: &str, request: &Request) -> io::Result<Response> {
+    let mut stream = UnixStream::connect(socket_path)?;
+    write_message(&mut stream, request)?;
+    read_message(&mut stream)
+}
+
+/// Shows the menu for `request`, with the options in it applied to `config`.
+pub fn handle_request<F>(mgr: &XDGManager<F>, config: &Config, request: Request) -> Response
+where
+    F: Fn(&str) -> Result<String, VarError>
+{
+    if request.version != PROTOCOL_VERSION {
+        return Response::error(format!("unsupported protocol version {} (expected {})",
+                                       request.version, PROTOCOL_VERSION));
+    }
+    let cli = match Cli::try_parse_from(iter::once("i3-dmenu-desktop-rs".to_string()).chain(request.args)) {
+        Ok(cli) => cli,
+        Err(err) => return Response::error(err.to_string()),
+    };
+    let mut config = config.clone();
+    cli.apply_to(&mut config);
+    Response::from_result(mgr.start_app_launcher(&config))
+}
+
+/// Runs the daemon: keeps the entries of `mgr` in memory and shows the menu
+/// with `config` (plus the options of each request) whenever a client asks,
+/// until an error occurs. SIGUSR1 makes it parse all desktop entry files
+/// again. With the dbus feature, the D-Bus service is exported too.
+pub fn run<F>(mut mgr: XDGManager<F>, config: Config) -> io::Result<()>
+where
+    F: Fn(&str) -> Result<String, VarError> + Clone + Send + 'static
+{
+    mgr.keep_entries_in_memory();
+    // Read the entries before the first client waits for them
+    mgr.get_desktop_entry_map();
+    let socket_path = mgr.get_daemon_socket_path();
+    let mgr = Arc::new(Mutex::new(mgr));
+    // Package manager hooks can send SIGUSR1 after installing applications
+    let mut signals = Signals::new([SIGUSR1])?;
+    let reload_mgr = Arc::clone(&mgr);
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            reload_mgr.lock().unwrap_or_else(PoisonError::into_inner).reload();
+        }
+    });
+    #[cfg(feature = "dbus")]
+    let _connection = match super::dbus::start(Arc::clone(&mgr), config.clone()) {
+        Ok(connection) => Some(connection),
+        Err(err) => {
+            log_warn(&tr("Could not export the D-Bus service: {}", &[&err]));
+            None
+        },
+    };
+    serve(&socket_path, |request| {
+        // The menu is shown with a copy, so that reloading and the D-Bus
+        // service do not have to wait until it is closed
+        let mgr = mgr.lock().unwrap_or_else(PoisonError::into_inner).snapshot();
+        handle_request(&mgr, &config, request)
+    })
+}
+
+/// Listens on `socket_path` and answers the requests of clients with
+/// `handle`. A socket left behind by a daemon which is no longer running is
+/// replaced. Clients which cannot be accepted are skipped, so only setting
+/// up the socket can fail.
+pub fn serve(socket_path: &str, mut handle: impl FnMut(Request) -> Response) -> io::Result<()> {
+    if UnixStream::connect(socket_path).is_ok() {
+        return Err(io::Error::new(io::ErrorKind::AddrInUse, format!("a daemon is already listening on {}", socket_path)));
+    }
+    match fs::remove_file(socket_path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        _ => (),
+    }
+    if let Some(dir) = Path::new(socket_path).parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let listener = UnixListener::bind(socket_path)?;
+    for stream in listener.incoming() {
+        let mut stream = match stream.and_then(|stream| stream.set_read_timeout(Some(REQUEST_TIMEOUT)).map(|()| stream)) {
+            Ok(stream) => stream,
+            Err(err) => {
+                log_warn(&tr("Could not accept a client of the daemon: {}", &[&err]));
+                continue;
+            },
+        };
+        // A misbehaving client should not take the daemon down
+        let response = match read_message(&mut stream) {
+            Ok(request) => handle(request),
+            Err(err) => Response::error(format!("invalid request: {}", err)),
+        };
+        if let Err(err) = write_message(&mut stream, &response) {
+            log_warn(&tr("Could not answer a client of the daemon: {}", &[&err]));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages() {
+        let request = Request { version: PROTOCOL_VERSION, args: vec!["--provider".to_string(), "run".to_string()] };
+        let mut buf = Vec::new();
+        write_message(&mut buf, &request).unwrap();
+        let json = br#"{"version":1,"args":["--provider","run"]}"#;
+        assert_eq!(buf[..4], (json.len() as u32).to_be_bytes());
+        assert_eq!(buf[4..], json[..]);
+        assert_eq!(read_message::<Request>(&mut buf.as_slice()).unwrap(), request);
+
+        let json = br#"{"version": 1, "status": 2, "error": null}"#;
+        let buf = [&(json.len() as u32).to_be_bytes(), &json[..]].concat();
+        let response: Response = read_message(&mut buf.as_slice()).unwrap();
+        assert_eq!(response, Response { version: 1, status: 2, error: None });
+        // Truncated or oversized messages
+        assert!(read_message::<Response>(&mut b"\0\0\0\x10{}".as_slice()).is_err());
+        assert!(read_message::<Response>(&mut b"\x7f\0\0\0".as_slice()).is_err());
+    }
+}
@@ -0,0 +1,88 @@
+//! The D-Bus service which the daemon exports on the session bus, so that
+//! other desktop components can use its entries and launch them the same
+//! way as the menu does.
+//!
+//! The `org.i3dmenudesktop.Launcher` interface at
+//! `/org/i3dmenudesktop/Launcher` has these methods:
+//!
+//! * `ListEntries() -> a(ssss)`: the desktop file ID, name, comment and icon
+//!   of each entry which the menu lists, sorted by desktop file ID
+//! * `Launch(s id, as uris)`: launches the entry with the given desktop file
+//!   ID, opening the URIs (or paths) with it
+//! * `Reload()`: parses all desktop entry files again
+//!
+//! For example, to open a URL in Firefox:
+//!
+//! ```text
+//! busctl --user call org.i3dmenudesktop.Launcher /org/i3dmenudesktop/Launcher \
+//!     org.i3dmenudesktop.Launcher Launch sas firefox.desktop 1 https://example.com
+//! ```
+
+use std::env::VarError;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use zbus::blocking::connection::{Builder, Connection};
+use zbus::{fdo, interface};
+
+use super::config::Config;
+use super::XDGManager;
+
+pub const BUS_NAME: &str = "org.i3dmenudesktop.Launcher";
+pub const OBJECT_PATH: &str = "/org/i3dmenudesktop/Launcher";
+
+struct Launcher<F>
+where
+    F: Fn(&str) -> Result<String, VarError>
+{
+    mgr: Arc<Mutex<XDGManager<F>>>,
+    config: Config,
+}
+
+impl<F> Launcher<F>
+where
+    F: Fn(&str) -> Result<String, VarError>
+{
+    fn mgr(&self) -> MutexGuard<'_, XDGManager<F>> {
+        self.mgr.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[interface(name = "org.i3dmenudesktop.Launcher")]
+impl<F> Launcher<F>
+where
+    F: Fn(&str) -> Result<String, VarError> + Send + 'static
+{
+    fn list_entries(&self) -> Vec<(String, String, String, String)> {
+        self.mgr().list_menu_entries(&self.config)
+            .into_iter()
+            .map(|app| (
+                app.source.id,
+                app.keys.Name,
+                app.keys.Comment.unwrap_or_default(),
+                app.keys.Icon.unwrap_or_default(),
+            ))
+            .collect()
+    }
+
+    fn launch(&self, id: &str, uris: Vec<String>) -> fdo::Result<()> {
+        let uris: Vec<&str> = uris.iter().map(String::as_str).collect();
+        self.mgr().launch_by_id(id, &uris, &self.config).map_err(|err| fdo::Error::Failed(err.to_string()))
+    }
+
+    fn reload(&self) {
+        self.mgr().reload();
+    }
+}
+
+/// Exports the service on the session bus, using the entries of `mgr` and
+/// launching them with `config`. The service stops when the returned
+/// connection is dropped.
+pub fn start<F>(mgr: Arc<Mutex<XDGManager<F>>>, config: Config) -> zbus::Result<Connection>
+where
+    F: Fn(&str) -> Result<String, VarError> + Send + 'static
+{
+    Builder::session()?
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, Launcher { mgr, config })?
+        .build()
+}
@@ -1,17 +1,23 @@
-use std::fs::{self, File};
+// Field and variable names mirror the keys in the desktop entry spec.
+#![allow(non_snake_case)]
+
+use std::collections::HashMap;
+use std::fs::{File, Metadata};
 use std::fmt;
 use std::io::{self, BufRead};
-use std::os::unix::fs::PermissionsExt;
+use std::os::unix::fs::MetadataExt;
 use std::time::SystemTime;
 
 use lazy_static::lazy_static;
 use regex::Regex;
 use serde::{Serialize, Deserialize};
 
-use super::utils::join_path;
+use super::i18n::tr;
+use super::utils::{find_executable, log_warn};
 
-fn is_executable(path: &str) -> bool {
-    fs::metadata(path).map_or(false, |m| m.permissions().mode() & 0o111 == 0o111)
+/// Splits the value of a key of type "string(s)" into its elements.
+fn split_list(value: &str) -> Vec<String> {
+    value.split(';').filter(|s| !s.is_empty()).map(|s| s.to_string()).collect()
 }
 
 // Adapted from https://doc.rust-lang.org/std/convert/trait.From.html#examples
@@ -34,27 +40,267 @@ impl fmt::Display for DesktopEntryError {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct DesktopEntry {
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DesktopAction {
+    // See https://specifications.freedesktop.org/desktop-entry-spec/latest/extra-actions.html
+    #[serde(rename = "name")]
+    pub Name: String,
+    #[serde(rename = "exec")]
+    pub Exec: Option<String>,
+    #[serde(rename = "icon")]
+    pub Icon: Option<String>,
+    // This is the action identifier from the Actions key (not an actual key)
+    pub id: String,
+}
+
+// The keys of a [Desktop Action <id>] group, before we know whether they are valid
+#[derive(Default)]
+struct ActionGroup {
+    Name: Option<String>,
+    Exec: Option<String>,
+    Icon: Option<String>,
+    localized_values: HashMap<String, (usize, String)>,
+}
+
+/// Splits the value of an Exec key (after the general escape rule was
+/// applied) into arguments, following the quoting rules of
+/// https://specifications.freedesktop.org/desktop-entry-spec/latest/exec-variables.html.
+/// Backslashes outside of quotes and single quotes are not allowed by the
+/// spec, but are common enough that they are treated like sh would.
+fn split_exec_args(exec: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut arg: Option<String> = None;
+    let mut quote: Option<char> = None;
+    let mut chars = exec.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match (ch, quote) {
+            ('\\', Some('"')) => {
+                match chars.next_if(|next| matches!(next, '"' | '`' | '$' | '\\')) {
+                    Some(next) => arg.get_or_insert_with(String::new).push(next),
+                    None => arg.get_or_insert_with(String::new).push(ch),
+                }
+            },
+            ('\\', None) => {
+                if let Some(next) = chars.next() {
+                    arg.get_or_insert_with(String::new).push(next);
+                }
+            },
+            ('"' | '\'', None) => {
+                quote = Some(ch);
+                arg.get_or_insert_with(String::new);
+            },
+            (_, Some(q)) if ch == q => quote = None,
+            (_, None) if ch.is_whitespace() => args.extend(arg.take()),
+            _ => arg.get_or_insert_with(String::new).push(ch),
+        }
+    }
+    args.extend(arg);
+    args
+}
+
+/// Returns `arg` quoted for sh, which runs the expanded Exec value. Words
+/// which sh would not change are left as they are.
+pub(crate) fn quote_for_sh(arg: &str) -> String {
+    let is_safe = |ch: char| ch.is_ascii_alphanumeric() || "_-+=@%:,./".contains(ch);
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+/// The keys of the [Desktop Entry] group which we use.
+///
+/// The serialized form (see [`DesktopEntry::to_json`]) uses the snake_case
+/// names of the keys, e.g. "no_display" for NoDisplay, and is stable.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DesktopEntryKeys {
     // See https://specifications.freedesktop.org/desktop-entry-spec/latest/ar01s06.html
+    #[serde(rename = "name")]
     pub Name: String,
+    #[serde(rename = "generic_name")]
+    pub GenericName: Option<String>,
+    #[serde(rename = "exec")]
     pub Exec: Option<String>,
+    #[serde(rename = "try_exec")]
     pub TryExec: Option<String>,
+    #[serde(rename = "path")]
     pub Path: Option<String>,
+    #[serde(rename = "type")]
     pub Type: String,
+    #[serde(rename = "comment")]
+    pub Comment: Option<String>,
+    #[serde(rename = "icon")]
+    pub Icon: Option<String>,
+    #[serde(rename = "categories")]
+    pub Categories: Vec<String>,
+    #[serde(rename = "keywords")]
+    pub Keywords: Vec<String>,
+    #[serde(rename = "only_show_in")]
+    pub OnlyShowIn: Vec<String>,
+    #[serde(rename = "not_show_in")]
+    pub NotShowIn: Vec<String>,
     // These keys are optional, but we will provide defaults (see parse function)
+    #[serde(rename = "no_display")]
     pub NoDisplay: bool,
+    #[serde(rename = "hidden")]
     pub Hidden: bool,
-    pub StartupNotify: bool,
+    // None if the key is absent, which leaves it to the launcher (see
+    // Config::startup_notify_default)
+    #[serde(rename = "startup_notify")]
+    pub StartupNotify: Option<bool>,
+    #[serde(rename = "terminal")]
     pub Terminal: bool,
-    // This is the path of the desktop entry file (not an actual key)
+    #[serde(rename = "prefers_non_default_gpu")]
+    pub PrefersNonDefaultGPU: bool,
+    #[serde(rename = "single_main_window")]
+    pub SingleMainWindow: bool,
+    #[serde(rename = "startup_wm_class")]
+    pub StartupWMClass: Option<String>,
+    // These are the actions listed in the Actions key which have a valid group
+    #[serde(rename = "actions")]
+    pub Actions: Vec<DesktopAction>,
+}
+
+/// Where a desktop entry was read from.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EntrySource {
+    /// The path of the desktop entry file
     pub location: String,
-    // This is the mtime of the desktop entry file (not an actual key)
+    /// The desktop file ID, e.g. "org.mozilla.firefox.desktop"
+    pub id: String,
+    /// The mtime of the desktop entry file
     pub mtime: SystemTime,
+    /// The size of the desktop entry file in bytes
+    pub size: u64,
+    /// The inode number of the desktop entry file, which changes when it is
+    /// replaced by another file (e.g. by a package manager)
+    pub inode: u64,
+    /// The data directory which the file is in (or the directory of the
+    /// AppImage), empty if unknown
+    pub data_dir: String,
+    pub origin: Origin,
+}
+
+/// Where an entry was installed, as far as its data directory tells.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Origin {
+    /// $XDG_DATA_HOME, i.e. ~/.local/share
+    User,
+    /// Any other data directory, e.g. /usr/share
+    System,
+    Flatpak,
+    Snap,
+    AppImage,
+    /// The entry was not read from a data directory, e.g. with
+    /// [`DesktopEntry::parse`]
+    Unknown,
+}
+
+impl Origin {
+    /// Returns the origin of the entries in `data_dir`, where `data_home` is
+    /// $XDG_DATA_HOME.
+    pub fn for_data_dir(data_dir: &str, data_home: &str) -> Self {
+        let dir = data_dir.trim_end_matches('/');
+        if dir.ends_with("/flatpak/exports/share") {
+            Self::Flatpak
+        } else if dir == "/var/lib/snapd/desktop" {
+            Self::Snap
+        } else if dir == data_home.trim_end_matches('/') {
+            Self::User
+        } else {
+            Self::System
+        }
+    }
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::User => "User",
+            Self::System => "System",
+            Self::Flatpak => "Flatpak",
+            Self::Snap => "Snap",
+            Self::AppImage => "AppImage",
+            Self::Unknown => "Unknown",
+        })
+    }
+}
+
+impl EntrySource {
+    /// Returns whether the file with `metadata` is still the one which the
+    /// entry was read from. The mtime alone misses files which were rewritten
+    /// within its granularity, or which kept their mtime (e.g. with rsync -t).
+    pub fn matches(&self, metadata: &Metadata) -> bool {
+        metadata.modified().is_ok_and(|mtime| mtime == self.mtime)
+            && metadata.len() == self.size
+            && metadata.ino() == self.inode
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DesktopEntry {
+    pub keys: DesktopEntryKeys,
+    pub source: EntrySource,
+    // This is set if this entry was derived from one of the actions of
+    // another entry, see for_action
+    #[serde(skip)]
+    pub action: Option<DesktopAction>,
 }
 
 impl DesktopEntry {
     pub fn parse(filepath: &str, locale_keys: &[String]) -> Result<DesktopEntry, DesktopEntryError> {
+        Self::parse_impl(filepath, locale_keys, None)
+    }
+
+    /// Like [`DesktopEntry::parse`], but also returns all keys of the
+    /// [Desktop Entry] group with their values as they appear in the file,
+    /// including localized keys (e.g. "Name[de]") and vendor extensions
+    /// (e.g. "X-GNOME-UsesNotifications"). If a key appears more than once,
+    /// the last value is kept.
+    pub fn parse_with_extras(
+        filepath: &str,
+        locale_keys: &[String],
+    ) -> Result<(DesktopEntry, HashMap<String, String>), DesktopEntryError> {
+        let mut raw_keys = HashMap::new();
+        let entry = Self::parse_impl(filepath, locale_keys, Some(&mut raw_keys))?;
+        Ok((entry, raw_keys))
+    }
+
+    fn parse_impl(
+        filepath: &str,
+        locale_keys: &[String],
+        raw_keys: Option<&mut HashMap<String, String>>,
+    ) -> Result<DesktopEntry, DesktopEntryError> {
+        let file = File::open(filepath)?;
+        let metadata = file.metadata()?;
+        let mut entry = Self::parse_lines(io::BufReader::new(file).lines(), filepath, metadata.modified()?, locale_keys, raw_keys)?;
+        entry.source.size = metadata.len();
+        entry.source.inode = metadata.ino();
+        Ok(entry)
+    }
+
+    /// Parses the contents of the desktop entry file at `filepath` which
+    /// were already read, e.g. asynchronously. The file itself is not
+    /// accessed; `filepath` and `mtime` only end up in [`EntrySource`],
+    /// whose size and inode are left at zero for the caller to fill in.
+    pub fn parse_contents(
+        contents: &str,
+        filepath: &str,
+        mtime: SystemTime,
+        locale_keys: &[String],
+    ) -> Result<DesktopEntry, DesktopEntryError> {
+        Self::parse_lines(contents.lines().map(|line| Ok(line.to_string())), filepath, mtime, locale_keys, None)
+    }
+
+    fn parse_lines(
+        lines: impl Iterator<Item=io::Result<String>>,
+        filepath: &str,
+        mtime: SystemTime,
+        locale_keys: &[String],
+        mut raw_keys: Option<&mut HashMap<String, String>>,
+    ) -> Result<DesktopEntry, DesktopEntryError> {
         // Parsing logic is adapted from the original i3-dmenu-desktop script
         lazy_static! {
             // The 'x' flag enables insignificant whitespace mode
@@ -66,27 +312,39 @@ impl DesktopEntry {
                 \s* = \s*            # whitespace around '=' is ignored
                 (.*)                 # value
                 $").unwrap();
-            static ref LOCALIZED_NAME: Regex = Regex::new(r"^Name\[([^]]+)\]$").unwrap();
+            static ref LOCALIZED_KEY: Regex = Regex::new(r"^([A-Za-z0-9-]+)\[([^]]+)\]$").unwrap();
         }
         let mut Name: Option<String> = None;
+        let mut GenericName: Option<String> = None;
         let mut Exec: Option<String> = None;
         let mut TryExec: Option<String> = None;
         let mut Path: Option<String> = None;
         let mut Type: Option<String> = None;
+        let mut Comment: Option<String> = None;
+        let mut Icon: Option<String> = None;
+        let mut Categories: Vec<String> = Vec::new();
+        let mut Keywords: Vec<String> = Vec::new();
+        let mut OnlyShowIn: Vec<String> = Vec::new();
+        let mut NotShowIn: Vec<String> = Vec::new();
         // use sane defaults for these keys
         let mut NoDisplay = false;
         let mut Hidden = false;
-        let mut StartupNotify = true;
+        let mut StartupNotify = None;
         let mut Terminal = false;
+        let mut PrefersNonDefaultGPU = false;
+        let mut SingleMainWindow = false;
+        let mut StartupWMClass: Option<String> = None;
+        let mut Actions: Option<String> = None;
 
         let mut in_desktop_entry_section = false;
-        let mut localized_name: Option<String> = None;
+        // The ID of the [Desktop Action <id>] group we are in, if any
+        let mut current_action: Option<String> = None;
+        let mut action_groups: HashMap<String, ActionGroup> = HashMap::new();
+        // Maps keys to their best localized value so far, along with the
         // index into locale_keys (lower index = higher priority)
-        let mut localized_name_idx = 0;
+        let mut localized_values: HashMap<String, (usize, String)> = HashMap::new();
 
-        let file = File::open(filepath)?;
-        let mtime = file.metadata()?.modified()?;
-        for line in io::BufReader::new(file).lines() {
+        for line in lines {
             let line = line?;
             let line = line.trim();
             let first_char = match line.chars().next() {
@@ -95,9 +353,12 @@ impl DesktopEntry {
             };
             if first_char == '[' {
                 in_desktop_entry_section = line == "[Desktop Entry]";
+                current_action = line.strip_prefix("[Desktop Action ")
+                    .and_then(|s| s.strip_suffix(']'))
+                    .map(|s| s.to_string());
                 continue;
             }
-            if !in_desktop_entry_section {
+            if !in_desktop_entry_section && current_action.is_none() {
                 continue;
             }
             if first_char == '#' {
@@ -107,56 +368,164 @@ impl DesktopEntry {
                 Some(caps) => caps,
                 None => continue,
             };
-            let key = captures.get(1).unwrap().as_str();
-            let value = captures.get(2).unwrap().as_str();
-            if let Some(captures) = LOCALIZED_NAME.captures(key) {
-                let locale = captures.get(1).unwrap().as_str();
+            let key = &captures[1];
+            let value = &captures[2];
+            if let Some(raw_keys) = raw_keys.as_deref_mut().filter(|_| in_desktop_entry_section) {
+                raw_keys.insert(key.to_string(), value.to_string());
+            }
+            let action_group = current_action.as_ref()
+                .map(|id| action_groups.entry(id.clone()).or_default());
+            if let Some(captures) = LOCALIZED_KEY.captures(key) {
+                let key = &captures[1];
+                let locale = &captures[2];
+                let localized_values = match action_group {
+                    Some(group) => &mut group.localized_values,
+                    None => &mut localized_values,
+                };
                 // locale_keys is sorted from highest to lowest priority
                 if let Some(idx) = locale_keys.iter().position(|s| s == locale) {
-                    if localized_name.is_none() || idx < localized_name_idx {
-                        localized_name = Some(value.to_string());
-                        localized_name_idx = idx;
+                    if localized_values.get(key).is_none_or(|(old_idx, _)| idx < *old_idx) {
+                        localized_values.insert(key.to_string(), (idx, value.to_string()));
                     }
                 }
                 continue;
             }
+            if let Some(group) = action_group {
+                match key {
+                    "Name" => group.Name = Some(value.to_string()),
+                    "Exec" => group.Exec = Some(value.to_string()),
+                    "Icon" => group.Icon = Some(value.to_string()),
+                    _ => (),
+                }
+                continue;
+            }
             match key {
                 "Name" => Name = Some(value.to_string()),
+                "GenericName" => GenericName = Some(value.to_string()),
                 "Exec" => Exec = Some(value.to_string()),
                 "TryExec" => TryExec = Some(value.to_string()),
                 "Path" => Path = Some(value.to_string()),
                 "Type" => Type = Some(value.to_string()),
+                "Comment" => Comment = Some(value.to_string()),
+                "Icon" => Icon = Some(value.to_string()),
+                "Categories" => Categories = split_list(value),
+                "Keywords" => Keywords = split_list(value),
+                "OnlyShowIn" => OnlyShowIn = split_list(value),
+                "NotShowIn" => NotShowIn = split_list(value),
                 "NoDisplay" => NoDisplay = value == "true",
                 "Hidden" => Hidden = value == "true",
-                "StartupNotify" => StartupNotify = value == "true",
+                "StartupNotify" => StartupNotify = Some(value == "true"),
                 "Terminal" => Terminal = value == "true",
+                "PrefersNonDefaultGPU" => PrefersNonDefaultGPU = value == "true",
+                "SingleMainWindow" => SingleMainWindow = value == "true",
+                "StartupWMClass" => StartupWMClass = Some(value.to_string()),
+                "Actions" => Actions = Some(value.to_string()),
                 _ => (),
             }
         }
-        // Localized name takes priority over default name
-        if localized_name.is_some() {
-            Name = localized_name;
+        // Localized values take priority over default values
+        if let Some((_, value)) = localized_values.remove("Name") {
+            Name = Some(value);
         }
-        if Type.is_none() {
-            Err(DesktopEntryError::ParseError("missing Type key".to_string()))
-        } else if Name.is_none() {
-            Err(DesktopEntryError::ParseError("missing Name key".to_string()))
-        } else if Exec.is_none() && Type.as_ref().unwrap() == "Application" {
-            Err(DesktopEntryError::ParseError("missing Exec key".to_string()))
-        } else {
-            Ok(DesktopEntry{
-                Name: Name.unwrap(),
+        if let Some((_, value)) = localized_values.remove("GenericName") {
+            GenericName = Some(value);
+        }
+        if let Some((_, value)) = localized_values.remove("Comment") {
+            Comment = Some(value);
+        }
+        if let Some((_, value)) = localized_values.remove("Keywords") {
+            Keywords = split_list(&value);
+        }
+        // Actions which are not listed in the Actions key, or which are
+        // missing a Name, are ignored
+        let Actions = Actions.as_deref().unwrap_or("")
+            .split(';')
+            .filter_map(|id| {
+                let mut group = action_groups.remove(id)?;
+                let Name = match group.localized_values.remove("Name") {
+                    Some((_, value)) => value,
+                    None => group.Name?,
+                };
+                Some(DesktopAction { Name, Exec: group.Exec, Icon: group.Icon, id: id.to_string() })
+            })
+            .collect();
+        let Type = match Type {
+            Some(val) => val,
+            None => return Err(DesktopEntryError::ParseError("missing Type key".to_string())),
+        };
+        let Name = match Name {
+            Some(val) => val,
+            None => return Err(DesktopEntryError::ParseError("missing Name key".to_string())),
+        };
+        if Exec.is_none() && Type == "Application" {
+            return Err(DesktopEntryError::ParseError("missing Exec key".to_string()));
+        }
+        Ok(DesktopEntry {
+            keys: DesktopEntryKeys {
+                Name,
+                GenericName,
                 Exec,
                 TryExec,
                 Path,
-                Type: Type.unwrap(),
+                Type,
+                Comment,
+                Icon,
+                Categories,
+                Keywords,
+                OnlyShowIn,
+                NotShowIn,
                 NoDisplay,
                 Hidden,
                 StartupNotify,
                 Terminal,
+                PrefersNonDefaultGPU,
+                SingleMainWindow,
+                StartupWMClass,
+                Actions,
+            },
+            source: EntrySource {
                 location: filepath.to_string(),
+                // The caller prefixes the ID of entries in subdirectories
+                id: filepath.rsplit('/').next().unwrap_or(filepath).to_string(),
                 mtime,
-            })
+                size: 0,
+                inode: 0,
+                data_dir: String::new(),
+                origin: Origin::Unknown,
+            },
+            action: None,
+        })
+    }
+
+    /// Returns this entry as a single line of JSON, e.g.
+    /// `{"keys":{"name":"Firefox",...},"source":{"location":...}}`.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("desktop entries have no maps with non-string keys")
+    }
+
+    /// Whether this entry should be shown in a session whose
+    /// XDG_CURRENT_DESKTOP contains `desktops`, according to the OnlyShowIn
+    /// and NotShowIn keys.
+    pub fn is_shown_in(&self, desktops: &[String]) -> bool {
+        if !self.keys.OnlyShowIn.is_empty() {
+            return desktops.iter().any(|desktop| self.keys.OnlyShowIn.contains(desktop));
+        }
+        !desktops.iter().any(|desktop| self.keys.NotShowIn.contains(desktop))
+    }
+
+    /// Returns a copy of this entry which runs `action` instead of the
+    /// main Exec command.
+    pub fn for_action(&self, action: &DesktopAction) -> DesktopEntry {
+        DesktopEntry {
+            keys: DesktopEntryKeys {
+                Exec: action.Exec.clone(),
+                TryExec: None,
+                Icon: action.Icon.clone().or_else(|| self.keys.Icon.clone()),
+                Actions: Vec::new(),
+                ..self.keys.clone()
+            },
+            source: self.source.clone(),
+            action: Some(action.clone()),
         }
     }
 
@@ -186,79 +555,154 @@ impl DesktopEntry {
                 i += 1;
             }
         }
-        // This says that we need to unescape the double quote, backtick and
-        // dollar sign characters:
+        // The double quote, backtick and dollar sign characters are unescaped
+        // later, together with the quotes, by split_exec_args:
         // https://specifications.freedesktop.org/desktop-entry-spec/latest/ar01s07.html
-        // However, because i3 will pass the arguments to sh -c, we will skip this step
-        // because sh will unescape them for us.
         String::from_iter(new_chars)
     }
 
     pub fn escape_chars_for_exec_keys(&mut self) {
-        if let Some(ref cmd) = self.TryExec {
-            self.TryExec = Some(Self::escape_chars(cmd));
+        if let Some(ref cmd) = self.keys.TryExec {
+            self.keys.TryExec = Some(Self::escape_chars(cmd));
+        }
+        if let Some(ref cmd) = self.keys.Exec {
+            self.keys.Exec = Some(Self::escape_chars(cmd));
+        }
+        for action in &mut self.keys.Actions {
+            if let Some(ref cmd) = action.Exec {
+                action.Exec = Some(Self::escape_chars(cmd));
+            }
         }
-        if let Some(ref cmd) = self.Exec {
-            self.Exec = Some(Self::escape_chars(cmd));
+    }
+
+    /// Returns the arguments which the field code `code` (e.g. "%U")
+    /// expands to.
+    fn expand_field_code(&self, code: &str, extra_args: &[&str]) -> Vec<String> {
+        match code {
+            "%f" | "%u" => extra_args.iter().take(1).map(|arg| arg.to_string()).collect(),
+            "%F" | "%U" => extra_args.iter().map(|arg| arg.to_string()).collect(),
+            "%i" => match self.keys.Icon {
+                Some(ref icon) if !icon.is_empty() => vec!["--icon".to_string(), icon.clone()],
+                _ => Vec::new(),
+            },
+            "%c" => vec![self.keys.Name.clone()],
+            "%k" => vec![self.source.location.clone()],
+            "%%" => vec!["%".to_string()],
+            // deprecated, or invalid
+            _ => Vec::new(),
         }
     }
 
+    /// Expands the field codes in `exec_str` with the files or URLs in
+    /// `extra_args`, and returns the result as a command line for sh, with
+    /// each argument quoted as needed.
+    ///
+    /// Field codes are only expanded when they are arguments on their own.
+    /// Ones embedded in a longer argument (e.g. `--url=%u`) are removed, with
+    /// a warning, since the spec does not allow them there. Other percent
+    /// signs, like the ones of percent-encoded URLs, are left as they are.
     pub fn replace_field_codes(&self, exec_str: &str, extra_args: &[&str]) -> String {
         lazy_static! {
-            static ref FIELD_CODE: Regex = Regex::new("%[fFuUdDnNickvm]").unwrap();
+            static ref FIELD_CODE: Regex = Regex::new("%[fFuUdDnNickvm%]").unwrap();
+        }
+        let mut args = Vec::new();
+        for arg in split_exec_args(exec_str) {
+            if arg.len() == 2 && FIELD_CODE.is_match(&arg) {
+                // A field code on its own can expand to any number of arguments
+                args.extend(self.expand_field_code(&arg, extra_args));
+                continue;
+            }
+            let mut removed = false;
+            let new_arg = FIELD_CODE.replace_all(&arg, |caps: &regex::Captures| {
+                if &caps[0] == "%%" {
+                    return "%";
+                }
+                removed = true;
+                ""
+            });
+            if removed {
+                log_warn(&tr("{}: removing the field codes inside of the argument {}",
+                              &[&self.source.location, &format!("{:?}", arg)]));
+            }
+            args.push(new_arg.into_owned());
         }
-        let first_arg = extra_args.first().copied().unwrap_or("");
-        let all_args = &extra_args.join(" ");
-        FIELD_CODE.replace_all(exec_str, |caps: &regex::Captures| match &caps[0] {
-            "%f" => first_arg,
-            "%F" => all_args,
-            "%u" => first_arg,
-            "%U" => all_args,
-            "%i" => "",  // icon - not supported for now
-            "%c" => &self.Name,
-            "%k" => &self.location,
-            "%d" | "%D" | "%n" | "%N" | "%v" | "%m" => "",  // deprecated
-            "%%" => "%",
-            _ => "",
-        }).into_owned()
-    }
-
-    fn get_arg0(exec_str: &str) -> String {
+        args.iter().map(|arg| quote_for_sh(arg)).collect::<Vec<_>>().join(" ")
+    }
+
+    pub(crate) fn get_arg0(exec_str: &str) -> String {
         lazy_static! {
             static ref NONQUOTED_ARG0: Regex = Regex::new(r#"^([^"]+)(?:\s|$)"#).unwrap();
             static ref QUOTED_ARG0: Regex = Regex::new(r#"^"([^"]+)"(?:\s|$)"#).unwrap();
         }
         if let Some(captures) = NONQUOTED_ARG0.captures(exec_str) {
-            captures.get(1).unwrap().as_str().to_string()
+            captures[1].to_string()
         } else if let Some(captures) = QUOTED_ARG0.captures(exec_str) {
-            captures.get(1).unwrap().as_str().to_string()
+            captures[1].to_string()
         } else {
             // invalid quoting - return the whole string
             exec_str.to_string()
         }
     }
 
-    pub fn remove_invalid_tryexec(&mut self, env_paths: &[String]) {
-        let try_exec = match self.TryExec {
-            Some(ref val) => val,
-            None => return,
-        };
-        let arg0 = Self::get_arg0(try_exec);
-        let try_exec_is_valid = if arg0.contains('/') {
-            is_executable(&arg0)
-        } else {
-            env_paths.iter().any(|path| is_executable(&join_path(path, &arg0)))
+    /// Returns false if the entry has a TryExec key whose program is not
+    /// installed, in which case the spec says that the entry is ignored.
+    pub fn try_exec_is_installed(&self, env_paths: &[String]) -> bool {
+        match self.keys.TryExec {
+            Some(ref try_exec) => find_executable(&Self::get_arg0(try_exec), env_paths),
+            None => true,
+        }
+    }
+
+    /// Returns true if this entry was generated by Wine for a Windows
+    /// program, i.e. its Exec runs wine (often through
+    /// `env WINEPREFIX=...`), or it was installed to the wine subdirectory.
+    pub fn is_wine(&self) -> bool {
+        if self.source.id.starts_with("wine-") {
+            return true;
+        }
+        let Some(ref exec) = self.keys.Exec else {
+            return false;
         };
-        if !try_exec_is_valid {
-            self.TryExec = None;
+        let program = self.get_program_name().unwrap_or_default();
+        program.starts_with("wine") || exec.contains("WINEPREFIX=")
+    }
+
+    /// Returns the program which Exec runs, as it is written there (e.g.
+    /// "/usr/bin/nvim" for "/usr/bin/nvim %F"), looking past `env`, its
+    /// options and the environment variables which it sets.
+    pub fn get_program(&self) -> Option<String> {
+        let args = split_exec_args(self.keys.Exec.as_deref()?);
+        let mut args = args.into_iter();
+        let mut arg0 = args.next()?;
+        if arg0 == "env" || arg0.ends_with("/env") {
+            arg0 = loop {
+                let arg = args.next()?;
+                match arg.as_str() {
+                    // The options whose operand is a separate argument
+                    "-u" | "--unset" | "-C" | "--chdir" => {
+                        args.next()?;
+                    },
+                    _ if arg.contains('=') || arg.starts_with('-') => (),
+                    _ => break arg,
+                }
+            };
         }
+        Some(arg0)
+    }
+
+    /// Returns the file name of the program which Exec runs (e.g. "nvim"
+    /// for "/usr/bin/nvim %F"), see [`DesktopEntry::get_program`].
+    pub fn get_program_name(&self) -> Option<String> {
+        let program = self.get_program()?;
+        program.rsplit('/').next().filter(|name| !name.is_empty()).map(String::from)
     }
 
+    /// Returns the command line which launches this entry. TryExec is only
+    /// checked for existence, as the spec says; it often lacks arguments
+    /// which Exec has.
     pub fn get_exec_str(&self) -> &str {
-        match self.TryExec {
-            Some(ref val) => val,
-            None => self.Exec.as_ref().unwrap(),
-        }
+        // Only entries of Type=Application are required to have an Exec key
+        self.keys.Exec.as_deref().unwrap_or_default()
     }
 }
 
@@ -266,6 +710,172 @@ impl DesktopEntry {
 mod tests {
     use super::*;
 
+    fn parse_str(contents: &str, locale_keys: &[String]) -> DesktopEntry {
+        DesktopEntry::parse_contents(contents, "test.desktop", SystemTime::UNIX_EPOCH, locale_keys).unwrap()
+    }
+
+    #[test]
+    fn test_parse_actions() {
+        let app = parse_str("[Desktop Entry]
+Type=Application
+Name=Firefox
+Exec=firefox %u
+Actions=new-window;new-private-window;missing;
+
+[Desktop Action new-window]
+Name=New Window
+Name[de]=Neues Fenster
+Exec=firefox --new-window %u
+
+[Desktop Action new-private-window]
+Name=New Private Window
+Exec=firefox --private-window %u
+
+[Desktop Action not-listed]
+Name=Not Listed
+Exec=firefox
+", &["de".to_string()]);
+        let actions: Vec<_> = app.keys.Actions.iter().map(|a| (a.id.as_str(), a.Name.as_str())).collect();
+        assert_eq!(actions, [("new-window", "Neues Fenster"), ("new-private-window", "New Private Window")]);
+        assert_eq!(app.keys.Exec.as_deref(), Some("firefox %u"));
+        let action_app = app.for_action(&app.keys.Actions[1]);
+        assert_eq!(action_app.get_exec_str(), "firefox --private-window %u");
+    }
+
+    #[test]
+    fn test_parse_directory() {
+        let dir = parse_str("[Desktop Entry]
+Type=Directory
+Name=Development
+Name[de]=Entwicklung
+Icon=applications-development
+", &["de".to_string()]);
+        assert_eq!(dir.keys.Name, "Entwicklung");
+        assert_eq!(dir.keys.Icon.as_deref(), Some("applications-development"));
+        assert!(dir.keys.Exec.is_none());
+    }
+
+    #[test]
+    fn test_is_shown_in() {
+        let app = parse_str("[Desktop Entry]
+Type=Application
+Name=Applet
+Exec=applet
+OnlyShowIn=GNOME;KDE;
+", &[]);
+        assert!(app.is_shown_in(&["ubuntu".to_string(), "GNOME".to_string()]));
+        assert!(!app.is_shown_in(&["i3".to_string()]));
+        assert!(!app.is_shown_in(&[]));
+        let app = parse_str("[Desktop Entry]
+Type=Application
+Name=Applet
+Exec=applet
+NotShowIn=GNOME;
+", &[]);
+        assert!(app.is_shown_in(&["i3".to_string()]));
+        assert!(!app.is_shown_in(&["GNOME".to_string()]));
+    }
+
+    #[test]
+    fn test_get_program_name() {
+        let program_name = |exec: &str| parse_str(
+            &format!("[Desktop Entry]\nType=Application\nName=Foo\nExec={exec}\n"), &[]).get_program_name();
+        assert_eq!(program_name("/usr/bin/nvim %F").as_deref(), Some("nvim"));
+        assert_eq!(program_name("env GDK_BACKEND=x11 firefox %u").as_deref(), Some("firefox"));
+        assert_eq!(program_name(r#""/opt/My App/app" --new"#).as_deref(), Some("app"));
+        assert_eq!(program_name("env FOO=1").as_deref(), None);
+        let program = |exec: &str| parse_str(
+            &format!("[Desktop Entry]\nType=Application\nName=Foo\nExec={exec}\n"), &[]).get_program();
+        assert_eq!(program("env GDK_BACKEND=x11 /usr/bin/nvim %F").as_deref(), Some("/usr/bin/nvim"));
+        assert_eq!(program("env -u WAYLAND_DISPLAY firefox %u").as_deref(), Some("firefox"));
+        assert_eq!(program("/usr/bin/env --chdir /tmp -i --unset=A B=1 foo").as_deref(), Some("foo"));
+        assert_eq!(program("env -u").as_deref(), None);
+        assert_eq!(parse_str("[Desktop Entry]\nType=Link\nName=Foo\nURL=https://example.com\n", &[]).get_program_name(), None);
+    }
+
+    #[test]
+    fn test_is_wine() {
+        let exec_is_wine = |exec: &str| parse_str(
+            &format!("[Desktop Entry]\nType=Application\nName=Foo\nExec={exec}\n"), &[]).is_wine();
+        assert!(exec_is_wine(r#"env WINEPREFIX="/home/max/.wine" wine C:\\\\windows\\\\notepad.exe"#));
+        assert!(exec_is_wine("/usr/bin/wine64 foo.exe"));
+        assert!(!exec_is_wine("env GDK_BACKEND=x11 firefox %u"));
+        assert!(!exec_is_wine("steam steam://rungameid/570"));
+    }
+
+    #[test]
+    fn test_parse_contents_malformed() {
+        let parse = |contents| DesktopEntry::parse_contents(contents, "/a/b.desktop", SystemTime::UNIX_EPOCH, &[]);
+        assert!(parse("[Desktop Entry]\nName[=x\n=\n[\n").is_err());
+        // Links do not need an Exec key
+        let app = parse("[Desktop Entry]\nType=Link\nName=Link\nURL=https://example.com\n").unwrap();
+        assert_eq!(app.get_exec_str(), "");
+        assert_eq!(app.replace_field_codes(app.get_exec_str(), &["a"]), "");
+    }
+
+    #[test]
+    fn test_to_json() {
+        let path = "/usr/share/applications/foo.desktop";
+        let app = DesktopEntry::parse_contents("[Desktop Entry]\nType=Application\nName=Foo\nExec=foo\nStartupWMClass=Foo\n",
+                                               path, SystemTime::UNIX_EPOCH, &[]).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&app.to_json()).unwrap();
+        assert_eq!(json["keys"]["name"], "Foo");
+        assert_eq!(json["keys"]["no_display"], false);
+        assert_eq!(json["keys"]["startup_wm_class"], "Foo");
+        assert_eq!(json["source"]["location"], path);
+        assert!(json["source"]["id"].as_str().unwrap().ends_with(".desktop"));
+    }
+
+    #[test]
+    fn test_parse_with_extras() {
+        let dir = std::env::temp_dir().join(format!("i3-dmenu-desktop-rs-extras-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("foo.desktop");
+        std::fs::write(&path, "[Desktop Entry]\nType=Application\nName=Foo\nName[de]=Fu\nExec=foo\n\
+                               X-KDE-Protocols=http;https;\nActions=new;\n\
+                               [Desktop Action new]\nName=New\nExec=foo --new\n").unwrap();
+        let (app, raw_keys) = DesktopEntry::parse_with_extras(path.to_str().unwrap(), &[]).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(app.keys.Name, "Foo");
+        assert_eq!(raw_keys.len(), 6);
+        assert_eq!(raw_keys["Name[de]"], "Fu");
+        assert_eq!(raw_keys["X-KDE-Protocols"], "http;https;");
+        assert_eq!(raw_keys["Exec"], "foo");
+    }
+
+    #[test]
+    fn test_replace_icon_field_code() {
+        let mut app = parse_str("[Desktop Entry]\nType=Application\nName=Foo\nExec=foo %i %u\nIcon=it's\n", &[]);
+        assert_eq!(app.replace_field_codes(app.get_exec_str(), &["a.txt"]), r"foo --icon 'it'\''s' a.txt");
+        app.keys.Icon = None;
+        assert_eq!(app.replace_field_codes(app.get_exec_str(), &[]), "foo");
+    }
+
+    #[test]
+    fn test_replace_field_codes() {
+        let app = parse_str("[Desktop Entry]\nType=Application\nName=My App\nExec=foo\n", &[]);
+        assert_eq!(app.replace_field_codes("foo %%U 100%%", &["a"]), "foo %U 100%");
+        assert_eq!(app.replace_field_codes("foo --name %c", &[]), "foo --name 'My App'");
+        assert_eq!(app.replace_field_codes(r#""/opt/my app/run" %F"#, &["a b", "c"]), "'/opt/my app/run' 'a b' c");
+        assert_eq!(app.replace_field_codes(r#"sh -c "echo \"\$HOME\"" %u"#, &[]), r#"sh -c 'echo "$HOME"'"#);
+        assert_eq!(app.replace_field_codes("foo %f", &["it's"]), r"foo 'it'\''s'");
+        // Field codes inside of an argument are removed, but the other percent
+        // signs are left alone
+        assert_eq!(app.replace_field_codes("xdg-open https://example.com/%%u%uFFFF --file=%f", &["a"]),
+                   "xdg-open https://example.com/%uFFFF --file=");
+        assert_eq!(app.replace_field_codes("foo --url=%u%U", &["a"]), "foo --url=");
+        assert_eq!(app.replace_field_codes("xdg-open https://example.com/caf%C3%A9 %X", &["a"]),
+                   "xdg-open https://example.com/caf%C3%A9 %X");
+
+        // The location, the name and each file are one argument each, however
+        // many spaces and quotes they contain
+        let contents = "[Desktop Entry]\nType=Application\nName=Bob's \"App\"\nExec=foo\n";
+        let app = DesktopEntry::parse_contents(contents, "/home/me/My Apps/bob's app.desktop", SystemTime::UNIX_EPOCH, &[])
+            .unwrap();
+        assert_eq!(app.replace_field_codes("foo %k %c %F", &["a; b", "$c"]),
+                   r#"foo '/home/me/My Apps/bob'\''s app.desktop' 'Bob'\''s "App"' 'a; b' '$c'"#);
+    }
+
     #[test]
     fn test_general_escape_rule() {
         assert_eq!(DesktopEntry::escape_chars(r"a\nb"), "a\nb");
@@ -1,72 +1,355 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::fs;
+use std::fs::{self, File};
+use std::hash::Hasher;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
 use serde::{Serialize, Deserialize};
 
 use super::DesktopEntry;
-use super::utils::{join_path, log_warn};
+use super::i18n::tr;
+use super::profile::CacheStats;
+use super::utils::{join_path, log_warn, Fnv1aHasher};
 
-const CACHE_VERSION: u32 = 1;
-const CACHE_FILE_NAME: &str = "i3-dmenu-desktop-rs.bincode";
+pub const CACHE_VERSION: u32 = 20;
+
+/// How the contents which follow the header are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum Compression {
+    None,
+    Zstd,
+}
+
+/// Builds with the zstd feature compress the cache, but all builds read
+/// both formats, or at least recognize the one which they cannot read.
+const COMPRESSION: Compression = if cfg!(feature = "zstd") { Compression::Zstd } else { Compression::None };
+
+#[derive(Serialize, Deserialize)]
+struct CacheHeader {
+    version: u32,
+    compression: Compression,
+}
 
 // There is a more concise way to do this using Cow:
 // https://stackoverflow.com/a/52733564
 // However, using two structs is easier to understand.
 
 #[derive(Serialize)]
-struct VersionedCacheForSerialize<'a> {
-    version: u32,
+struct CacheContentsForSerialize<'a> {
     data: Vec<&'a DesktopEntry>,
+    dirs: &'a CachedDirs,
+    locale_keys: &'a [String],
+    warned: &'a WarnedFiles,
 }
 
 #[derive(Deserialize)]
-struct VersionedCacheForDeserialize {
-    version: u32,
+struct CacheContentsForDeserialize {
     data: Vec<DesktopEntry>,
+    dirs: CachedDirs,
+    locale_keys: Vec<String>,
+    warned: WarnedFiles,
+}
+
+/// The paths and mtimes of an applications directory and all of its
+/// subdirectories, sorted by path.
+pub type DirMtimes = Vec<(String, SystemTime)>;
+
+/// The directory mtimes of applications directories, by path.
+pub type CachedDirs = HashMap<String, DirMtimes>;
+
+/// The mtimes of the files which could not be parsed, by path, as of when
+/// this was warned about.
+pub type WarnedFiles = HashMap<String, SystemTime>;
+
+/// Directories modified less than this long ago are not recorded, since a
+/// file could still be added within the same mtime granularity after they
+/// were read, without changing their mtime.
+const DIR_MTIME_MIN_AGE: Duration = Duration::from_secs(2);
+
+/// The contents of the cache.
+#[derive(Debug, Default, Clone)]
+pub struct Cache {
+    /// The cached entries, by the path of their file
+    pub entries: HashMap<String, DesktopEntry>,
+    /// The directory mtimes of each applications directory (by path) whose
+    /// files were all cached. As long as these do not change, no file was
+    /// added to or removed from the directory, so it does not have to be
+    /// read again.
+    pub dirs: CachedDirs,
+    /// The locales whose localized values the entries were parsed with
+    pub locale_keys: Vec<String>,
+    /// The files which could not be parsed. They are only warned about
+    /// again once they are modified.
+    pub warned: WarnedFiles,
 }
 
-/// Returns a map of absolute file paths to XDG desktop entries.
+/// Returns the mtimes of `dirs` (an applications directory and its
+/// subdirectories), or `None` if one of them cannot be read or was modified
+/// too recently to be trusted.
+pub fn get_dir_mtimes(dirs: &[PathBuf]) -> Option<DirMtimes> {
+    let now = SystemTime::now();
+    let mut mtimes = dirs.iter()
+        .map(|dir| {
+            let mtime = dir.metadata().and_then(|metadata| metadata.modified()).ok()?;
+            if !now.duration_since(mtime).is_ok_and(|age| age >= DIR_MTIME_MIN_AGE) {
+                return None;
+            }
+            Some((dir.to_str()?.to_string(), mtime))
+        })
+        .collect::<Option<DirMtimes>>()?;
+    mtimes.sort();
+    Some(mtimes)
+}
+
+/// Returns whether none of the directories were modified since their
+/// mtimes were recorded.
+pub fn dirs_unchanged(mtimes: &DirMtimes) -> bool {
+    mtimes.iter().all(|(dir, mtime)| {
+        fs::metadata(dir).and_then(|metadata| metadata.modified()).is_ok_and(|current| current == *mtime)
+    })
+}
+
+/// Returns a hash of the data directories (and the hostname, if given),
+/// which the cache file can be named after.
+pub fn fingerprint(data_dirs: &[String], hostname: Option<&str>) -> u64 {
+    let mut hasher = Fnv1aHasher::default();
+    // Neither can contain NUL bytes
+    for dir in data_dirs {
+        hasher.write(dir.as_bytes());
+        hasher.write_u8(0);
+    }
+    if let Some(hostname) = hostname {
+        hasher.write_u8(1);
+        hasher.write(hostname.as_bytes());
+    }
+    hasher.finish()
+}
+
+/// Removes the cache files in `cache_dir` other than `file_path` which were
+/// named after a fingerprint and have an older format version. They are
+/// left behind whenever the name of the cache file changes, e.g. because the
+/// data directories did, and are never read again.
+fn remove_stale_cache_files(cache_dir: &Path, file_path: &str) {
+    let Ok(files) = fs::read_dir(cache_dir) else {
+        return;
+    };
+    for path in files.flatten().map(|file| file.path()) {
+        let is_cache_file = path.file_name().and_then(|name| name.to_str()).is_some_and(|name| {
+            name.starts_with("i3-dmenu-desktop-rs-") && name.ends_with(".bincode")
+        });
+        if !is_cache_file || path.to_str() == Some(file_path) {
+            continue;
+        }
+        let mut version = Vec::new();
+        let is_stale = File::open(&path).and_then(|file| file.take(4).read_to_end(&mut version)).is_ok()
+            && peek_version(&version).is_some_and(|version| version < CACHE_VERSION);
+        if is_stale {
+            let _ = fs::remove_file(&path);
+        }
+    }
+}
+
+/// Returns the path of the cache file, which includes the fingerprint if
+/// there is one.
+pub fn get_cache_file_path(cache_dir: &str, fingerprint: Option<u64>) -> String {
+    match fingerprint {
+        Some(fingerprint) => join_path(cache_dir, &format!("i3-dmenu-desktop-rs-{:016x}.bincode", fingerprint)),
+        None => join_path(cache_dir, "i3-dmenu-desktop-rs.bincode"),
+    }
+}
+
+/// Reads the cache file, returning its format version along with all of
+/// the entries and directory mtimes in it (even if the version is
+/// outdated). Returns `Ok(None)` if there is no cache file.
+pub fn read_cache_file(file_path: &str) -> Result<Option<(u32, Vec<DesktopEntry>, CachedDirs)>, String> {
+    match read_cache_contents(file_path)? {
+        Some(contents) => parse_cache_contents(file_path, &contents)
+            .map(|(version, cache)| Some((version, cache.data, cache.dirs))),
+        None => Ok(None),
+    }
+}
+
+fn read_cache_contents(file_path: &str) -> Result<Option<Vec<u8>>, String> {
+    match fs::read(file_path) {
+        Ok(data) => Ok(Some(data)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(tr("could not read {}: {}", &[&file_path, &err])),
+    }
+}
+
+/// The version comes first, so it can be read even if the layout of the
+/// file has changed since it was written.
+fn peek_version(contents: &[u8]) -> Option<u32> {
+    bincode::deserialize(contents).ok()
+}
+
+fn parse_cache_contents(file_path: &str, contents: &[u8]) -> Result<(u32, CacheContentsForDeserialize), String> {
+    decode_cache(contents).map_err(|reason| match peek_version(contents) {
+        Some(version) if version != CACHE_VERSION => tr(
+            "could not deserialize {} (format version {}, expected {})", &[&file_path, &version, &CACHE_VERSION]),
+        _ => tr("could not deserialize {}: {}", &[&file_path, &reason]),
+    })
+}
+
+fn decode_cache(contents: &[u8]) -> Result<(u32, CacheContentsForDeserialize), String> {
+    let mut rest = contents;
+    let header: CacheHeader = bincode::deserialize_from(&mut rest).map_err(|err| err.to_string())?;
+    let rest: Cow<[u8]> = match header.compression {
+        Compression::None => Cow::Borrowed(rest),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Cow::Owned(zstd::decode_all(rest).map_err(|err| err.to_string())?),
+        #[cfg(not(feature = "zstd"))]
+        Compression::Zstd => return Err("it is compressed with zstd, which this build does not support".to_string()),
+    };
+    let cache: CacheContentsForDeserialize = bincode::deserialize(&rest).map_err(|err| err.to_string())?;
+    Ok((header.version, cache))
+}
+
+/// Returns the cached entries, by their absolute file paths, and the
+/// directory mtimes, along with how much was read. Only the stale entries
+/// are left for the caller to count.
 ///
 /// # Arguments
 ///
-/// * `cache_dir`: the $XDG_CACHE_HOME directory from which the cache file
-///   will be read
-pub fn get_cached_desktop_entries(cache_dir: &str) -> HashMap<String, DesktopEntry> {
-    let mut apps = HashMap::new();
-    let file_path = join_path(cache_dir, CACHE_FILE_NAME);
-    let contents = match fs::read(&file_path) {
-        Ok(data) => data,
-        Err(_) => return apps,
+/// * `file_path`: the cache file (see [`get_cache_file_path`])
+pub fn get_cached_desktop_entries(file_path: &str) -> (Cache, CacheStats) {
+    let mut cache = Cache::default();
+    let mut stats = CacheStats::default();
+    let contents = match read_cache_contents(file_path) {
+        Ok(Some(contents)) => contents,
+        Ok(None) => return (cache, stats),
+        Err(msg) => {
+            log_warn(&msg);
+            return (cache, stats);
+        },
     };
-    let cache: VersionedCacheForDeserialize = match bincode::deserialize(&contents) {
-        Ok(data) => data,
-        Err(_) => {
-            log_warn(&format!("could not deserialize {}", &file_path));
-            return apps;
+    stats.bytes_read = contents.len() as u64;
+    if peek_version(&contents).is_some_and(|version| version != CACHE_VERSION) {
+        stats.version_mismatches += 1;
+    }
+    let (version, contents) = match parse_cache_contents(file_path, &contents) {
+        Ok(cache) => cache,
+        Err(msg) => {
+            log_warn(&msg);
+            return (cache, stats);
         },
     };
-    if cache.version != CACHE_VERSION {
-        return apps;
+    if version != CACHE_VERSION {
+        return (cache, stats);
     }
-    for desktop_entry in cache.data {
-        apps.insert(desktop_entry.location.clone(), desktop_entry);
+    stats.entries_loaded = contents.data.len();
+    for desktop_entry in contents.data {
+        cache.entries.insert(desktop_entry.source.location.clone(), desktop_entry);
     }
-    apps
+    cache.dirs = contents.dirs;
+    cache.locale_keys = contents.locale_keys;
+    cache.warned = contents.warned;
+    (cache, stats)
 }
 
-/// Saves the desktop entries to a serialized cache file.
+/// Saves the desktop entries and directory mtimes to a serialized cache file.
 ///
 /// # Arguments
 ///
-/// * `cache_dir`: the $XDG_CACHE_HOME directory where the file will be saved
-pub fn save_desktop_entries_to_cache<'a>(cache_dir: &str, apps: impl Iterator<Item=&'a DesktopEntry>) {
-    let cache = VersionedCacheForSerialize {
+/// * `file_path`: the cache file (see [`get_cache_file_path`])
+/// * `locale_keys`: the locales whose localized values the entries were
+///   parsed with
+/// * `warned`: the files which could not be parsed
+pub fn save_desktop_entries_to_cache<'a>(
+    file_path: &str,
+    apps: impl Iterator<Item=&'a DesktopEntry>,
+    dirs: &CachedDirs,
+    locale_keys: &[String],
+    warned: &WarnedFiles,
+) {
+    let header = CacheHeader {
         version: CACHE_VERSION,
+        compression: COMPRESSION,
+    };
+    let cache = CacheContentsForSerialize {
         data: apps.collect(),
+        dirs,
+        locale_keys,
+        warned,
     };
-    let encoded = bincode::serialize(&cache).unwrap();
-    let file_path = join_path(cache_dir, CACHE_FILE_NAME);
-    if let Err(err) = fs::write(&file_path, encoded) {
-        log_warn(&format!("Could not save desktop entries to {}: {}", &file_path, err));
+    let mut encoded = bincode::serialize(&header).unwrap();
+    let contents = bincode::serialize(&cache).unwrap();
+    // 0 is zstd's default level
+    #[cfg(feature = "zstd")]
+    encoded.extend(zstd::encode_all(contents.as_slice(), 0).unwrap());
+    #[cfg(not(feature = "zstd"))]
+    encoded.extend(contents);
+    let is_new = !Path::new(file_path).exists();
+    // Another instance may be reading the cache, so it is replaced at once
+    // instead of being rewritten in place
+    let tmp_path = format!("{file_path}.{}.tmp", std::process::id());
+    if let Err(err) = fs::write(&tmp_path, encoded).and_then(|()| fs::rename(&tmp_path, file_path)) {
+        let _ = fs::remove_file(&tmp_path);
+        log_warn(&tr("Could not save desktop entries to {}: {}", &[&file_path, &err]));
+    } else if is_new {
+        if let Some(cache_dir) = Path::new(file_path).parent() {
+            remove_stale_cache_files(cache_dir, file_path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let cache_dir = std::env::temp_dir().join(format!("i3-dmenu-desktop-rs-cache-{}", std::process::id()));
+        fs::create_dir_all(&cache_dir).unwrap();
+        let cache_dir = cache_dir.to_str().unwrap();
+        let contents = "[Desktop Entry]\nType=Application\nName=A\nExec=a\n";
+        let app = DesktopEntry::parse_contents(contents, "/a.desktop", SystemTime::UNIX_EPOCH, &[]).unwrap();
+        let dirs = CachedDirs::from([("/".to_string(), vec![("/".to_string(), SystemTime::UNIX_EPOCH)])]);
+        let file_path = get_cache_file_path(cache_dir, Some(fingerprint(&["/usr/share".to_string()], None)));
+        save_desktop_entries_to_cache(&file_path, [&app].into_iter(), &dirs, &["de".to_string()], &WarnedFiles::new());
+        let contents = fs::read(&file_path).unwrap();
+        // The header says whether the rest is compressed
+        assert_eq!(contents[..4], CACHE_VERSION.to_le_bytes());
+        assert_eq!(contents[4..8], (COMPRESSION as u32).to_le_bytes());
+        let (version, apps, read_dirs) = read_cache_file(&file_path).unwrap().unwrap();
+        assert_eq!((version, apps.len(), read_dirs), (CACHE_VERSION, 1, dirs));
+        assert_eq!(apps[0].keys.Name, "A");
+        assert_eq!(get_cached_desktop_entries(&file_path).0.locale_keys, ["de"]);
+        // The temporary file was renamed to the cache file
+        assert_eq!(fs::read_dir(cache_dir).unwrap().count(), 1);
+        fs::remove_dir_all(cache_dir).unwrap();
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let dirs = ["/usr/local/share".to_string(), "/usr/share".to_string()];
+        assert_eq!(fingerprint(&dirs, Some("a")), fingerprint(&dirs, Some("a")));
+        assert_ne!(fingerprint(&dirs, Some("a")), fingerprint(&dirs, Some("b")));
+        assert_ne!(fingerprint(&dirs, None), fingerprint(&dirs[..1], None));
+        assert_eq!(get_cache_file_path("/cache", None), "/cache/i3-dmenu-desktop-rs.bincode");
+        assert!(get_cache_file_path("/cache", Some(1)).ends_with("/i3-dmenu-desktop-rs-0000000000000001.bincode"));
+        // The names of the cache files do not change with the Rust release
+        assert_eq!(fingerprint(&dirs, None), 0x4888_f38a_f0a4_3717);
+    }
+
+    #[test]
+    fn test_remove_stale_cache_files() {
+        let cache_dir = std::env::temp_dir().join(format!("i3-dmenu-desktop-rs-stale-{}", std::process::id()));
+        fs::create_dir_all(&cache_dir).unwrap();
+        let path = |name: &str| cache_dir.join(name).to_str().unwrap().to_string();
+        fs::write(path("i3-dmenu-desktop-rs-0000000000000001.bincode"), (CACHE_VERSION - 1).to_le_bytes()).unwrap();
+        fs::write(path("i3-dmenu-desktop-rs-0000000000000002.bincode"), (CACHE_VERSION + 1).to_le_bytes()).unwrap();
+        fs::write(path("i3-dmenu-desktop-rs.bincode"), (CACHE_VERSION - 1).to_le_bytes()).unwrap();
+        let file_path = path("i3-dmenu-desktop-rs-0000000000000003.bincode");
+        save_desktop_entries_to_cache(&file_path, std::iter::empty(), &CachedDirs::new(), &[], &WarnedFiles::new());
+        let mut names: Vec<_> = fs::read_dir(&cache_dir).unwrap()
+            .map(|file| file.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["i3-dmenu-desktop-rs-0000000000000002.bincode", "i3-dmenu-desktop-rs-0000000000000003.bincode",
+                           "i3-dmenu-desktop-rs.bincode"]);
+        fs::remove_dir_all(&cache_dir).unwrap();
     }
 }
@@ -0,0 +1,50 @@
+//! Records of the desktop entries which the menu leaves out, and why, as
+//! printed by `--diagnostics-json`.
+
+use serde::Serialize;
+
+/// Why an entry is left out of the menu.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    /// The file could not be parsed
+    ParseError,
+    /// An entry with the same desktop file ID in a directory with higher
+    /// precedence is used instead
+    Masked,
+    /// The Type is not Application
+    NotApplication,
+    /// Hidden=true
+    Hidden,
+    /// NoDisplay=true
+    NoDisplay,
+    /// terminal_apps leaves out entries with (or without) Terminal=true
+    TerminalApp,
+    /// The entry was generated by Wine and wine_apps is exclude
+    WineApp,
+    /// The program in TryExec is not installed
+    TryExecMissing,
+    /// The program in Exec is not installed (only with verify_exec)
+    ExecMissing,
+    /// OnlyShowIn or NotShowIn exclude the current desktop (only in
+    /// autostart mode)
+    NotShownIn,
+}
+
+/// A desktop entry file which the menu leaves out.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub path: String,
+    /// The desktop file ID
+    pub id: String,
+    pub reason: Reason,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// Returns this record as a single line of JSON, e.g.
+    /// `{"path":"/usr/share/applications/a.desktop","id":"a.desktop","reason":"no_display",...}`.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
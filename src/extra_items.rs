@@ -0,0 +1,79 @@
+//! Menu items which do not come from desktop entries, e.g. the ones which a
+//! wrapper script passes in with `--stdin`.
+
+use serde::Deserialize;
+
+use super::i18n::tr;
+use super::utils::log_warn;
+
+/// A row of the menu which runs a shell command.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ExtraItem {
+    pub name: String,
+    /// The shell command which is run when the item is selected
+    pub exec: String,
+    #[serde(default)]
+    pub comment: Option<String>,
+    #[serde(default)]
+    pub icon: Option<String>,
+}
+
+/// Parses items from `input`, one per line. Each line is either a name and a
+/// command separated by a tab, or a JSON object with the fields of
+/// [`ExtraItem`]. Invalid lines are skipped with a warning.
+pub fn parse_extra_items(input: &str) -> Vec<ExtraItem> {
+    let mut items = Vec::new();
+    for line in input.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let item = if line.trim_start().starts_with('{') {
+            serde_json::from_str(line).map_err(|err| err.to_string())
+        } else {
+            match line.split_once('\t') {
+                Some((name, exec)) => Ok(ExtraItem {
+                    name: name.to_string(),
+                    exec: exec.to_string(),
+                    comment: None,
+                    icon: None,
+                }),
+                None => Err("expected a name and a command separated by a tab".to_string()),
+            }
+        };
+        match item {
+            Ok(item) => items.push(item),
+            Err(err) => log_warn(&tr("Ignoring item {}: {}", &[&format!("{:?}", line), &err])),
+        }
+    }
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_extra_items() {
+        let items = parse_extra_items(concat!(
+            "ssh example.com\tssh example.com\n",
+            "\n",
+            "no tab here\n",
+            r#"{"name": "Project", "exec": "code ~/project", "icon": "code"}"#, "\n",
+        ));
+        assert_eq!(items, [
+            ExtraItem {
+                name: "ssh example.com".to_string(),
+                exec: "ssh example.com".to_string(),
+                comment: None,
+                icon: None,
+            },
+            ExtraItem {
+                name: "Project".to_string(),
+                exec: "code ~/project".to_string(),
+                comment: None,
+                icon: Some("code".to_string()),
+            },
+        ]);
+    }
+}
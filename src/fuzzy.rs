@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+
+use super::desktop_entry::DesktopEntry;
+use super::utils::fold_for_matching;
+
+/// Returns how well `query` fuzzily matches `text`, from 0 (not at all) to
+/// 1, or `None` if the characters of `query` do not appear in `text` in
+/// order. Matching is case- and accent-insensitive.
+///
+/// Like in editors such as Sublime Text, each matched character counts
+/// twice if it starts a word (e.g. "gimp" for "GNU Image Manipulation
+/// Program") or directly follows the previous match (e.g. "fire" for
+/// "Firefox"), so a query scores 1 if it consists of word prefixes.
+pub fn score(query: &str, text: &str) -> Option<f64> {
+    let query: Vec<char> = fold_for_matching(query, false).chars().filter(|ch| !ch.is_whitespace()).collect();
+    if query.is_empty() {
+        return None;
+    }
+    let original: Vec<char> = fold_for_matching(text, true).chars().collect();
+    let text: Vec<char> = fold_for_matching(text, false).chars().collect();
+    if text.len() != original.len() {
+        // Lowercasing changed the number of characters, so the word starts
+        // cannot be told from the original
+        return None;
+    }
+    let is_word_start = |i: usize| {
+        i == 0 || !original[i - 1].is_alphanumeric()
+            || (original[i - 1].is_lowercase() && original[i].is_uppercase())
+    };
+    // best[j][i] is the best score for matching the first j + 1 characters
+    // of the query, with the last one matched at text[i]
+    let mut best: Vec<Vec<Option<u32>>> = vec![vec![None; text.len()]; query.len()];
+    for (j, &ch) in query.iter().enumerate() {
+        for i in 0..text.len() {
+            if text[i] != ch {
+                continue;
+            }
+            let previous = if j == 0 {
+                Some(0)
+            } else {
+                (0..i).filter_map(|k| best[j - 1][k].map(|score| (k, score)))
+                    .map(|(k, score)| score + u32::from(k + 1 == i && !is_word_start(i)))
+                    .max()
+            };
+            best[j][i] = previous.map(|score| score + 1 + u32::from(is_word_start(i)));
+        }
+    }
+    let score = best[query.len() - 1].iter().flatten().max()?;
+    Some(f64::from(*score) / (2.0 * query.len() as f64))
+}
+
+/// Returns how well `query` matches `app`, by its name, keywords or
+/// executable.
+fn score_app(query: &str, name: &str, app: &DesktopEntry) -> Option<f64> {
+    let program = app.get_program_name().unwrap_or_default();
+    [name].into_iter()
+        .chain(app.keys.Keywords.iter().map(String::as_str))
+        .chain([program.as_str()])
+        .filter_map(|text| score(query, text))
+        .max_by(f64::total_cmp)
+}
+
+/// Returns the entry in `app_map` (by name) which `query` matches best, if
+/// its score is at least `threshold`. Of entries with the same score, the
+/// one with the shortest name wins.
+pub fn best_match<'a>(
+    query: &str,
+    app_map: &'a HashMap<String, DesktopEntry>,
+    threshold: f64,
+) -> Option<(&'a str, &'a DesktopEntry)> {
+    app_map.iter()
+        .filter_map(|(name, app)| score_app(query, name, app).map(|score| (score, name, app)))
+        .filter(|(score, _, _)| *score >= threshold)
+        .max_by(|(score1, name1, _), (score2, name2, _)| {
+            score1.total_cmp(score2)
+                .then_with(|| name2.len().cmp(&name1.len()))
+                .then_with(|| name2.cmp(name1))
+        })
+        .map(|(_, name, app)| (name.as_str(), app))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score() {
+        assert_eq!(score("fire", "Firefox"), Some(1.0));
+        assert_eq!(score("gimp", "GNU Image Manipulation Program"), Some(1.0));
+        assert_eq!(score("libreoffice", "LibreOffice Writer"), Some(1.0));
+        assert_eq!(score("édit", "Editor"), Some(1.0));
+        assert_eq!(score("ffx", "Firefox"), Some(4.0 / 6.0));
+        assert_eq!(score("xf", "Firefox"), None);
+        assert_eq!(score("", "Firefox"), None);
+        // "ls" should not launch "Files" unless the threshold is low
+        assert!(score("ls", "Files").unwrap() < 0.6);
+    }
+
+    #[test]
+    fn test_best_match() {
+        let contents = "[Desktop Entry]\nType=Application\nName=App\nExec=/usr/bin/gnome-terminal\nKeywords=shell;prompt;\n";
+        let app = DesktopEntry::parse_contents(contents, "/a/app.desktop", std::time::SystemTime::UNIX_EPOCH, &[]).unwrap();
+        let app_map: HashMap<_, _> = ["Terminal", "Terminal Emulator", "Text Editor"].into_iter()
+            .map(|name| (name.to_string(), app.clone()))
+            .collect();
+        let best = |query| best_match(query, &app_map, 0.75).map(|(name, _)| name);
+        assert_eq!(best("term"), Some("Terminal"));
+        assert_eq!(best("te"), Some("Terminal"));
+        assert_eq!(best("temu"), Some("Terminal Emulator"));
+        assert_eq!(best("shell"), Some("Terminal"));
+        assert_eq!(best("gnome-term"), Some("Terminal"));
+        assert_eq!(best("xyz"), None);
+    }
+}
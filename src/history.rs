@@ -0,0 +1,215 @@
+//! Which entries were launched, how often and when.
+//!
+//! The history file is JSON with a stable schema, so that other tools can
+//! read it:
+//!
+//! ```json
+//! {"version": 1, "entries": {"firefox.desktop": {"count": 12, "last_used": 1700000000}}}
+//! ```
+//!
+//! `last_used` is in seconds since the Unix epoch. Changes which are not
+//! backwards compatible increment the version.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use serde::{Serialize, Deserialize};
+
+pub const HISTORY_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum HistoryError {
+    IoError(io::Error),
+    ParseError(String),
+}
+
+impl From<io::Error> for HistoryError {
+    fn from(error: io::Error) -> Self { Self::IoError(error) }
+}
+
+impl From<serde_json::Error> for HistoryError {
+    fn from(error: serde_json::Error) -> Self { Self::ParseError(error.to_string()) }
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::IoError(err) => write!(f, "{err}"),
+            Self::ParseError(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+/// The launches of a single entry.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub count: u64,
+    /// When the entry was last launched, in seconds since the Unix epoch
+    pub last_used: u64,
+}
+
+impl HistoryEntry {
+    /// Whether the entry was launched within `max_age` before `now`.
+    pub fn used_within(&self, max_age: Duration, now: SystemTime) -> bool {
+        let now = now.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+        now.saturating_sub(self.last_used) <= max_age.as_secs()
+    }
+}
+
+/// The launches of a single entry, as exported by `--history-json`.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct HistoryRecord {
+    /// The desktop file ID
+    pub app: String,
+    pub count: u64,
+    pub last_used: u64,
+}
+
+impl HistoryRecord {
+    /// Returns this record as a single line of JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+/// The launch history, keyed by desktop file ID.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct History {
+    version: u32,
+    pub entries: HashMap<String, HistoryEntry>,
+}
+
+impl History {
+    /// Reads the history file at `path`. A missing file is an empty history.
+    pub fn load(path: &str) -> Result<Self, HistoryError> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err.into()),
+        };
+        let history: Self = serde_json::from_str(&contents)?;
+        if history.version != HISTORY_VERSION {
+            return Err(HistoryError::ParseError(format!(
+                "unsupported format version {} (expected {})", history.version, HISTORY_VERSION)));
+        }
+        Ok(history)
+    }
+
+    /// Writes the history to `path`. The file is replaced atomically, so that
+    /// concurrent launches never see a partially written file.
+    pub fn save(&self, path: &str) -> Result<(), HistoryError> {
+        let history = Self { version: HISTORY_VERSION, entries: self.entries.clone() };
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp_path = format!("{path}.{}.tmp", std::process::id());
+        fs::write(&tmp_path, serde_json::to_string(&history)?)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Records that the entry with the desktop file ID `id` was launched at
+    /// `now`.
+    pub fn record(&mut self, id: &str, now: SystemTime) {
+        let last_used = now.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+        let entry = self.entries.entry(id.to_string()).or_insert(HistoryEntry { count: 0, last_used });
+        entry.count += 1;
+        entry.last_used = last_used;
+    }
+
+    pub fn get(&self, id: &str) -> Option<&HistoryEntry> {
+        self.entries.get(id)
+    }
+
+    /// Returns the launches of each entry, most frequently launched first.
+    pub fn records(&self) -> Vec<HistoryRecord> {
+        let mut records: Vec<_> = self.entries.iter()
+            .map(|(id, entry)| HistoryRecord { app: id.clone(), count: entry.count, last_used: entry.last_used })
+            .collect();
+        records.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.app.cmp(&b.app)));
+        records
+    }
+
+    /// Removes the entries which were last launched more than `max_age`
+    /// before `now`, and then all but the `max_entries` most recently
+    /// launched ones. Zero means no limit for either.
+    pub fn prune(&mut self, max_entries: usize, max_age: Duration, now: SystemTime) {
+        if !max_age.is_zero() {
+            self.entries.retain(|_, entry| entry.used_within(max_age, now));
+        }
+        if max_entries > 0 && self.entries.len() > max_entries {
+            let mut last_used: Vec<_> = self.entries.values().map(|entry| entry.last_used).collect();
+            last_used.sort_unstable_by(|a, b| b.cmp(a));
+            let cutoff = last_used[max_entries - 1];
+            self.entries.retain(|_, entry| entry.last_used >= cutoff);
+            // Of the entries which were launched in the same second as the
+            // cutoff, the ones with the smallest IDs are removed
+            let mut ids: Vec<_> = self.entries.iter()
+                .filter(|(_, entry)| entry.last_used == cutoff)
+                .map(|(id, _)| id.clone())
+                .collect();
+            ids.sort();
+            for id in ids.iter().take(self.entries.len().saturating_sub(max_entries)) {
+                self.entries.remove(id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_reload() {
+        let path = std::env::temp_dir().join(format!("i3-dmenu-desktop-rs-history-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut history = History::load(path).unwrap();
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        history.record("firefox.desktop", now);
+        history.record("firefox.desktop", now + Duration::from_secs(10));
+        history.save(path).unwrap();
+        let history = History::load(path).unwrap();
+        fs::remove_file(path).unwrap();
+        let entry = history.get("firefox.desktop").unwrap();
+        assert_eq!(*entry, HistoryEntry { count: 2, last_used: 1010 });
+        assert!(entry.used_within(Duration::from_secs(60), now + Duration::from_secs(60)));
+        assert!(!entry.used_within(Duration::from_secs(60), now + Duration::from_secs(100)));
+    }
+
+    #[test]
+    fn test_records() {
+        let mut history = History::default();
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        history.record("b.desktop", now);
+        history.record("a.desktop", now);
+        history.record("b.desktop", now);
+        let lines: Vec<_> = history.records().iter().map(HistoryRecord::to_json).collect();
+        assert_eq!(lines, [
+            r#"{"app":"b.desktop","count":2,"last_used":1000}"#,
+            r#"{"app":"a.desktop","count":1,"last_used":1000}"#,
+        ]);
+    }
+
+    #[test]
+    fn test_prune() {
+        let mut history = History::default();
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        for (i, id) in ["a", "b", "c", "d"].iter().enumerate() {
+            history.record(id, start + Duration::from_secs(10 * i as u64));
+        }
+        let now = start + Duration::from_secs(30);
+        history.prune(0, Duration::from_secs(25), now);
+        let mut ids: Vec<_> = history.entries.keys().map(String::as_str).collect();
+        ids.sort();
+        assert_eq!(ids, ["b", "c", "d"]);
+        history.prune(2, Duration::ZERO, now);
+        let mut ids: Vec<_> = history.entries.keys().map(String::as_str).collect();
+        ids.sort();
+        assert_eq!(ids, ["c", "d"]);
+    }
+}
@@ -0,0 +1,100 @@
+//! Translation of the launcher's own messages, i.e. its warnings, errors and
+//! menu labels, using gettext. Without the nls feature, they stay in English.
+//!
+//! Messages are marked with [`gettext`], or with [`tr`] if they have
+//! arguments, so that xgettext can extract them into
+//! `po/i3-dmenu-desktop-rs.pot`:
+//!
+//! ```text
+//! xgettext --language=Rust --keyword=gettext --keyword=tr --add-comments=TRANSLATORS \
+//!     --package-name=i3-dmenu-desktop-rs -o po/i3-dmenu-desktop-rs.pot src/*.rs
+//! ```
+//!
+//! The build script compiles the catalogs in `po/` with msgfmt.
+
+use std::fmt::Display;
+
+/// The gettext domain, i.e. the name of the compiled catalogs.
+pub const DOMAIN: &str = "i3-dmenu-desktop-rs";
+
+/// Sets up the translations for the locale from the environment. The
+/// catalogs are looked up in `$I3_DMENU_DESKTOP_LOCALEDIR`, or else in the
+/// ones compiled by the build script if they are still around (as with
+/// `cargo run`), or else in /usr/share/locale.
+#[cfg(feature = "nls")]
+pub fn init() {
+    use gettextrs::{bind_textdomain_codeset, bindtextdomain, setlocale, LocaleCategory};
+
+    setlocale(LocaleCategory::LcMessages, "");
+    setlocale(LocaleCategory::LcCType, "");
+    let dir = std::env::var("I3_DMENU_DESKTOP_LOCALEDIR").unwrap_or_else(|_| {
+        let built = env!("I3_DMENU_DESKTOP_RS_CATALOGS");
+        let dir = if std::path::Path::new(built).is_dir() { built } else { "/usr/share/locale" };
+        dir.to_string()
+    });
+    if bindtextdomain(DOMAIN, dir).is_ok() {
+        let _ = bind_textdomain_codeset(DOMAIN, "UTF-8");
+    }
+}
+
+#[cfg(not(feature = "nls"))]
+pub fn init() {}
+
+/// Returns the translation of `msgid`, or `msgid` itself if there is none.
+pub fn gettext(msgid: &str) -> String {
+    #[cfg(feature = "nls")]
+    return gettextrs::dgettext(DOMAIN, msgid);
+    #[cfg(not(feature = "nls"))]
+    msgid.to_string()
+}
+
+/// Translates `template` and fills in its `{}` placeholders with `args`, in
+/// order. Translations can refer to the arguments as `{0}`, `{1}` etc.
+/// instead, if the language needs them in another order.
+pub fn tr(template: &str, args: &[&dyn Display]) -> String {
+    fill(&gettext(template), args)
+}
+
+fn fill(template: &str, args: &[&dyn Display]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut next_arg = 0;
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.find('}').unwrap_or(0);
+        let index = match &rest[1..end.max(1)] {
+            "" if end == 1 => {
+                next_arg += 1;
+                Some(next_arg - 1)
+            },
+            digits => digits.parse::<usize>().ok(),
+        };
+        match index.and_then(|index| args.get(index)) {
+            Some(arg) => {
+                out.push_str(&arg.to_string());
+                rest = &rest[end + 1..];
+            },
+            // Anything else is not a placeholder
+            None => {
+                out.push('{');
+                rest = &rest[1..];
+            },
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fill() {
+        assert_eq!(fill("Could not parse {}: {}", &[&"a.desktop", &"no Name"]), "Could not parse a.desktop: no Name");
+        assert_eq!(fill("{1} in {0}", &[&"dir", &2]), "2 in dir");
+        // Braces which are not placeholders are left alone
+        assert_eq!(fill("{x} {} {5} {", &[&1]), "{x} 1 {5} {");
+    }
+}
@@ -0,0 +1,44 @@
+use std::path::Path;
+
+use super::utils::join_path;
+
+// Sizes in order of preference. Menus usually display icons at a small size,
+// so there is no point in loading huge images.
+const ICON_SIZES: [&str; 7] = ["48x48", "scalable", "64x64", "32x32", "128x128", "24x24", "256x256"];
+const ICON_EXTENSIONS: [&str; 3] = ["png", "svg", "xpm"];
+
+/// Returns the path of the image file for the value of an Icon key.
+///
+/// This is a greatly simplified version of the lookup algorithm from
+/// https://specifications.freedesktop.org/icon-theme-spec/latest/: only the
+/// hicolor fallback theme and the pixmaps directories are searched.
+///
+/// # Arguments
+///
+/// * `icon`: either an absolute path or an icon name
+/// * `data_dirs`: the XDG data directories, in order of preference
+pub fn find_icon_path(icon: &str, data_dirs: &[String]) -> Option<String> {
+    if icon.starts_with('/') {
+        return Path::new(icon).is_file().then(|| icon.to_string());
+    }
+    for data_dir in data_dirs {
+        let theme_dir = join_path(data_dir, "icons/hicolor");
+        for size in ICON_SIZES {
+            for ext in ICON_EXTENSIONS {
+                let path = format!("{theme_dir}/{size}/apps/{icon}.{ext}");
+                if Path::new(&path).is_file() {
+                    return Some(path);
+                }
+            }
+        }
+    }
+    for data_dir in data_dirs {
+        for ext in ICON_EXTENSIONS {
+            let path = format!("{}/{icon}.{ext}", join_path(data_dir, "pixmaps"));
+            if Path::new(&path).is_file() {
+                return Some(path);
+            }
+        }
+    }
+    None
+}
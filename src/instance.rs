@@ -0,0 +1,58 @@
+//! Keeps a second launcher from showing a menu on the same display while the
+//! first one is still open, e.g. when the keybinding is pressed twice.
+
+use std::io;
+#[cfg(target_os = "linux")]
+use std::os::unix::net::UnixListener;
+
+/// Held while the menu is shown, and released when dropped.
+///
+/// The lock is an abstract unix socket, which the kernel removes together
+/// with its last file descriptor, so it cannot go stale if the launcher
+/// crashes or gets killed, unlike a pidfile. Abstract sockets only exist on
+/// Linux; elsewhere, every instance gets the lock.
+pub struct InstanceLock {
+    #[cfg(target_os = "linux")]
+    _listener: UnixListener,
+}
+
+/// Takes the lock for `display` (the value of WAYLAND_DISPLAY or DISPLAY) for
+/// the current user, or returns `None` if another instance holds it.
+#[cfg(target_os = "linux")]
+pub fn lock(display: &str) -> io::Result<Option<InstanceLock>> {
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::fs::MetadataExt;
+    use std::os::unix::net::SocketAddr;
+
+    // Abstract sockets are shared by all users, so the name includes ours
+    let uid = std::fs::metadata("/proc/self")?.uid();
+    let name = format!("i3-dmenu-desktop-rs/{}/{}", uid, display);
+    let addr = SocketAddr::from_abstract_name(name.as_bytes())?;
+    match UnixListener::bind_addr(&addr) {
+        Ok(listener) => Ok(Some(InstanceLock { _listener: listener })),
+        Err(err) if err.kind() == io::ErrorKind::AddrInUse => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn lock(_display: &str) -> io::Result<Option<InstanceLock>> {
+    Ok(Some(InstanceLock {}))
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock() {
+        let display = format!("test-{}", std::process::id());
+        let first = lock(&display).unwrap();
+        assert!(first.is_some());
+        assert!(lock(&display).unwrap().is_none());
+        // Other displays have their own lock
+        assert!(lock(&format!("{}-other", display)).unwrap().is_some());
+        drop(first);
+        assert!(lock(&display).unwrap().is_some());
+    }
+}
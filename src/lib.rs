@@ -1,22 +1,95 @@
-use std::collections::HashMap;
+use std::borrow::Borrow;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::env::VarError;
 use std::fs;
-use std::path::Path;
+use std::io;
+#[cfg(feature = "launcher")]
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+#[cfg(feature = "launcher")]
+use std::time::SystemTime;
 
 use lazy_static::lazy_static;
 use regex::Regex;
+use serde::Deserialize;
 
+#[cfg(feature = "launcher")]
 pub mod app_launcher;
+#[cfg(feature = "launcher")]
+pub mod appimage;
+#[cfg(feature = "launcher")]
+pub mod calc;
+#[cfg(feature = "launcher")]
+pub mod cli;
+#[cfg(feature = "launcher")]
+pub mod config;
+#[cfg(feature = "launcher")]
+pub mod daemon;
+#[cfg(feature = "dbus")]
+pub mod dbus;
 pub mod desktop_entry;
+pub mod diagnostics;
+pub mod extra_items;
+#[cfg(feature = "launcher")]
+pub mod fuzzy;
+pub mod history;
+pub mod i18n;
+pub mod icons;
+pub mod lint;
+#[cfg(feature = "launcher")]
+pub mod instance;
+#[cfg(feature = "launcher")]
+pub mod menu;
+pub mod menu_spec;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+pub mod overrides;
+pub mod profile;
+#[cfg(feature = "launcher")]
+pub mod provider;
+#[cfg(feature = "launcher")]
+pub mod session;
+#[cfg(feature = "launcher")]
+pub mod startup_notify;
 mod utils;
 mod desktop_entry_cache;
+#[cfg(test)]
+mod test_utils;
 
-use app_launcher::ChildProcessError;
-use desktop_entry::DesktopEntry;
-use desktop_entry_cache::{get_cached_desktop_entries, save_desktop_entries_to_cache};
-use utils::{join_path, log_warn};
+#[cfg(feature = "launcher")]
+use app_launcher::{ChildProcessError, LaunchBackend};
+#[cfg(feature = "launcher")]
+use config::{ActionMode, Config, Mode, UsageBadge, WineMode};
+#[cfg(feature = "launcher")]
+use menu::{MenuItem, MenuProgram};
+use profile::{Profile, Stats};
+#[cfg(feature = "launcher")]
+use provider::{Launch, Provider, ProviderContext, ProviderKind};
+#[cfg(feature = "launcher")]
+use session::SessionType;
+use desktop_entry::{DesktopEntry, DesktopEntryError, Origin};
+#[cfg(feature = "launcher")]
+use diagnostics::{Diagnostic, Reason};
+use extra_items::ExtraItem;
+use history::History;
+#[cfg(feature = "launcher")]
+use i18n::gettext;
+use i18n::tr;
+use lint::LintIssue;
+use desktop_entry_cache::{
+    dirs_unchanged, get_cached_desktop_entries, get_dir_mtimes, save_desktop_entries_to_cache, Cache, WarnedFiles,
+    CACHE_VERSION,
+};
+use utils::{get_hostname, join_path, log_warn, PathExecutables};
+#[cfg(feature = "launcher")]
+use utils::log_info;
 
-fn get_locale_keys(lc_messages: &str) -> Vec<String> {
+/// Returns the locales whose localized values (e.g. `Name[de_AT]`) apply to
+/// the locale `lc_messages`, e.g. "de_AT.UTF-8", from highest to lowest
+/// priority. Values without a locale come after all of them.
+pub fn get_locale_keys(lc_messages: &str) -> Vec<String> {
     // Ignore the encoding (e.g. .UTF-8)
     lazy_static! {
         static ref ENCODING: Regex = Regex::new(r"\.[^@]+").unwrap();
@@ -48,12 +121,219 @@ fn get_locale_keys(lc_messages: &str) -> Vec<String> {
     suffixes
 }
 
+/// Like [`get_locale_keys`], but for several locales in order of
+/// preference, as in the LANGUAGE environment variable ("de:en"). The
+/// localized values for the first locale come first.
+pub fn get_locale_keys_for_languages<S: AsRef<str>>(languages: &[S]) -> Vec<String> {
+    let mut keys = Vec::new();
+    for key in languages.iter().flat_map(|lang| get_locale_keys(lang.as_ref())) {
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+    keys
+}
+
+/// Desktop file IDs and the paths of their files.
+type DesktopFiles = Vec<(String, PathBuf)>;
+
+/// Returns the desktop file IDs and paths of the desktop entries in
+/// `app_dir` (an applications directory) and its subdirectories, sorted by
+/// path. The ID of an entry in a subdirectory is prefixed with the
+/// subdirectory, e.g. "wine-Programs-foo.desktop" for wine/Programs/foo.desktop.
+fn find_desktop_files(app_dir: &str) -> io::Result<DesktopFiles> {
+    find_desktop_files_and_dirs(app_dir).map(|(files, _)| files)
+}
+
+/// Like [`find_desktop_files`], but also returns the directories which were
+/// read, i.e. `app_dir` and its subdirectories.
+///
+/// Symlinks to directories are followed, but each directory is only read
+/// once, so that a symlink loop does not recurse forever.
+fn find_desktop_files_and_dirs(app_dir: &str) -> io::Result<(DesktopFiles, Vec<PathBuf>)> {
+    let mut files = Vec::new();
+    let mut read_dirs = vec![PathBuf::from(app_dir)];
+    let mut visited: HashSet<_> = fs::canonicalize(app_dir).into_iter().collect();
+    let mut dirs = vec![(String::new(), fs::read_dir(app_dir)?)];
+    while let Some((prefix, entries)) = dirs.pop() {
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let id = format!("{prefix}{file_name}");
+            if path.is_dir() {
+                if !fs::canonicalize(&path).is_ok_and(|real_path| visited.insert(real_path)) {
+                    continue;
+                }
+                if let Ok(entries) = fs::read_dir(&path) {
+                    dirs.push((format!("{id}-"), entries));
+                    read_dirs.push(path);
+                }
+            } else if path.is_file() && file_name.ends_with(".desktop") {
+                files.push((id, path));
+            }
+        }
+    }
+    files.sort_by(|(_, a), (_, b)| a.cmp(b));
+    Ok((files, read_dirs))
+}
+
+// The row between the most recently launched entries and all other entries
+#[cfg(feature = "launcher")]
+const RECENT_APPS_DIVIDER: &str = "────────";
+
+/// Moves the `count` most recently launched entries in `names` to the
+/// top, most recent first, followed by a divider row.
+#[cfg(feature = "launcher")]
+fn pin_recent_apps<'a>(
+    names: Vec<&'a str>,
+    app_map: &HashMap<String, DesktopEntry>,
+    history: &History,
+    count: usize,
+) -> Vec<&'a str> {
+    // The desktop actions share the history of their entry
+    let last_used = |name: &str| app_map.get(name)
+        .filter(|app| app.action.is_none())
+        .and_then(|app| history.get(&app.source.id))
+        .map(|entry| entry.last_used);
+    let mut recent: Vec<_> = names.iter().copied().filter(|name| last_used(name).is_some()).collect();
+    if recent.is_empty() {
+        return names;
+    }
+    recent.sort_by_key(|name| std::cmp::Reverse(last_used(name)));
+    recent.truncate(count);
+    let rest = names.into_iter().filter(|name| !recent.contains(name));
+    recent.iter().copied().chain(iter::once(RECENT_APPS_DIVIDER)).chain(rest).collect()
+}
+
+/// Returns the entry in `app_map` whose name is `name`. Names which only
+/// differ in diacritics (e.g. "Editeur" typed for "Éditeur") or, unless
+/// `case_sensitive` is true, in case (e.g. "firefox" typed for "Firefox")
+/// match too, but exact matches are preferred.
+///
+/// If no name matches, the entry whose desktop file ID is `name` (with or
+/// without ".desktop", e.g. "org.mozilla.firefox") is returned, or else the
+/// entry whose Exec runs the program `name` (e.g. "nvim" for "Neovim"),
+/// unless several entries run it (e.g. flatpak or wine).
+#[cfg(feature = "launcher")]
+fn find_app_by_name<'a>(
+    app_map: &'a HashMap<String, DesktopEntry>,
+    name: &str,
+    case_sensitive: bool,
+) -> Option<&'a DesktopEntry> {
+    if let Some(app) = app_map.get(name) {
+        return Some(app);
+    }
+    let is_id = |id: &str| id == name || id.strip_suffix(".desktop") == Some(name);
+    let name = utils::fold_for_matching(name, case_sensitive);
+    let by_name = app_map.iter()
+        .filter(|(other, _)| utils::fold_for_matching(other, case_sensitive) == name)
+        // The map is unordered, so pick one of several matches consistently
+        .min_by_key(|(other, _)| other.as_str())
+        .map(|(_, app)| app);
+    if by_name.is_some() {
+        return by_name;
+    }
+    if let Some(app) = app_map.values().find(|app| app.action.is_none() && is_id(&app.source.id)) {
+        return Some(app);
+    }
+    let mut by_program = app_map.values().filter(|app| {
+        app.action.is_none()
+            && app.get_program_name().is_some_and(|program| utils::fold_for_matching(&program, case_sensitive) == name)
+    });
+    match (by_program.next(), by_program.next()) {
+        (Some(app), None) => Some(app),
+        _ => None,
+    }
+}
+
+/// Returns `template` with "%s" replaced by `query`, percent-encoded so that
+/// it can be used anywhere in a URL.
+#[cfg(feature = "launcher")]
+fn get_search_url(template: &str, query: &str) -> String {
+    let encoded: String = query.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => char::from(byte).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect();
+    template.replace("%s", &encoded)
+}
+
+/// Returns `names` with a counter appended to the ones which already
+/// appeared, e.g. an action called "Open" after the item which opens the
+/// application itself becomes "Open (2)".
+#[cfg(feature = "launcher")]
+fn unique_names<'a>(names: impl IntoIterator<Item=&'a str>) -> Vec<String> {
+    let mut unique: Vec<String> = Vec::new();
+    for base_name in names {
+        let mut name = base_name.to_string();
+        let mut counter = 1;
+        while unique.contains(&name) {
+            counter += 1;
+            name = format!("{} ({})", base_name, counter);
+        }
+        unique.push(name);
+    }
+    unique
+}
+
+/// Which state the launcher may write to disk. Files which the user asks
+/// for explicitly (e.g. with the hide prefix) are always written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Persistence {
+    /// Whether the desktop entry cache and the entries extracted from
+    /// AppImages are saved
+    pub cache: bool,
+    /// Whether launches are recorded in the history
+    pub history: bool,
+}
+
+impl Default for Persistence {
+    fn default() -> Self {
+        Self { cache: true, history: true }
+    }
+}
+
+/// What the name of the desktop entry cache file depends on, so that
+/// machines which share a home directory (e.g. over NFS) but have other
+/// data directories do not keep replacing each other's cache.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "launcher", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum CacheKey {
+    /// One cache file for everything
+    Shared,
+    /// One cache file per list of data directories
+    DataDirs,
+    /// One cache file per list of data directories and hostname
+    #[default]
+    Host,
+}
+
+#[derive(Clone)]
 pub struct XDGManager<F>
 where
     F: Fn(&str) -> Result<String, VarError>
 {
     get_env: F,
     home: String,
+    profile: RefCell<Profile>,
+    extra_items: Vec<ExtraItem>,
+    extra_data_dirs: Vec<String>,
+    skipped_data_dirs: Vec<String>,
+    skipped_origins: Vec<Origin>,
+    locales: Vec<String>,
+    #[cfg(feature = "launcher")]
+    appimage_dirs: Vec<String>,
+    persistence: Persistence,
+    cache_key: CacheKey,
+    check_try_exec: bool,
+    verify_exec: bool,
+    /// The entries of the last scan, if they are kept in memory instead of
+    /// being read from the cache file every time
+    entries_in_memory: Option<RefCell<Cache>>,
 }
 
 impl<F> XDGManager<F>
@@ -63,14 +343,182 @@ where
     pub fn new(get_env: F) -> Self {
         let home = get_env("HOME").expect("HOME environment variable must be set");
 
-        Self { get_env, home }
+        Self {
+            get_env,
+            home,
+            profile: RefCell::default(),
+            extra_items: Vec::new(),
+            extra_data_dirs: Vec::new(),
+            skipped_data_dirs: Vec::new(),
+            skipped_origins: Vec::new(),
+            locales: Vec::new(),
+            #[cfg(feature = "launcher")]
+            appimage_dirs: Vec::new(),
+            persistence: Persistence::default(),
+            cache_key: CacheKey::default(),
+            check_try_exec: true,
+            verify_exec: false,
+            entries_in_memory: None,
+        }
     }
 
-    fn get_data_dirs(&self) -> Vec<String> {
-        let xdg_data_home = match (self.get_env)("XDG_DATA_HOME") {
+    fn expand_home(&self, dir: String) -> String {
+        match dir.strip_prefix("~/") {
+            Some(rest) => join_path(&self.home, rest),
+            None => dir,
+        }
+    }
+
+    /// Adds data directories which are searched after the ones from the XDG
+    /// environment variables. A leading "~/" refers to the home directory.
+    pub fn add_extra_data_dirs(&mut self, dirs: impl IntoIterator<Item=String>) {
+        for dir in dirs {
+            let dir = self.expand_home(dir);
+            self.extra_data_dirs.push(dir);
+        }
+    }
+
+    /// Excludes data directories from being searched, e.g. /usr/share on a
+    /// system where only flatpaks should be listed. A leading "~/" refers to
+    /// the home directory.
+    pub fn skip_data_dirs(&mut self, dirs: impl IntoIterator<Item=String>) {
+        for dir in dirs {
+            let dir = self.expand_home(dir);
+            self.skipped_data_dirs.push(dir.trim_end_matches('/').to_string());
+        }
+    }
+
+    /// Excludes the data directories (or AppImage directories) with the
+    /// given origins from being searched, e.g. to only list flatpaks.
+    pub fn skip_origins(&mut self, origins: impl IntoIterator<Item=Origin>) {
+        self.skipped_origins.extend(origins);
+    }
+
+    /// Sets the locales whose names and comments are used, in order of
+    /// preference, instead of the ones from LANGUAGE and the locale.
+    pub fn set_locales(&mut self, locales: Vec<String>) {
+        self.locales = locales;
+    }
+
+    /// Adds directories whose AppImages are listed alongside the desktop
+    /// entries. A leading "~/" refers to the home directory.
+    #[cfg(feature = "launcher")]
+    pub fn add_appimage_dirs(&mut self, dirs: impl IntoIterator<Item=String>) {
+        for dir in dirs {
+            let dir = self.expand_home(dir);
+            self.appimage_dirs.push(dir);
+        }
+    }
+
+    /// Sets which state may be written to disk.
+    pub fn set_persistence(&mut self, persistence: Persistence) {
+        self.persistence = persistence;
+    }
+
+    /// Sets what the name of the desktop entry cache file depends on.
+    pub fn set_cache_key(&mut self, cache_key: CacheKey) {
+        self.cache_key = cache_key;
+    }
+
+    /// Sets whether entries whose TryExec program is not installed are left
+    /// out, as the spec says (which is the default).
+    pub fn set_check_try_exec(&mut self, check_try_exec: bool) {
+        self.check_try_exec = check_try_exec;
+    }
+
+    /// Sets whether entries whose Exec program cannot be found are left out,
+    /// e.g. leftovers in ~/.local/share/applications of removed packages.
+    pub fn set_verify_exec(&mut self, verify_exec: bool) {
+        self.verify_exec = verify_exec;
+    }
+
+    /// Keeps the scanned entries in memory, so that the next scan only has to
+    /// check their mtimes instead of reading the cache file. This is for
+    /// long-running processes like the daemon.
+    pub fn keep_entries_in_memory(&mut self) {
+        self.entries_in_memory.get_or_insert_with(RefCell::default);
+    }
+
+    /// Adds rows which run shell commands to the list of entries.
+    pub fn add_extra_items(&mut self, items: impl IntoIterator<Item=ExtraItem>) {
+        self.extra_items.extend(items);
+    }
+
+    /// Returns where the time was spent so far.
+    pub fn profile(&self) -> Profile {
+        self.profile.borrow().clone()
+    }
+
+    /// Runs `f`, adding the time it took to the duration returned by `field`.
+    fn timed<T>(&self, field: fn(&mut Profile) -> &mut Duration, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        *field(&mut self.profile.borrow_mut()) += start.elapsed();
+        result
+    }
+
+    #[cfg(feature = "launcher")]
+    fn get_dmenu_choice(
+        &self,
+        items: &[MenuItem],
+        menu: MenuProgram,
+        config: &Config,
+        prompt: Option<&str>,
+    ) -> Result<String, ChildProcessError> {
+        self.timed(|p| &mut p.menu_time, || app_launcher::get_dmenu_choice(items, menu, config, prompt))
+    }
+
+    #[cfg(feature = "launcher")]
+    fn launch_desktop_entry(
+        &self,
+        app: &DesktopEntry,
+        extra_args: &[&str],
+        backend: LaunchBackend,
+        config: &Config,
+    ) -> Result<(), ChildProcessError> {
+        // Entries with SingleMainWindow=true are raised instead of started
+        // again, unless there are files to open
+        let raised = config.single_main_window && app.keys.SingleMainWindow && app.action.is_none()
+            && extra_args.is_empty()
+            && self.timed(|p| &mut p.launch_time, || {
+                app_launcher::focus_existing_window(app, SessionType::detect(&self.get_env))
+            });
+        if !raised {
+            self.timed(|p| &mut p.launch_time, || app_launcher::launch_desktop_entry(app, extra_args, backend, config))
+                .map_err(ChildProcessError::LaunchFailed)?;
+        }
+        self.record_launch(app, config);
+        Ok(())
+    }
+
+    /// Launches the entry with the desktop file ID `id` (with or without the
+    /// ".desktop" suffix) without showing a menu, opening `uris` with it.
+    #[cfg(feature = "launcher")]
+    pub fn launch_by_id(&self, id: &str, uris: &[&str], config: &Config) -> Result<(), ChildProcessError> {
+        let apps = self.get_desktop_entries().0
+            .into_iter()
+            .filter(|app| app.keys.Type == "Application" && !app.keys.Hidden
+                    && (app.source.id == id || app.source.id.strip_suffix(".desktop") == Some(id)));
+        let app = self.filter_installed(apps)
+            .into_iter()
+            .next()
+            .ok_or_else(|| ChildProcessError::LaunchFailed(
+                io::Error::new(io::ErrorKind::NotFound, format!("no entry with the ID {:?}", id))
+            ))?;
+        let backend = config.launch_backend
+            .unwrap_or_else(|| SessionType::detect(&self.get_env).default_launch_backend());
+        self.launch_desktop_entry(&app, uris, backend, config)
+    }
+
+    fn get_data_home(&self) -> String {
+        match (self.get_env)("XDG_DATA_HOME") {
             Ok(val) => val,
             Err(_) => format!("{}/.local/share", self.home),
-        };
+        }
+    }
+
+    fn get_data_dirs(&self) -> Vec<String> {
+        let xdg_data_home = self.get_data_home();
         let xdg_data_dirs = match (self.get_env)("XDG_DATA_DIRS") {
             Ok(val) => val,
             Err(_) => String::from("/usr/local/share/:/usr/share/"),
@@ -79,9 +527,49 @@ where
         for dir in xdg_data_dirs.split(':') {
             dirs.push(dir.to_string());
         }
+        dirs.extend(self.extra_data_dirs.iter().cloned());
+        // "/usr/share" and "/usr/share/" are the same directory
+        dirs.retain(|dir| !self.skipped_data_dirs.iter().any(|skipped| skipped == dir.trim_end_matches('/')));
+        let data_home = self.get_data_home();
+        dirs.retain(|dir| !self.skipped_origins.contains(&Origin::for_data_dir(dir, &data_home)));
         dirs
     }
 
+    #[cfg(feature = "launcher")]
+    fn get_config_dir(&self) -> String {
+        match (self.get_env)("XDG_CONFIG_HOME") {
+            Ok(val) => val,
+            Err(_) => join_path(&self.home, ".config"),
+        }
+    }
+
+    /// Returns the user configuration directory followed by the ones from
+    /// XDG_CONFIG_DIRS, in order of precedence.
+    #[cfg(feature = "launcher")]
+    fn get_config_dirs(&self) -> Vec<String> {
+        let xdg_config_dirs = match (self.get_env)("XDG_CONFIG_DIRS") {
+            Ok(val) if !val.is_empty() => val,
+            _ => String::from("/etc/xdg"),
+        };
+        let mut dirs = vec![self.get_config_dir()];
+        dirs.extend(xdg_config_dirs.split(':').filter(|dir| !dir.is_empty()).map(|dir| dir.to_string()));
+        dirs
+    }
+
+    /// Reads the config file, falling back to the default settings if it
+    /// cannot be parsed.
+    #[cfg(feature = "launcher")]
+    pub fn load_config(&self) -> Config {
+        let path = join_path(&self.get_config_dir(), "i3-dmenu-desktop-rs/config.toml");
+        match Config::load(&path) {
+            Ok(config) => config,
+            Err(err) => {
+                log_warn(&tr("Could not load {}: {}", &[&path, &err]));
+                Config::default()
+            },
+        }
+    }
+
     fn get_cache_dir(&self) -> String {
         match (self.get_env)("XDG_CACHE_HOME") {
             Ok(val) => val,
@@ -89,6 +577,107 @@ where
         }
     }
 
+    /// Returns the path of the desktop entry cache.
+    pub fn get_cache_file_path(&self) -> String {
+        let fingerprint = match self.cache_key {
+            CacheKey::Shared => None,
+            CacheKey::DataDirs => Some(desktop_entry_cache::fingerprint(&self.get_data_dirs(), None)),
+            CacheKey::Host => Some(desktop_entry_cache::fingerprint(&self.get_data_dirs(), Some(&get_hostname()))),
+        };
+        desktop_entry_cache::get_cache_file_path(&self.get_cache_dir(), fingerprint)
+    }
+
+    /// Returns the directory for state which should persist between runs,
+    /// but is not important enough for the config directory, e.g. the
+    /// launch history.
+    pub fn get_state_dir(&self) -> String {
+        match (self.get_env)("XDG_STATE_HOME") {
+            Ok(val) => val,
+            Err(_) => join_path(&self.home, ".local/state"),
+        }
+    }
+
+    /// Returns the path of the socket which the daemon listens on, in
+    /// $XDG_RUNTIME_DIR (or the cache directory if that is not set).
+    #[cfg(feature = "launcher")]
+    pub fn get_daemon_socket_path(&self) -> String {
+        let dir = (self.get_env)("XDG_RUNTIME_DIR").unwrap_or_else(|_| self.get_cache_dir());
+        join_path(&dir, "i3-dmenu-desktop-rs.sock")
+    }
+
+    fn get_history_file_path(&self) -> String {
+        join_path(&self.get_state_dir(), "i3-dmenu-desktop-rs/history.json")
+    }
+
+    // Older versions kept the history in the cache directory
+    fn get_legacy_history_file_path(&self) -> String {
+        join_path(&self.get_cache_dir(), "i3-dmenu-desktop-rs/history.json")
+    }
+
+    /// Returns the launch history, or an empty one if it cannot be read.
+    pub fn load_history(&self) -> History {
+        let mut path = self.get_history_file_path();
+        if !Path::new(&path).exists() && Path::new(&self.get_legacy_history_file_path()).exists() {
+            path = self.get_legacy_history_file_path();
+        }
+        History::load(&path).unwrap_or_else(|err| {
+            log_warn(&tr("Could not load {}: {}", &[&path, &err]));
+            History::default()
+        })
+    }
+
+    // Only reads the history file if it is needed
+    #[cfg(feature = "launcher")]
+    fn load_history_for_menu(&self, config: &Config) -> History {
+        if config.usage_badge == UsageBadge::None && config.recent_apps == 0 {
+            return History::default();
+        }
+        self.load_history()
+    }
+
+    /// Deletes the launch history.
+    pub fn clear_history(&self) -> io::Result<()> {
+        for path in [self.get_history_file_path(), self.get_legacy_history_file_path()] {
+            match fs::remove_file(path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "launcher")]
+    fn record_launch(&self, app: &DesktopEntry, config: &Config) {
+        if !self.persistence.history {
+            return;
+        }
+        let path = self.get_history_file_path();
+        let mut history = self.load_history();
+        let now = SystemTime::now();
+        history.record(&app.source.id, now);
+        let max_age = Duration::from_secs(config.history_max_age_days.saturating_mul(24 * 60 * 60));
+        history.prune(config.history_max_entries, max_age, now);
+        match history.save(&path) {
+            Ok(()) => {
+                // The legacy file was migrated by loading it
+                let _ = fs::remove_file(self.get_legacy_history_file_path());
+            },
+            Err(err) => log_warn(&tr("Could not save {}: {}", &[&path, &err])),
+        }
+    }
+
+    #[cfg(feature = "launcher")]
+    fn get_usage_badge(app: &DesktopEntry, history: &History, config: &Config) -> Option<String> {
+        // Entries launched within this time are marked as recently used
+        const RECENT: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+        let entry = history.get(&app.source.id)?;
+        match config.usage_badge {
+            UsageBadge::None => None,
+            UsageBadge::Count => Some(entry.count.to_string()),
+            UsageBadge::Recent => entry.used_within(RECENT, SystemTime::now()).then(|| "recent".to_string()),
+        }
+    }
+
     fn get_env_paths(&self) -> Vec<String> {
         match (self.get_env)("PATH") {
             Ok(val) => val.split(':').map(|s| s.to_string()).collect(),
@@ -106,122 +695,1157 @@ where
         "C".to_string()
     }
 
-    fn get_desktop_entry_from_file(
-        path: &Path,
-        locale_keys: &[String],
-        env_paths: &[String],
-    )  -> Option<DesktopEntry> {
-        let path_str = path.to_str().unwrap();
-        let mut app = match DesktopEntry::parse(path_str, locale_keys) {
-            Ok(app) => app,
+    /// Returns the locales whose localized values are used, from highest to
+    /// lowest priority.
+    fn get_locale_keys(&self) -> Vec<String> {
+        if !self.locales.is_empty() {
+            return get_locale_keys_for_languages(&self.locales);
+        }
+        let lc_messages = self.get_lc_messages();
+        // Like gettext, LANGUAGE takes precedence over the locale, unless the
+        // locale is C (which disables translations)
+        let is_c_locale = matches!(lc_messages.split('.').next(), Some("C" | "POSIX"));
+        if let Ok(language) = (self.get_env)("LANGUAGE") {
+            let languages: Vec<_> = language.split(':').filter(|lang| !lang.is_empty()).collect();
+            if !is_c_locale && !languages.is_empty() {
+                return get_locale_keys_for_languages(&languages);
+            }
+        }
+        get_locale_keys(&lc_messages)
+    }
+
+    #[cfg(feature = "launcher")]
+    fn get_desktop_entry_from_file(path: &Path, locale_keys: &[String]) -> Option<DesktopEntry> {
+        let Some(path_str) = path.to_str() else {
+            log_warn(&tr("Skipping {}: the path is not valid UTF-8", &[&path.display()]));
+            return None;
+        };
+        match Self::parse_desktop_entry_file(path_str, locale_keys) {
+            Ok(app) => Some(app),
             Err(err) => {
-                log_warn(&format!("Could not parse {}: {}", path_str, err));
-                return None;
+                log_warn(&tr("Could not parse {}: {}", &[&path_str, &err]));
+                None
             },
-        };
+        }
+    }
+
+    fn parse_desktop_entry_file(path: &str, locale_keys: &[String]) -> Result<DesktopEntry, DesktopEntryError> {
+        let mut app = DesktopEntry::parse(path, locale_keys)?;
         app.escape_chars_for_exec_keys();
-        app.remove_invalid_tryexec(env_paths);
-        Some(app)
+        Ok(app)
     }
 
-    fn get_unique_name_for_desktop_entry(
+    fn get_unique_name_for_desktop_entry<V: Borrow<DesktopEntry>>(
         app: &DesktopEntry,
-        existing_apps: &HashMap<String, DesktopEntry>,
+        existing_apps: &HashMap<String, V>,
     ) -> String {
-        let mut name = app.Name.clone();
+        // Entries which would normally not be shown get a marker
+        let base_name = if let Some(ref action) = app.action {
+            format!("{} - {}", app.keys.Name, action.Name)
+        } else if app.keys.Hidden {
+            format!("{} [Hidden]", app.keys.Name)
+        } else if app.keys.NoDisplay {
+            format!("{} [NoDisplay]", app.keys.Name)
+        } else {
+            app.keys.Name.clone()
+        };
+        let base_name = utils::sanitize(&base_name);
+        let mut name = base_name.clone();
+        // An entry which has the same name as one from elsewhere, e.g. the
+        // Flatpak of a system package, is told apart by its origin
+        if existing_apps.get(&name).is_some_and(|existing| existing.borrow().source.origin != app.source.origin)
+            && app.source.origin != Origin::Unknown {
+            name = format!("{} ({})", &base_name, app.source.origin);
+        }
         let mut counter = 1;
         while existing_apps.contains_key(&name) {
             counter += 1;
-            name = format!("{} ({})", &app.Name, counter);
+            name = format!("{} ({})", &base_name, counter);
         }
         name
     }
 
-    fn get_app_map(&self) -> HashMap<String, DesktopEntry> {
-        let mut apps_by_name = HashMap::new();
-        let cache_dir = self.get_cache_dir();
-        let mut cached_apps_by_path = get_cached_desktop_entries(&cache_dir);
-        let mut at_least_one_app_not_in_cache = false;
+    /// Returns a map of unique display names to the desktop entries from all
+    /// data directories, along with counters from scanning them.
+    pub fn get_desktop_entry_map(&self) -> (HashMap<String, DesktopEntry>, Stats) {
+        let (apps, stats) = self.get_desktop_entries();
+        (self.get_app_map(apps), stats)
+    }
+
+    /// Like [`XDGManager::get_desktop_entry_map`], but scans the data
+    /// directories asynchronously, without the cache.
+    #[cfg(feature = "async")]
+    pub async fn get_desktop_entry_map_async(&self) -> HashMap<String, DesktopEntry> {
+        let locale_keys = self.get_locale_keys();
+        let mut apps = nonblocking::get_desktop_entries(&self.get_data_dirs(), &locale_keys).await;
+        let data_home = self.get_data_home();
+        for app in &mut apps {
+            app.source.origin = Origin::for_data_dir(&app.source.data_dir, &data_home);
+        }
+        self.get_app_map(apps)
+    }
+
+    /// Returns the desktop entries from all data directories, except for
+    /// those which are masked by an entry with the same desktop file ID.
+    fn get_desktop_entries(&self) -> (Vec<DesktopEntry>, Stats) {
+        self.scan_desktop_entries(true)
+    }
+
+    /// Forgets the entries kept in memory and in the cache, and parses all
+    /// desktop entry files again, e.g. after applications were installed.
+    pub fn reload(&self) {
+        if let Some(cache) = &self.entries_in_memory {
+            cache.take();
+        }
+        self.scan_desktop_entries(false);
+    }
+
+    fn scan_desktop_entries(&self, use_cache: bool) -> (Vec<DesktopEntry>, Stats) {
+        let mut stats = Stats::default();
+        // Entries with distinct desktop file IDs
+        let mut apps = Vec::new();
+        // Entries whose desktop file ID was already seen in a data directory
+        // with higher precedence
+        let mut masked_apps = Vec::new();
+        let mut seen_ids = HashSet::new();
+        let cache_file = self.get_cache_file_path();
+        let mut cache = match &self.entries_in_memory {
+            _ if !use_cache => Cache::default(),
+            Some(cache) if !cache.borrow().entries.is_empty() => cache.take(),
+            _ => {
+                let (cache, cache_stats) = self.timed(|p| &mut p.cache_read_time, || get_cached_desktop_entries(&cache_file));
+                stats.cache = cache_stats;
+                cache
+            },
+        };
+        let locale_keys = self.get_locale_keys();
+        // Entries parsed for other locales have the wrong names
+        if cache.locale_keys != locale_keys {
+            cache = Cache::default();
+        }
+        let mut cached_apps_by_path = std::mem::take(&mut cache.entries);
+        let mut dirs = HashMap::new();
+        let mut warned = WarnedFiles::new();
+        let mut entries_added_or_changed = false;
         let data_dirs = self.get_data_dirs();
-        let env_paths = self.get_env_paths();
-        let locale_keys = get_locale_keys(&self.get_lc_messages());
+        let data_home = self.get_data_home();
+        // The data directories are sorted by precedence, so the first entry
+        // with a given ID wins. This is how a user-level entry with
+        // Hidden=true "deletes" a system-level one.
+        let mut add_app = |mut app: DesktopEntry, data_dir: &str| {
+            app.source.data_dir = data_dir.to_string();
+            app.source.origin = Origin::for_data_dir(data_dir, &data_home);
+            if seen_ids.insert(app.source.id.clone()) {
+                apps.push(app);
+            } else {
+                masked_apps.push(app);
+            }
+        };
         for data_dir in &data_dirs {
             let app_dir = join_path(data_dir, "applications");
-            let entries = match fs::read_dir(app_dir) {
-                Ok(entries) => entries,
+            // If no file was added to or removed from a directory (which
+            // changes its mtime), its cached entries are all there is. Files
+            // modified in place do not change the mtime, which is why the
+            // directory which users edit by hand is always read.
+            match cache.dirs.get(&app_dir) {
+                Some(mtimes) if *data_dir != data_home && dirs_unchanged(mtimes) => {
+                    let prefix = join_path(&app_dir, "");
+                    let mut paths: Vec<_> = cached_apps_by_path.keys()
+                        .filter(|path| path.starts_with(&prefix))
+                        .cloned()
+                        .collect();
+                    paths.sort();
+                    stats.dirs_scanned += 1;
+                    for path in paths {
+                        stats.entries_scanned += 1;
+                        stats.cache_hits += 1;
+                        add_app(cached_apps_by_path.remove(&path).unwrap(), data_dir);
+                    }
+                    // Neither are the files which could not be parsed
+                    warned.extend(cache.warned.iter()
+                        .filter(|(path, _)| path.starts_with(&prefix))
+                        .map(|(path, mtime)| (path.clone(), *mtime)));
+                    dirs.insert(app_dir, mtimes.clone());
+                    continue;
+                },
+                _ => (),
+            }
+            let files = match find_desktop_files_and_dirs(&app_dir) {
+                Ok((files, read_dirs)) => {
+                    if let Some(mtimes) = get_dir_mtimes(&read_dirs).filter(|_| *data_dir != data_home) {
+                        dirs.insert(app_dir, mtimes);
+                    }
+                    files
+                },
                 Err(_) => continue,
             };
-            for entry in entries {
-                let entry = match entry {
-                    Ok(entry) => entry,
-                    Err(_) => continue,
+            stats.dirs_scanned += 1;
+            for (id, path) in files {
+                let Some(path_str) = path.to_str() else {
+                    continue;
                 };
-                let path = entry.path();
-                let path_str = path.to_str().unwrap();
-                if !path.is_file() || !path_str.ends_with(".desktop") {
+                let Ok(metadata) = path.metadata() else {
                     continue;
-                }
-                let mtime = match path.metadata() {
-                    Ok(metadata) => match metadata.modified() {
-                        Ok(mtime) => mtime,
-                        Err(_) => continue,
-                    },
-                    Err(_) => continue,
                 };
+                stats.entries_scanned += 1;
                 let mut app_opt: Option<DesktopEntry> = None;
                 if let Some(app) = cached_apps_by_path.remove(path_str) {
-                    if app.mtime == mtime {
+                    if app.source.matches(&metadata) {
                         app_opt = Some(app);
+                        stats.cache_hits += 1;
+                    } else {
+                        stats.cache.stale_entries += 1;
                     }
                 }
                 if app_opt.is_none() {
-                    if let Some(app) = Self::get_desktop_entry_from_file(&path, &locale_keys, &env_paths) {
-                        app_opt = Some(app);
-                        at_least_one_app_not_in_cache = true;
+                    stats.cache_misses += 1;
+                    let app = self.timed(|p| &mut p.parse_time, || {
+                        Self::parse_desktop_entry_file(path_str, &locale_keys)
+                    });
+                    match app {
+                        Ok(mut app) => {
+                            app.source.id = id;
+                            app_opt = Some(app);
+                            entries_added_or_changed = true;
+                        },
+                        Err(err) => {
+                            stats.parse_failures += 1;
+                            // A broken file would otherwise be warned about
+                            // on every run, until it is fixed
+                            let mtime = metadata.modified().ok();
+                            if mtime.is_none() || mtime != cache.warned.get(path_str).copied() {
+                                log_warn(&tr("Could not parse {}: {}", &[&path_str, &err]));
+                            }
+                            if let Some(mtime) = mtime {
+                                warned.insert(path_str.to_string(), mtime);
+                            }
+                        },
                     }
                 }
                 if let Some(app) = app_opt {
-                    let name = Self::get_unique_name_for_desktop_entry(&app, &apps_by_name);
-                    apps_by_name.insert(name, app);
+                    add_app(app, data_dir);
                 }
             }
         }
-        if at_least_one_app_not_in_cache {
-            save_desktop_entries_to_cache(&cache_dir, apps_by_name.values());
+        // Whatever is left of the cache belongs to files which were deleted
+        // (or are no longer searched), so rewriting it prunes them. Stale
+        // entries need to go too, even if their files no longer parse.
+        stats.cache.deleted_entries = cached_apps_by_path.len();
+        let cache_is_outdated = entries_added_or_changed
+            || stats.cache.stale_entries > 0
+            || stats.cache.deleted_entries > 0
+            || dirs != cache.dirs
+            || warned != cache.warned;
+        if cache_is_outdated && self.persistence.cache {
+            self.timed(|p| &mut p.cache_write_time, || {
+                save_desktop_entries_to_cache(&cache_file, apps.iter().chain(&masked_apps), &dirs, &locale_keys, &warned)
+            });
+        }
+        if let Some(memory) = &self.entries_in_memory {
+            *memory.borrow_mut() = Cache {
+                entries: apps.iter().chain(&masked_apps)
+                    .map(|app| (app.source.location.clone(), app.clone()))
+                    .collect(),
+                dirs,
+                locale_keys: locale_keys.clone(),
+                warned,
+            };
+        }
+        // AppImages have their own cache, keyed by the contents of the file
+        #[cfg(feature = "launcher")]
+        if !self.appimage_dirs.is_empty() && !self.skipped_origins.contains(&Origin::AppImage) {
+            let appimage_apps = self.timed(|p| &mut p.parse_time, || {
+                appimage::get_appimage_entries(&self.appimage_dirs, &self.get_cache_dir(), &locale_keys, self.persistence.cache)
+            });
+            apps.extend(appimage_apps.into_iter().filter(|app| seen_ids.insert(app.source.id.clone())));
+        }
+        self.profile.borrow_mut().stats.add(&stats);
+        (apps, stats)
+    }
+
+    /// Brings the entries kept in memory up to date and returns a copy of
+    /// this manager, e.g. to show a menu without holding a lock on it.
+    pub fn snapshot(&self) -> Self
+    where
+        F: Clone
+    {
+        self.get_desktop_entries();
+        self.clone()
+    }
+
+    /// Returns the desktop entries from all data directories (including the
+    /// hidden ones), sorted by desktop file ID.
+    pub fn list_desktop_entries(&self) -> Vec<DesktopEntry> {
+        let mut apps = self.get_desktop_entries().0;
+        apps.sort_by(|a, b| a.source.id.cmp(&b.source.id));
+        apps
+    }
+
+    /// Returns a human-readable description of the contents of the cache
+    /// file, and of how each cached entry compares to the file on disk.
+    pub fn dump_cache(&self) -> String {
+        let file_path = self.get_cache_file_path();
+        let (version, mut apps, _) = match desktop_entry_cache::read_cache_file(&file_path) {
+            Ok(Some(cache)) => cache,
+            Ok(None) => return format!("{file_path} does not exist\n"),
+            Err(msg) => return format!("{msg}\n"),
+        };
+        let mut out = format!("{file_path}: format version {version}, {} entries\n", apps.len());
+        if version != CACHE_VERSION {
+            out += &format!("The format version is outdated (expected {CACHE_VERSION}), so the cache will be ignored\n");
+        }
+        // Sort the entries by precedence, like get_desktop_entries sees them
+        let data_dirs = self.get_data_dirs();
+        let precedence = |app: &DesktopEntry| data_dirs.iter()
+            .position(|dir| app.source.location.starts_with(&join_path(&join_path(dir, "applications"), "")))
+            .unwrap_or(data_dirs.len());
+        apps.sort_by_cached_key(|app| (precedence(app), app.source.location.clone()));
+        let mut seen_ids = HashMap::new();
+        for app in &apps {
+            let secs = app.source.mtime.duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+            let status = match fs::metadata(&app.source.location) {
+                Ok(metadata) if app.source.matches(&metadata) => "up to date".to_string(),
+                Ok(_) => "stale (the file was modified since it was cached)".to_string(),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => "stale (the file was deleted)".to_string(),
+                Err(err) => format!("stale ({err})"),
+            };
+            out += &format!("\n{}\n", app.source.location);
+            out += &format!("    Name: {}\n", app.keys.Name);
+            out += &format!("    Type: {}\n", app.keys.Type);
+            out += &format!("    mtime: {secs}\n");
+            out += &format!("    status: {status}\n");
+            let keys = &app.keys;
+            let flags: Vec<_> = [("Hidden", keys.Hidden), ("NoDisplay", keys.NoDisplay), ("Terminal", keys.Terminal)]
+                .iter().filter(|(_, set)| *set).map(|(key, _)| format!("{key}=true")).collect();
+            if !flags.is_empty() {
+                out += &format!("    flags: {}\n", flags.join(", "));
+            }
+            if let Some(location) = seen_ids.get(&app.source.id) {
+                out += &format!("    masked by: {location}\n");
+            } else {
+                seen_ids.insert(app.source.id.clone(), app.source.location.clone());
+            }
+        }
+        let cached_paths: HashSet<_> = apps.iter().map(|app| app.source.location.as_str()).collect();
+        for data_dir in &data_dirs {
+            let Ok(files) = find_desktop_files(&join_path(data_dir, "applications")) else {
+                continue;
+            };
+            let paths = files.iter()
+                .filter_map(|(_, path)| path.to_str())
+                .filter(|path| !cached_paths.contains(path));
+            for path in paths {
+                out += &format!("\n{path}\n    status: not cached (it could not be parsed, or it is newer than the cache)\n");
+            }
+        }
+        out
+    }
+
+    /// Checks all desktop entry files in the data directories, including the
+    /// ones which are masked by another entry or fail to parse.
+    pub fn lint_desktop_entries(&self) -> Vec<LintIssue> {
+        let env_paths = self.get_env_paths();
+        let mut issues = Vec::new();
+        for data_dir in self.get_data_dirs() {
+            let Ok(files) = find_desktop_files(&join_path(&data_dir, "applications")) else {
+                continue;
+            };
+            for path in files.iter().filter_map(|(_, path)| path.to_str()) {
+                issues.extend(lint::lint_file(path, &env_paths));
+            }
+        }
+        issues
+    }
+
+    /// Returns a record for each desktop entry file which the menu leaves
+    /// out in `config.mode`, with the first reason why, in the order in
+    /// which the files are read.
+    #[cfg(feature = "launcher")]
+    pub fn diagnose_desktop_entries(&self, config: &Config) -> Vec<Diagnostic> {
+        let locale_keys = self.get_locale_keys();
+        let env_paths = self.get_env_paths();
+        let executables = self.verify_exec.then(|| PathExecutables::new(&env_paths));
+        let autostart = config.mode == Mode::Autostart;
+        let files: DesktopFiles = if autostart {
+            self.get_autostart_files().into_iter()
+                .filter_map(|path| Some((path.file_name()?.to_str()?.to_string(), path)))
+                .collect()
+        } else {
+            self.get_data_dirs().iter()
+                .flat_map(|dir| find_desktop_files(&join_path(dir, "applications")).unwrap_or_default())
+                .collect()
+        };
+        let desktops = self.get_current_desktops();
+        // The menu of --mode menu always leaves these out
+        let include_hidden = config.include_hidden && config.mode == Mode::Apps;
+        let include_nodisplay = config.include_nodisplay && config.mode == Mode::Apps;
+        // The paths of the entries which were used, by desktop file ID
+        let mut seen_ids = HashMap::new();
+        let mut diagnostics = Vec::new();
+        for (id, path) in files {
+            let Some(path_str) = path.to_str() else {
+                continue;
+            };
+            let mut report = |reason, message| diagnostics.push(Diagnostic {
+                path: path_str.to_string(),
+                id: id.clone(),
+                reason,
+                message,
+            });
+            let app = match Self::parse_desktop_entry_file(path_str, &locale_keys) {
+                Ok(app) => app,
+                Err(err) => {
+                    report(Reason::ParseError, err.to_string());
+                    continue;
+                },
+            };
+            let try_exec_missing = self.check_try_exec && !app.try_exec_is_installed(&env_paths);
+            // In the autostart directories, entries whose TryExec is missing
+            // do not override other ones
+            if autostart && try_exec_missing {
+                report(Reason::TryExecMissing,
+                       format!("the TryExec program {} is not installed", app.keys.TryExec.as_deref().unwrap_or_default()));
+                continue;
+            }
+            if let Some(masking_path) = seen_ids.get(&id) {
+                report(Reason::Masked, format!("{} has the same desktop file ID", masking_path));
+                continue;
+            }
+            seen_ids.insert(id.clone(), path_str.to_string());
+            let (reason, message) = if autostart {
+                if app.is_shown_in(&desktops) {
+                    continue;
+                }
+                (Reason::NotShownIn, format!("OnlyShowIn or NotShowIn exclude the current desktop ({})", desktops.join(":")))
+            } else if app.keys.Type != "Application" {
+                (Reason::NotApplication, format!("the Type is {}", app.keys.Type))
+            } else if app.keys.Hidden && !include_hidden {
+                (Reason::Hidden, "Hidden is true".to_string())
+            } else if app.keys.NoDisplay && !include_nodisplay {
+                (Reason::NoDisplay, "NoDisplay is true".to_string())
+            } else if !config.terminal_apps.matches(app.keys.Terminal) {
+                let message = if app.keys.Terminal {
+                    "Terminal is true and terminal_apps is exclude"
+                } else {
+                    "Terminal is not true and terminal_apps is only"
+                };
+                (Reason::TerminalApp, message.to_string())
+            } else if config.wine_apps == WineMode::Exclude && app.is_wine() {
+                (Reason::WineApp, "Wine generated the entry and wine_apps is exclude".to_string())
+            } else if try_exec_missing {
+                (Reason::TryExecMissing,
+                 format!("the TryExec program {} is not installed", app.keys.TryExec.as_deref().unwrap_or_default()))
+            } else {
+                match (&executables, app.get_program()) {
+                    (Some(executables), Some(program)) if !executables.contains(&program) =>
+                        (Reason::ExecMissing, format!("the Exec program {} is not installed", program)),
+                    _ => continue,
+                }
+            };
+            report(reason, message);
+        }
+        diagnostics
+    }
+
+    /// Returns a map of unique display names to desktop entries.
+    fn get_app_map(&self, apps: impl IntoIterator<Item=DesktopEntry>) -> HashMap<String, DesktopEntry> {
+        let mut apps_by_name = HashMap::new();
+        for app in self.filter_installed(apps) {
+            let name = Self::get_unique_name_for_desktop_entry(&app, &apps_by_name);
+            apps_by_name.insert(name, app);
         }
-        // Only keep apps which do not have Hidden or NoDisplay set to true.
-        // We still want to cache these entries to avoid reading them again on the next run.
-        apps_by_name.retain(|_, app| app.Type == "Application" && !app.Hidden && !app.NoDisplay);
         apps_by_name
     }
 
-    pub fn start_app_launcher(&self) -> Result<(), ChildProcessError> {
-        let app_map = self.get_app_map();
+    /// Leaves out the entries whose programs are not installed, i.e. those
+    /// whose TryExec is missing and, with verify_exec, those whose Exec is.
+    fn filter_installed(&self, apps: impl IntoIterator<Item=DesktopEntry>) -> Vec<DesktopEntry> {
+        let env_paths = self.get_env_paths();
+        let executables = self.verify_exec.then(|| PathExecutables::new(&env_paths));
+        // Whether the programs are installed is checked every time rather
+        // than cached, so that entries show up as soon as they are
+        apps.into_iter()
+            .filter(|app| !self.check_try_exec || app.try_exec_is_installed(&env_paths))
+            .filter(|app| match (&executables, app.get_program()) {
+                (Some(executables), Some(program)) => executables.contains(&program),
+                _ => true,
+            })
+            .collect()
+    }
+
+    /// Returns the entries which are shown in the categories of menu mode
+    /// and by the D-Bus service with `config`, sorted by desktop file ID.
+    #[cfg(feature = "launcher")]
+    pub fn list_menu_entries(&self, config: &Config) -> Vec<DesktopEntry> {
+        let apps = self.list_desktop_entries()
+            .into_iter()
+            .filter(|app| app.keys.Type == "Application" && !app.keys.Hidden && !app.keys.NoDisplay)
+            .filter(|app| config.wine_apps != WineMode::Exclude || !app.is_wine())
+            .filter(|app| config.terminal_apps.matches(app.keys.Terminal));
+        self.filter_installed(apps)
+    }
+
+    /// Returns the icon which should be passed to `menu`, if any.
+    #[cfg(feature = "launcher")]
+    fn get_menu_icon(icon: Option<&str>, menu: MenuProgram, data_dirs: &[String]) -> Option<String> {
+        let icon = icon?;
+        if menu.needs_icon_paths() {
+            icons::find_icon_path(icon, data_dirs)
+        } else {
+            Some(icon.to_string())
+        }
+    }
+
+    /// Shows a second menu with the main action of `app` ("Open") and its
+    /// desktop actions. Returns the entry which should be launched, if any.
+    #[cfg(feature = "launcher")]
+    fn choose_action(
+        &self,
+        app: &DesktopEntry,
+        menu: MenuProgram,
+        config: &Config,
+    ) -> Result<Option<DesktopEntry>, ChildProcessError> {
+        // TRANSLATORS: the item which launches the application itself rather
+        // than one of its actions
+        let open = gettext("Open");
+        let data_dirs = self.get_data_dirs();
+        // Actions without an Exec key can only be activated via D-Bus
+        let actions: Vec<_> = app.keys.Actions.iter().filter(|action| action.Exec.is_some()).collect();
+        // The first name is the application itself, the others are its actions
+        let names = unique_names(iter::once(open.as_str()).chain(actions.iter().map(|action| action.Name.as_str())));
+        let icons: Vec<_> = iter::once(app.keys.Icon.as_deref())
+            .chain(actions.iter().map(|action| action.Icon.as_deref().or(app.keys.Icon.as_deref())))
+            .map(|icon| Self::get_menu_icon(icon, menu, &data_dirs))
+            .collect();
+        let items: Vec<_> = names.iter().zip(&icons)
+            .map(|(name, icon)| MenuItem { prefix: None, name, comment: None, icon: icon.as_deref(), badge: None })
+            .collect();
+        let choice = self.get_dmenu_choice(&items, menu, config, Some(&config.action_prompt))?;
+        Ok(match names.iter().position(|name| *name == choice) {
+            Some(0) => Some(app.clone()),
+            Some(i) => Some(app.for_action(actions[i - 1])),
+            None => None,
+        })
+    }
+
+    /// Shows a submenu with the entries which Wine generated, and launches the
+    /// selected one.
+    #[cfg(feature = "launcher")]
+    fn choose_wine_app(
+        &self,
+        wine_apps: Vec<DesktopEntry>,
+        menu: MenuProgram,
+        backend: LaunchBackend,
+        config: &Config,
+    ) -> Result<(), ChildProcessError> {
+        let app_map = self.get_app_map(wine_apps);
         let mut app_names: Vec<_> = app_map.keys().collect();
         app_names.sort();
-        let choice = match app_launcher::get_dmenu_choice(&app_names) {
-            Ok(choice) => choice,
-            Err(err) => return Err(err),
+        let data_dirs = self.get_data_dirs();
+        let icons: Vec<_> = app_names.iter()
+            .map(|name| Self::get_menu_icon(app_map[*name].keys.Icon.as_deref(), menu, &data_dirs))
+            .collect();
+        let items: Vec<_> = app_names.iter().zip(&icons)
+            .map(|(name, icon)| {
+                MenuItem { prefix: None, name, comment: app_map[*name].keys.Comment.as_deref(), icon: icon.as_deref(), badge: None }
+            })
+            .collect();
+        let choice = self.get_dmenu_choice(&items, menu, config, Some(&gettext("wine:")))?;
+        match app_map.get(&choice) {
+            Some(app) => self.launch_desktop_entry(app, &[], backend, config),
+            None => Err(ChildProcessError::Cancelled),
+        }
+    }
+
+    /// Shows the menu and launches the selected entry. Returns `Ok(())` if
+    /// something was launched (or hidden etc.), otherwise the error tells
+    /// what went wrong; see [`ChildProcessError::exit_code`] for how this
+    /// maps to the exit status of the launcher.
+    #[cfg(feature = "launcher")]
+    pub fn start_app_launcher(&self, config: &Config) -> Result<(), ChildProcessError> {
+        let session = SessionType::detect(&self.get_env);
+        let menu = match config.menu {
+            Some(menu) => menu,
+            None => {
+                let candidates = config.menu_fallbacks.clone()
+                    .unwrap_or_else(|| session.default_menu_programs());
+                MenuProgram::find_installed(&candidates, &self.get_env_paths())
+                    .ok_or_else(|| ChildProcessError::MenuNotInstalled(
+                        gettext("none of the menu programs are installed")
+                    ))?
+            },
+        };
+        let backend = config.launch_backend.unwrap_or_else(|| session.default_launch_backend());
+        let display = (self.get_env)("WAYLAND_DISPLAY").or_else(|_| (self.get_env)("DISPLAY")).ok()
+            .filter(|display| !display.is_empty());
+        // Held until the selection was launched. Without a display (e.g. in
+        // tests), there is nothing to share.
+        let _instance_lock = match display {
+            Some(display) if config.single_instance => match instance::lock(&display) {
+                Ok(Some(lock)) => Some(lock),
+                Ok(None) => return Err(ChildProcessError::AlreadyRunning),
+                Err(err) => {
+                    log_warn(&tr("Could not check for other instances: {}", &[&err]));
+                    None
+                },
+            },
+            _ => None,
+        };
+        if config.mode == Mode::Autostart {
+            return self.manage_autostart(menu, backend, config);
+        }
+        if config.mode == Mode::Menu {
+            let apps = self.list_menu_entries(config);
+            return self.navigate_menu(&apps, menu, backend, config);
+        }
+        // Only show apps which do not have Hidden or NoDisplay set to true, unless
+        // requested otherwise. We still want to cache the other entries to avoid
+        // reading them again on the next run.
+        let apps = if config.providers.contains(&ProviderKind::Drun) {
+            self.get_desktop_entries().0
+        } else {
+            Vec::new()
+        };
+        let (mut visible_apps, nodisplay_apps): (Vec<_>, Vec<_>) = apps
+            .into_iter()
+            .filter(|app| app.keys.Type == "Application" && (!app.keys.Hidden || config.include_hidden))
+            .filter(|app| config.terminal_apps.matches(app.keys.Terminal))
+            .partition(|app| !app.keys.NoDisplay || config.include_nodisplay);
+        if config.actions == ActionMode::Flatten {
+            let action_apps: Vec<_> = visible_apps.iter()
+                .flat_map(|app| {
+                    app.keys.Actions.iter()
+                        .filter(|action| action.Exec.is_some())
+                        .map(|action| app.for_action(action))
+                })
+                .collect();
+            visible_apps.extend(action_apps);
+        }
+        let mut wine_apps = Vec::new();
+        match config.wine_apps {
+            WineMode::Show => (),
+            WineMode::Prefix => {
+                for app in visible_apps.iter_mut().filter(|app| app.is_wine()) {
+                    app.keys.Name = tr("Wine: {}", &[&app.keys.Name]);
+                }
+            },
+            WineMode::Group => (wine_apps, visible_apps) = visible_apps.into_iter().partition(|app| app.is_wine()),
+            WineMode::Exclude => visible_apps.retain(|app| !app.is_wine()),
+        }
+        let app_map = self.get_app_map(visible_apps);
+        // Aliases can refer to entries which are not displayed too
+        let alias_map: Vec<_> = config.aliases.iter()
+            .filter_map(|(alias, id)| {
+                let app = app_map.values().chain(&nodisplay_apps).find(|app| {
+                    app.action.is_none() && (app.source.id == *id || app.source.id.strip_suffix(".desktop") == Some(id))
+                });
+                if app.is_none() {
+                    log_warn(&tr("Ignoring the alias {}: there is no entry with the ID {}",
+                                 &[&format!("{:?}", alias), &format!("{:?}", id)]));
+                }
+                Some((alias.as_str(), app?))
+            })
+            .collect();
+        // The extra items, the aliases and the row of the Wine submenu get
+        // unique names in the same way as the entries
+        let mut extra_map = HashMap::new();
+        let mut alias_rows = HashMap::new();
+        let unique_name = |base_name: &str, extra_map: &HashMap<String, _>, alias_rows: &HashMap<String, _>| {
+            let base_name = utils::sanitize(base_name);
+            let mut name = base_name.clone();
+            let mut counter = 1;
+            while app_map.contains_key(&name) || extra_map.contains_key(&name) || alias_rows.contains_key(&name) {
+                counter += 1;
+                name = format!("{} ({})", &base_name, counter);
+            }
+            name
+        };
+        let env_paths = self.get_env_paths();
+        let cache_dir = self.get_cache_dir();
+        let context = ProviderContext {
+            home: &self.home,
+            env_paths: &env_paths,
+            session,
+            cache_dir: &cache_dir,
+            write_cache: self.persistence.cache,
+        };
+        let scripts = config.scripts.iter()
+            .map(|(name, script)| Box::new(provider::ScriptProvider { name, script }) as Box<dyn Provider>);
+        let providers = iter::once(Box::new(provider::ExtraItems(&self.extra_items)) as Box<dyn Provider>)
+            .chain(config.providers.iter().filter_map(|kind| kind.provider()))
+            .chain(scripts);
+        let mut sources = usize::from(config.providers.contains(&ProviderKind::Drun));
+        for provider in providers {
+            let items = provider.items(&context);
+            sources += usize::from(!items.is_empty());
+            let prefix = provider.prefix();
+            for item in items {
+                extra_map.insert(unique_name(&item.name, &extra_map, &alias_rows), (prefix.clone(), item));
+            }
+        }
+        // Rows are only prefixed with their provider if there are several
+        let show_prefixes = config.provider_prefixes && sources > 1;
+        if config.list_aliases {
+            for (alias, app) in &alias_map {
+                alias_rows.insert(unique_name(alias, &extra_map, &alias_rows), *app);
+            }
+        }
+        let wine_label = (!wine_apps.is_empty()).then(|| unique_name("Wine", &extra_map, &alias_rows));
+        let mut app_names: Vec<_> = app_map.keys().chain(extra_map.keys()).chain(alias_rows.keys()).chain(&wine_label)
+            .map(String::as_str)
+            .collect();
+        app_names.sort();
+        let history = self.load_history_for_menu(config);
+        if config.recent_apps > 0 {
+            app_names = pin_recent_apps(app_names, &app_map, &history, config.recent_apps);
+        }
+        let comment_and_icon = |name: &str| match (app_map.get(name).or_else(|| alias_rows.get(name).copied()), extra_map.get(name)) {
+            (Some(app), _) => (app.keys.Comment.as_deref(), app.keys.Icon.as_deref()),
+            (None, Some((_, item))) => (item.comment.as_deref(), item.icon.as_deref()),
+            _ if wine_label.as_deref() == Some(name) => (None, Some("wine")),
+            _ => (None, None),
         };
+        let data_dirs = self.get_data_dirs();
+        let icons: Vec<_> = app_names.iter()
+            .map(|name| Self::get_menu_icon(comment_and_icon(name).1, menu, &data_dirs))
+            .collect();
+        let badges: Vec<_> = app_names.iter()
+            .map(|name| app_map.get(*name).and_then(|app| Self::get_usage_badge(app, &history, config)))
+            .collect();
+        let prefix = |name: &str| match extra_map.get(name) {
+            _ if !show_prefixes || name == RECENT_APPS_DIVIDER => None,
+            Some((prefix, _)) => Some(prefix.as_str()),
+            None => Some(provider::DRUN_PREFIX),
+        };
+        let items: Vec<_> = app_names.iter().zip(&icons).zip(&badges)
+            .map(|((name, icon), badge)| MenuItem {
+                prefix: prefix(name),
+                name,
+                comment: comment_and_icon(name).0,
+                icon: icon.as_deref(),
+                badge: badge.as_deref(),
+            })
+            .collect();
+        let choice = self.get_dmenu_choice(&items, menu, config, config.prompt.as_deref())?;
+        if config.recent_apps > 0 && choice == RECENT_APPS_DIVIDER {
+            return Err(ChildProcessError::Cancelled);
+        }
+        if let Some((_, item)) = extra_map.get(&choice) {
+            return match &item.launch {
+                Launch::Command(cmd) => self.launch_command(cmd, backend, config),
+                Launch::Action(action) => action().map_err(ChildProcessError::LaunchFailed),
+            };
+        }
+        if wine_label.as_ref() == Some(&choice) {
+            return self.choose_wine_app(wine_apps, menu, backend, config);
+        }
+        // Typed text may start with the prefix of a provider too
+        let choice = match choice.split_once(": ") {
+            Some((prefix, rest)) if show_prefixes
+                && (prefix == provider::DRUN_PREFIX || extra_map.values().any(|(other, _)| other == prefix)) => {
+                rest.to_string()
+            },
+            _ => choice,
+        };
+        // Aliases take precedence over names which only match after folding
+        let find_app = |name: &str| app_map.get(name)
+            .or_else(|| alias_rows.get(name).copied())
+            .or_else(|| {
+                let name = utils::fold_for_matching(name, config.case_sensitive);
+                alias_map.iter()
+                    .find(|(alias, _)| utils::fold_for_matching(alias, config.case_sensitive) == name)
+                    .map(|(_, app)| *app)
+            })
+            .or_else(|| find_app_by_name(&app_map, name, config.case_sensitive));
         // The user selected one of the dmenu options.
-        if let Some(app) = app_map.get(&choice) {
-            return app_launcher::launch_desktop_entry(app, &[]).map_err(Into::into);
+        if let Some(app) = find_app(&choice) {
+            if config.actions == ActionMode::Submenu && !app.keys.Actions.is_empty() {
+                return match self.choose_action(app, menu, config)? {
+                    Some(app) => self.launch_desktop_entry(&app, &[], backend, config),
+                    None => Err(ChildProcessError::Cancelled),
+                };
+            }
+            return self.launch_desktop_entry(app, &[], backend, config);
         }
         // The user selected one of the dmenu options with one or more extra
         // arguments.
         if let Some((left, right)) = choice.rsplit_once(' ') {
-            if let Some(app) = app_map.get(left) {
-                return app_launcher::launch_desktop_entry(app, &[right]).map_err(Into::into);
+            if let Some(app) = find_app(left) {
+                return self.launch_desktop_entry(app, &[right], backend, config);
+            }
+        }
+        // The user wants to get rid of one of the dmenu options.
+        if let Some(app) = choice.strip_prefix(config.hide_prefix.as_str())
+            .filter(|_| !config.hide_prefix.is_empty())
+            .and_then(|name| find_app_by_name(&app_map, name, config.case_sensitive)) {
+            let user_dir = join_path(&self.get_data_home(), "applications");
+            let path = overrides::set_hidden(app, true, &user_dir)?;
+            log_info(&tr("Hid {} in {}", &[&app.source.id, &path]));
+            return Ok(());
+        }
+        // The user wants to calculate something.
+        if let Some(expr) = choice.strip_prefix(config.calc_prefix.as_str())
+            .filter(|_| !config.calc_prefix.is_empty()) {
+            return Self::calculate(expr.trim(), session);
+        }
+        // The user wants to search the web.
+        if let Some((query, template)) = choice.strip_prefix(config.search_prefix.as_str())
+            .filter(|_| !config.search_prefix.is_empty())
+            .zip(config.search_url.as_deref()) {
+            let url = get_search_url(template, query.trim());
+            return self.launch_command(&format!("xdg-open {}", desktop_entry::quote_for_sh(&url)), backend, config);
+        }
+        // The user typed the exact name or desktop file ID of an entry which is
+        // not displayed.
+        if config.resolve_nodisplay {
+            let app = nodisplay_apps.iter().find(|app| {
+                let id = &app.source.id;
+                let name_matches = utils::fold_for_matching(&app.keys.Name, config.case_sensitive)
+                    == utils::fold_for_matching(&choice, config.case_sensitive);
+                name_matches || *id == choice || id.strip_suffix(".desktop") == Some(&choice)
+            });
+            if let Some(app) = app {
+                return self.launch_desktop_entry(app, &[], backend, config);
+            }
+        }
+        // The user typed something close to the name of an entry.
+        if let Some(threshold) = config.fuzzy_threshold {
+            if let Some((name, app)) = fuzzy::best_match(&choice, &app_map, threshold) {
+                log_info(&tr("Launching {} for {}", &[&name, &format!("{:?}", choice)]));
+                return self.launch_desktop_entry(app, &[], backend, config);
+            }
+        }
+        self.launch_arbitrary_input(&choice, menu, backend, config)
+    }
+
+    #[cfg(feature = "launcher")]
+    fn launch_arbitrary_input(
+        &self,
+        choice: &str,
+        menu: MenuProgram,
+        backend: LaunchBackend,
+        config: &Config,
+    ) -> Result<(), ChildProcessError> {
+        if !config.allow_arbitrary_input {
+            return Err(ChildProcessError::Cancelled);
+        }
+        if let Some(target) = self.get_openable_target(choice) {
+            return self.launch_command(&format!("xdg-open {}", desktop_entry::quote_for_sh(&target)), backend, config);
+        }
+        if config.confirm_arbitrary_input {
+            let question = tr("run '{}' in shell?", &[&choice]);
+            let items = [MenuItem { prefix: None, name: &question, comment: None, icon: None, badge: None }];
+            // Typing something else is the same as dismissing the menu
+            if self.get_dmenu_choice(&items, menu, config, None)? != question {
+                return Err(ChildProcessError::Cancelled);
+            }
+        }
+        if config.create_entry {
+            // If no name is entered, the command is run without being saved
+            let name = match self.get_dmenu_choice(&[], menu, config, Some(&gettext("save as:"))) {
+                Ok(name) => Some(name),
+                Err(ChildProcessError::Cancelled) => None,
+                Err(err) => return Err(err),
+            };
+            if let Some(name) = name.as_deref().map(str::trim).filter(|name| !name.is_empty()) {
+                let user_dir = join_path(&self.get_data_home(), "applications");
+                let path = overrides::create_entry(name, choice, &user_dir)?;
+                log_info(&tr("Saved {} as {}", &[&format!("{:?}", choice), &path]));
+            }
+        }
+        self.launch_command(choice, backend, config)
+    }
+
+    /// Shows the result of the arithmetic expression `expr`, or why it could
+    /// not be evaluated, in a notification.
+    #[cfg(feature = "launcher")]
+    fn calculate(expr: &str, session: SessionType) -> Result<(), ChildProcessError> {
+        match calc::evaluate(expr) {
+            Ok(value) => {
+                let value = calc::format_number(value);
+                calc::show_result(&format!("{} = {}", expr, value), Some(&value), session)
+                    .map_err(ChildProcessError::LaunchFailed)
+            },
+            Err(err) => {
+                let message = format!("{}: {}", expr, err);
+                calc::show_result(&message, None, session).map_err(ChildProcessError::LaunchFailed)?;
+                Err(ChildProcessError::LaunchFailed(io::Error::new(io::ErrorKind::InvalidInput, message)))
+            },
+        }
+    }
+
+    /// Returns what `input` refers to if it should be opened with xdg-open
+    /// rather than run: a URL (e.g. "https://example.com"), or an existing
+    /// file which is not executable, or directory. A leading "~/" refers to
+    /// the home directory.
+    #[cfg(feature = "launcher")]
+    fn get_openable_target(&self, input: &str) -> Option<String> {
+        lazy_static! {
+            static ref URL: Regex = Regex::new(r"^([a-zA-Z][a-zA-Z0-9+.-]*://|mailto:)\S+$").unwrap();
+        }
+        if URL.is_match(input) {
+            return Some(input.to_string());
+        }
+        if !input.starts_with('/') && !input.starts_with("~/") {
+            return None;
+        }
+        let path = self.expand_home(input.to_string());
+        let metadata = fs::metadata(&path).ok()?;
+        (metadata.is_dir() || !utils::is_executable(&path)).then_some(path)
+    }
+
+    /// Runs a shell command which does not belong to a desktop entry.
+    #[cfg(feature = "launcher")]
+    fn launch_command(&self, cmd: &str, backend: LaunchBackend, config: &Config) -> Result<(), ChildProcessError> {
+        self.timed(|p| &mut p.launch_time, || app_launcher::launch_cmd_without_desktop_entry(cmd, backend, config))
+            .map_err(ChildProcessError::LaunchFailed)
+    }
+
+    /// Returns the path of the root menu file, along with the directories
+    /// which `<DefaultMergeDirs>` refers to.
+    #[cfg(feature = "launcher")]
+    fn get_menu_file(&self) -> Option<(String, Vec<String>)> {
+        let prefix = (self.get_env)("XDG_MENU_PREFIX").unwrap_or_default();
+        let config_dirs = self.get_config_dirs();
+        let merge_dirs = config_dirs.iter()
+            .map(|dir| join_path(dir, "menus/applications-merged"))
+            .collect();
+        config_dirs.iter()
+            .map(|dir| join_path(dir, &format!("menus/{prefix}applications.menu")))
+            .find(|path| Path::new(path).is_file())
+            .map(|path| (path, merge_dirs))
+    }
+
+    /// Returns the autostart directories, in order of precedence.
+    #[cfg(feature = "launcher")]
+    fn get_autostart_dirs(&self) -> Vec<String> {
+        self.get_config_dirs().iter()
+            .map(|dir| join_path(dir, "autostart"))
+            .collect()
+    }
+
+    /// Returns the desktop environments from XDG_CURRENT_DESKTOP.
+    #[cfg(feature = "launcher")]
+    fn get_current_desktops(&self) -> Vec<String> {
+        match (self.get_env)("XDG_CURRENT_DESKTOP") {
+            Ok(val) => val.split(':').filter(|s| !s.is_empty()).map(|s| s.to_string()).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Returns the autostart entries which apply to the current desktop,
+    /// including the ones with Hidden=true.
+    #[cfg(feature = "launcher")]
+    fn get_autostart_entries(&self) -> Vec<DesktopEntry> {
+        let locale_keys = self.get_locale_keys();
+        let env_paths = self.get_env_paths();
+        let desktops = self.get_current_desktops();
+        let mut seen_ids = HashSet::new();
+        let mut apps = Vec::new();
+        for path in self.get_autostart_files() {
+            let Some(app) = Self::get_desktop_entry_from_file(&path, &locale_keys) else {
+                continue;
+            };
+            if self.check_try_exec && !app.try_exec_is_installed(&env_paths) {
+                continue;
+            }
+            // A user entry with the same ID overrides the system-wide one,
+            // even if it does not apply to the current desktop.
+            if seen_ids.insert(app.source.id.clone()) && app.is_shown_in(&desktops) {
+                apps.push(app);
+            }
+        }
+        apps
+    }
+
+    /// Returns the paths of the files in the autostart directories, in
+    /// order of precedence.
+    #[cfg(feature = "launcher")]
+    fn get_autostart_files(&self) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        for dir in self.get_autostart_dirs() {
+            let mut paths: Vec<_> = match fs::read_dir(&dir) {
+                Ok(entries) => entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect(),
+                Err(_) => continue,
+            };
+            paths.sort();
+            files.extend(paths.into_iter().filter(|path| path.extension().is_some_and(|ext| ext == "desktop")));
+        }
+        files
+    }
+
+    /// Lists the autostart entries and lets the user either launch the
+    /// selected one, or toggle whether it gets started with the session.
+    #[cfg(feature = "launcher")]
+    fn manage_autostart(
+        &self,
+        menu: MenuProgram,
+        backend: LaunchBackend,
+        config: &Config,
+    ) -> Result<(), ChildProcessError> {
+        let (launch, enable, disable) = (gettext("Launch"), gettext("Enable"), gettext("Disable"));
+        let app_map = self.get_app_map(self.get_autostart_entries());
+        let mut app_names: Vec<_> = app_map.keys().collect();
+        app_names.sort();
+        let data_dirs = self.get_data_dirs();
+        let icons: Vec<_> = app_names.iter()
+            .map(|name| Self::get_menu_icon(app_map[*name].keys.Icon.as_deref(), menu, &data_dirs))
+            .collect();
+        let items: Vec<_> = app_names.iter().zip(&icons)
+            .map(|(name, icon)| MenuItem {
+                prefix: None,
+                name,
+                comment: app_map[*name].keys.Comment.as_deref(),
+                icon: icon.as_deref(),
+                badge: None,
+            })
+            .collect();
+        let choice = self.get_dmenu_choice(&items, menu, config, config.prompt.as_deref())?;
+        let Some(app) = app_map.get(&choice) else {
+            return Err(ChildProcessError::Cancelled);
+        };
+        let toggle = if app.keys.Hidden { &enable } else { &disable };
+        let items = [&launch, toggle].map(|name| MenuItem { prefix: None, name, comment: None, icon: None, badge: None });
+        let prompt = format!("{}:", app.keys.Name);
+        let choice = self.get_dmenu_choice(&items, menu, config, Some(&prompt))?;
+        if choice == launch {
+            return self.launch_desktop_entry(app, &[], backend, config);
+        }
+        if choice == *toggle {
+            let user_dir = &self.get_autostart_dirs()[0];
+            let path = overrides::set_hidden(app, !app.keys.Hidden, user_dir)?;
+            let args: [&dyn std::fmt::Display; 2] = [&app.source.id, &path];
+            log_info(&if app.keys.Hidden { tr("Enabled {} in {}", &args) } else { tr("Disabled {} in {}", &args) });
+            return Ok(());
+        }
+        Err(ChildProcessError::Cancelled)
+    }
+
+    /// Returns the entry of type Directory which describes a submenu, i.e.
+    /// its localized name, comment and icon.
+    ///
+    /// # Arguments
+    ///
+    /// * `file`: the value of a `<Directory>` element, relative to the
+    ///   desktop-directories subdirectory of the data directories
+    #[cfg(feature = "launcher")]
+    fn get_directory_entry(&self, file: &str, locale_keys: &[String]) -> Option<DesktopEntry> {
+        let path = self.get_data_dirs().iter()
+            .map(|dir| join_path(dir, &format!("desktop-directories/{file}")))
+            .find(|path| Path::new(path).is_file())?;
+        match DesktopEntry::parse(&path, locale_keys) {
+            Ok(entry) => Some(entry),
+            Err(err) => {
+                log_warn(&tr("Could not parse {}: {}", &[&path, &err]));
+                None
+            },
+        }
+    }
+
+    /// Lets the user navigate through the menu hierarchy from
+    /// applications.menu, one level at a time, and launches the selected app.
+    #[cfg(feature = "launcher")]
+    fn navigate_menu(
+        &self,
+        apps: &[DesktopEntry],
+        menu: MenuProgram,
+        backend: LaunchBackend,
+        config: &Config,
+    ) -> Result<(), ChildProcessError> {
+        const PARENT: &str = "..";
+        let (path, merge_dirs) = self.get_menu_file().ok_or_else(|| {
+            ChildProcessError::ProcessFailed(gettext("could not find applications.menu"))
+        })?;
+        let mut root = menu_spec::Menu::load(&path, &merge_dirs).map_err(|err| {
+            ChildProcessError::ProcessFailed(tr("Could not load {}: {}", &[&path, &err]))
+        })?;
+        let apps: Vec<_> = apps.iter().collect();
+        root.allocate(&apps);
+        let apps_by_id = menu_spec::index_by_id(&apps);
+        let data_dirs = self.get_data_dirs();
+        let locale_keys = self.get_locale_keys();
+        let history = self.load_history_for_menu(config);
+        // The indices of the submenus leading to the current menu
+        let mut indices: Vec<usize> = Vec::new();
+        // The labels of those submenus, for the prompt
+        let mut labels: Vec<String> = Vec::new();
+        loop {
+            let current = indices.iter().fold(&root, |m, &i| &m.submenus[i]);
+            // Submenus are labelled with the Name from their .directory file
+            // (e.g. "Development tools"), falling back to the raw menu name.
+            let mut submenus: Vec<_> = current.submenus.iter().enumerate()
+                .filter_map(|(i, submenu)| {
+                    let directory = submenu.directory.as_deref()
+                        .and_then(|file| self.get_directory_entry(file, &locale_keys));
+                    if directory.as_ref().is_some_and(|d| d.keys.NoDisplay || d.keys.Hidden) {
+                        return None;
+                    }
+                    let label = directory.as_ref().map_or(submenu.name.as_str(), |d| d.keys.Name.as_str());
+                    Some((format!("{label}/"), i, directory))
+                })
+                .collect();
+            submenus.sort_by(|a, b| a.0.cmp(&b.0));
+            let submenu_icons: Vec<_> = submenus.iter()
+                .map(|(_, _, d)| {
+                    Self::get_menu_icon(d.as_ref().and_then(|d| d.keys.Icon.as_deref()), menu, &data_dirs)
+                })
+                .collect();
+            let mut app_map = HashMap::new();
+            for app in current.app_ids.iter().filter_map(|id| apps_by_id.get(id.as_str())) {
+                let name = Self::get_unique_name_for_desktop_entry(app, &app_map);
+                app_map.insert(name, *app);
+            }
+            let mut app_names: Vec<_> = app_map.keys().collect();
+            app_names.sort();
+            let icons: Vec<_> = app_names.iter()
+                .map(|name| Self::get_menu_icon(app_map[*name].keys.Icon.as_deref(), menu, &data_dirs))
+                .collect();
+            let mut items = Vec::new();
+            if !indices.is_empty() {
+                items.push(MenuItem { prefix: None, name: PARENT, comment: None, icon: None, badge: None });
+            }
+            items.extend(submenus.iter().zip(&submenu_icons).map(|((name, _, directory), icon)| MenuItem {
+                prefix: None,
+                name,
+                comment: directory.as_ref().and_then(|d| d.keys.Comment.as_deref()),
+                icon: icon.as_deref(),
+                badge: None,
+            }));
+            let badges: Vec<_> = app_names.iter()
+                .map(|name| Self::get_usage_badge(app_map[*name], &history, config))
+                .collect();
+            items.extend(app_names.iter().zip(&icons).zip(&badges).map(|((name, icon), badge)| MenuItem {
+                prefix: None,
+                name,
+                comment: app_map[*name].keys.Comment.as_deref(),
+                icon: icon.as_deref(),
+                badge: badge.as_deref(),
+            }));
+            let prompt = match labels.last() {
+                Some(label) => Some(format!("{}:", label.trim_end_matches('/'))),
+                None => config.prompt.clone(),
+            };
+            let choice = self.get_dmenu_choice(&items, menu, config, prompt.as_deref())?;
+            if choice == PARENT && !indices.is_empty() {
+                indices.pop();
+                labels.pop();
+            } else if let Some((label, i, _)) = submenus.iter().find(|(name, _, _)| *name == choice) {
+                labels.push(label.clone());
+                indices.push(*i);
+            } else if let Some(app) = app_map.get(&choice) {
+                return self.launch_desktop_entry(app, &[], backend, config);
+            } else {
+                return self.launch_arbitrary_input(&choice, menu, backend, config);
             }
         }
-        // The user typed arbitrary input.
-        app_launcher::launch_i3_cmd_without_desktop_entry(&choice).map_err(Into::into)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::profile::CacheStats;
+    use super::test_utils::{entry, Fixture};
 
     #[test]
     fn test_get_data_dirs_default() {
@@ -242,6 +1866,21 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_get_state_dir() {
+        let mgr = XDGManager::new(|s| match s {
+            "HOME" => Ok("/home/max".to_string()),
+            _ => Err(VarError::NotPresent),
+        });
+        assert_eq!(mgr.get_state_dir(), "/home/max/.local/state");
+        let mgr = XDGManager::new(|s| match s {
+            "HOME" => Ok("/home/max".to_string()),
+            "XDG_STATE_HOME" => Ok("/tmp/state".to_string()),
+            _ => Err(VarError::NotPresent),
+        });
+        assert_eq!(mgr.get_state_dir(), "/tmp/state");
+    }
+
     #[test]
     fn test_get_data_dirs_custom() {
         let home = "/home/max";
@@ -264,6 +1903,328 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_desktop_entry_map_stats() {
+        let fixture = Fixture::new("stats");
+        let app_dir = PathBuf::from(fixture.path("home/.local/share/applications"));
+        fixture.write("home/.local/share/applications/good.desktop", &entry("Good", "good", ""));
+        fixture.write("home/.local/share/applications/bad.desktop", "[Desktop Entry]\nName=Bad\n");
+        let mut env = fixture.env("C");
+        env.insert("XDG_DATA_DIRS".to_string(), fixture.path("nonexistent"));
+        let mgr = Fixture::manager_with_env(env);
+        let (apps, stats) = mgr.get_desktop_entry_map();
+        assert_eq!(apps.keys().collect::<Vec<_>>(), ["Good"]);
+        assert_eq!(stats, Stats {
+            dirs_scanned: 1, entries_scanned: 2, cache_hits: 0, cache_misses: 2, parse_failures: 1,
+            cache: CacheStats::default(),
+        });
+        let cache_len = fs::metadata(mgr.get_cache_file_path()).unwrap().len();
+        let (_, stats) = mgr.get_desktop_entry_map();
+        assert_eq!(stats, Stats {
+            dirs_scanned: 1, entries_scanned: 2, cache_hits: 1, cache_misses: 1, parse_failures: 1,
+            cache: CacheStats { entries_loaded: 1, version_mismatches: 0, stale_entries: 0, deleted_entries: 0, bytes_read: cache_len },
+        });
+        assert_eq!(mgr.profile().stats.entries_scanned, 4);
+        fs::write(app_dir.join("good.desktop"), "[Desktop Entry]\nType=Application\nName=Better\nExec=good\n").unwrap();
+        let (_, stats) = mgr.get_desktop_entry_map();
+        assert_eq!((stats.cache.entries_loaded, stats.cache.stale_entries), (1, 1));
+        // Deleting a file rewrites the cache without it
+        fs::remove_file(app_dir.join("good.desktop")).unwrap();
+        let (_, stats) = mgr.get_desktop_entry_map();
+        assert_eq!((stats.cache.entries_loaded, stats.cache.deleted_entries), (1, 1));
+        let (_, stats) = mgr.get_desktop_entry_map();
+        assert_eq!((stats.cache.entries_loaded, stats.cache.deleted_entries), (0, 0));
+        // A cache file from another version is ignored
+        fs::write(mgr.get_cache_file_path(), 1u32.to_le_bytes()).unwrap();
+        let (_, stats) = mgr.get_desktop_entry_map();
+        assert_eq!(stats.cache, CacheStats {
+            entries_loaded: 0, version_mismatches: 1, stale_entries: 0, deleted_entries: 0, bytes_read: 4,
+        });
+    }
+
+    #[test]
+    fn test_dir_mtime_short_circuit() {
+        let fixture = Fixture::new("dir-mtime");
+        let app_dir = PathBuf::from(fixture.path("usr/share/applications"));
+        fixture.write("usr/share/applications/a.desktop", &entry("A", "a", ""));
+        // Directories modified within the last seconds are not trusted
+        let hour_ago = std::time::SystemTime::now() - Duration::from_secs(3600);
+        fs::File::open(&app_dir).unwrap().set_modified(hour_ago).unwrap();
+        let mut env = fixture.env("C");
+        env.insert("XDG_DATA_HOME".to_string(), fixture.path("nonexistent"));
+        env.insert("XDG_DATA_DIRS".to_string(), fixture.path("usr/share"));
+        let mgr = Fixture::manager_with_env(env);
+        let names = || {
+            let (apps, stats) = mgr.get_desktop_entry_map();
+            let mut names: Vec<_> = apps.into_keys().collect();
+            names.sort();
+            (names, stats.cache_misses)
+        };
+        assert_eq!(names(), (vec!["A".to_string()], 1));
+        // Modifying a file in place goes unnoticed, since the directory
+        // is not read at all
+        fs::write(app_dir.join("a.desktop"), "[Desktop Entry]\nType=Application\nName=Changed\nExec=a\n").unwrap();
+        assert_eq!(names(), (vec!["A".to_string()], 0));
+        // Adding a file changes the mtime of the directory
+        fs::write(app_dir.join("b.desktop"), "[Desktop Entry]\nType=Application\nName=B\nExec=b\n").unwrap();
+        assert_eq!(names(), (vec!["B".to_string(), "Changed".to_string()], 2));
+    }
+
+    #[cfg(feature = "launcher")]
+    #[test]
+    fn test_config_dirs() {
+        let fixture = Fixture::new("config-dirs");
+        fixture.write("xdg/menus/gnome-applications.menu", "<Menu><Name>Applications</Name></Menu>");
+        let mut env = fixture.env("C");
+        env.insert("XDG_CONFIG_DIRS".to_string(), format!("{}:{}", fixture.path("nonexistent"), fixture.path("xdg")));
+        env.insert("XDG_MENU_PREFIX".to_string(), "gnome-".to_string());
+        let mgr = Fixture::manager_with_env(env);
+        let (path, merge_dirs) = mgr.get_menu_file().unwrap();
+        assert_eq!(path, fixture.path("xdg/menus/gnome-applications.menu"));
+        assert_eq!(merge_dirs, [
+            fixture.path("home/.config/menus/applications-merged"),
+            fixture.path("nonexistent/menus/applications-merged"),
+            fixture.path("xdg/menus/applications-merged"),
+        ]);
+        assert_eq!(mgr.get_autostart_dirs()[2], fixture.path("xdg/autostart"));
+    }
+
+    #[test]
+    fn test_origins() {
+        let fixture = Fixture::new("origins");
+        fixture.write("flatpak/exports/share/applications/org.mozilla.firefox.desktop", &entry("Firefox", "firefox", ""));
+        fixture.write("usr/share/applications/firefox.desktop", &entry("Firefox", "firefox", ""));
+        let mut env = fixture.env("C");
+        env.insert("XDG_DATA_HOME".to_string(), fixture.path("nonexistent"));
+        env.insert("XDG_DATA_DIRS".to_string(), format!("{}:{}", fixture.path("flatpak/exports/share"), fixture.path("usr/share")));
+        let mut mgr = Fixture::manager_with_env(env);
+        let origins = |mgr: &XDGManager<_>| {
+            let mut origins: Vec<_> = mgr.get_desktop_entry_map().0.into_iter()
+                .map(|(name, app)| (name, app.source.origin))
+                .collect();
+            origins.sort_by(|a, b| a.0.cmp(&b.0));
+            origins
+        };
+        assert_eq!(origins(&mgr), [
+            ("Firefox".to_string(), Origin::Flatpak),
+            ("Firefox (System)".to_string(), Origin::System),
+        ]);
+        mgr.skip_origins([Origin::Flatpak]);
+        assert_eq!(origins(&mgr), [("Firefox".to_string(), Origin::System)]);
+    }
+
+    #[test]
+    fn test_try_exec() {
+        let fixture = Fixture::new("try-exec");
+        fixture.write("home/.local/share/applications/a.desktop", &entry("A", "sh -c a", "TryExec=sh\n"));
+        fixture.write("home/.local/share/applications/b.desktop", &entry("B", "b", "TryExec=/nonexistent/b\n"));
+        let mut mgr = fixture.manager("C");
+        #[cfg(feature = "launcher")]
+        let config = Config { mode: Mode::Menu, ..Config::default() };
+        // Menu mode lists the same entries as the scan
+        let names = |mgr: &XDGManager<_>| {
+            let mut names: Vec<_> = mgr.get_desktop_entry_map().0.into_keys().collect();
+            names.sort();
+            #[cfg(feature = "launcher")]
+            {
+                let mut menu_names: Vec<_> = mgr.list_menu_entries(&config).into_iter().map(|app| app.keys.Name).collect();
+                menu_names.sort();
+                assert_eq!(menu_names, names);
+            }
+            names
+        };
+        assert_eq!(names(&mgr), ["A"]);
+        #[cfg(feature = "launcher")]
+        assert!(matches!(mgr.launch_by_id("b", &[], &config), Err(ChildProcessError::LaunchFailed(_))));
+        mgr.set_check_try_exec(false);
+        assert_eq!(names(&mgr), ["A", "B"]);
+        // B's Exec is not installed either
+        mgr.set_verify_exec(true);
+        assert_eq!(names(&mgr), ["A"]);
+    }
+
+    #[test]
+    fn test_cache_validation() {
+        let fixture = Fixture::new("validation");
+        let app_dir = PathBuf::from(fixture.path("home/.local/share/applications"));
+        let path = app_dir.join("a.desktop");
+        let mgr = fixture.manager("C");
+        let name = || mgr.get_desktop_entry_map().0.into_keys().next().unwrap();
+        // Rewrites the file, keeping its mtime
+        let write = |name: &str, replace: bool| {
+            let mtime = fs::metadata(&path).map(|metadata| metadata.modified().unwrap());
+            let contents = entry(name, "a", "");
+            if replace {
+                fs::write(app_dir.join("a.tmp"), contents).unwrap();
+                fs::rename(app_dir.join("a.tmp"), &path).unwrap();
+            } else {
+                fs::write(&path, contents).unwrap();
+            }
+            if let Ok(mtime) = mtime {
+                fs::File::options().write(true).open(&path).unwrap().set_modified(mtime).unwrap();
+            }
+        };
+        write("A", false);
+        assert_eq!(name(), "A");
+        // Another size
+        write("AB", false);
+        assert_eq!(name(), "AB");
+        // Another inode
+        write("CD", true);
+        assert_eq!(name(), "CD");
+    }
+
+    #[test]
+    fn test_cache_pruning() {
+        let fixture = Fixture::new("pruning");
+        fixture.write("home/.local/share/applications/a.desktop", &entry("A", "a", ""));
+        fixture.write("home/.local/share/applications/b.desktop", &entry("B", "b", ""));
+        let mgr = fixture.manager("C");
+        let cached_ids = || {
+            let (_, apps, _) = desktop_entry_cache::read_cache_file(&mgr.get_cache_file_path()).unwrap().unwrap();
+            let mut ids: Vec<_> = apps.into_iter().map(|app| app.source.id).collect();
+            ids.sort();
+            ids
+        };
+        mgr.get_desktop_entry_map();
+        assert_eq!(cached_ids(), ["a.desktop", "b.desktop"]);
+        // Only a deletion, which still rewrites the cache
+        fs::remove_file(fixture.path("home/.local/share/applications/b.desktop")).unwrap();
+        mgr.get_desktop_entry_map();
+        assert_eq!(cached_ids(), ["a.desktop"]);
+    }
+
+    #[test]
+    fn test_find_desktop_files() {
+        let root = std::env::temp_dir().join(format!("i3-dmenu-desktop-rs-find-{}", std::process::id()));
+        fs::create_dir_all(root.join("wine/Programs")).unwrap();
+        for file in ["a.desktop", "b.txt", "wine/Programs/Notepad.desktop"] {
+            fs::write(root.join(file), "").unwrap();
+        }
+        // A symlink back to a directory which was already read is skipped
+        std::os::unix::fs::symlink(&root, root.join("wine/loop")).unwrap();
+        let ids: Vec<_> = find_desktop_files(root.to_str().unwrap()).unwrap()
+            .into_iter().map(|(id, _)| id).collect();
+        assert_eq!(ids, ["a.desktop", "wine-Programs-Notepad.desktop"]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "launcher")]
+    fn test_pin_recent_apps() {
+        let app = DesktopEntry::parse_contents("[Desktop Entry]\nType=Application\nName=App\nExec=app\n",
+                                               "/a/app.desktop", SystemTime::UNIX_EPOCH, &[]).unwrap();
+        let app_map: HashMap<_, _> = ["a", "b", "c", "d"].into_iter()
+            .map(|name| {
+                let mut app = app.clone();
+                app.source.id = format!("{name}.desktop");
+                (name.to_string(), app)
+            })
+            .collect();
+        let mut history = History::default();
+        let now = SystemTime::now();
+        history.record("d.desktop", now);
+        history.record("b.desktop", now + Duration::from_secs(1));
+        history.record("c.desktop", now - Duration::from_secs(1));
+        assert_eq!(pin_recent_apps(vec!["a", "b", "c", "d"], &app_map, &history, 2),
+                   ["b", "d", RECENT_APPS_DIVIDER, "a", "c"]);
+        assert_eq!(pin_recent_apps(vec!["a"], &app_map, &History::default(), 2), ["a"]);
+    }
+
+    #[test]
+    #[cfg(feature = "launcher")]
+    fn test_find_app_by_name() {
+        let app = DesktopEntry::parse_contents("[Desktop Entry]\nType=Application\nName=App\nExec=app\n",
+                                               "/a/app.desktop", SystemTime::UNIX_EPOCH, &[]).unwrap();
+        let app_map: HashMap<_, _> = ["Firefox", "GIMP", "gimp"].into_iter()
+            .map(|name| {
+                let mut app = app.clone();
+                app.source.id = format!("{name}.desktop");
+                (name.to_string(), app)
+            })
+            .collect();
+        let find = |name, case_sensitive| find_app_by_name(&app_map, name, case_sensitive).map(|app| app.source.id.as_str());
+        assert_eq!(find("firefox", false), Some("Firefox.desktop"));
+        assert_eq!(find("firefox", true), None);
+        assert_eq!(find("gimp", false), Some("gimp.desktop"));
+        assert_eq!(find("Gimp", false), Some("GIMP.desktop"));
+        assert_eq!(find("Chromium", false), None);
+        // All of them run "app"
+        assert_eq!(find("app", false), None);
+        let neovim = DesktopEntry::parse_contents("[Desktop Entry]\nType=Application\nName=Neovim\nExec=/usr/bin/nvim %F\n",
+                                                  "/a/nvim.desktop", SystemTime::UNIX_EPOCH, &[]).unwrap();
+        let app_map: HashMap<_, _> = [("Neovim".to_string(), neovim), ("App".to_string(), app.clone())].into();
+        let find = |name, case_sensitive| find_app_by_name(&app_map, name, case_sensitive).map(|app| app.source.id.as_str());
+        assert_eq!(find("nvim", false), Some("nvim.desktop"));
+        assert_eq!(find("NVIM", false), Some("nvim.desktop"));
+        assert_eq!(find("NVIM", true), None);
+        assert_eq!(find("app", false), Some("app.desktop"));
+        assert_eq!(find("nvim.desktop", false), Some("nvim.desktop"));
+        assert_eq!(find("NVIM.desktop", false), None);
+        let app_map: HashMap<_, _> = [("Éditeur de texte".to_string(), app.clone())].into();
+        assert!(find_app_by_name(&app_map, "Editeur de texte", true).is_some());
+        assert!(find_app_by_name(&app_map, "editeur de texte", false).is_some());
+        // The same name in decomposed form
+        assert!(find_app_by_name(&app_map, "E\u{301}diteur de texte", true).is_some());
+        assert!(find_app_by_name(&app_map, "editeur de texte", true).is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "launcher")]
+    fn test_get_search_url() {
+        assert_eq!(get_search_url("https://duckduckgo.com/?q=%s", "rust lifetimes"),
+                   "https://duckduckgo.com/?q=rust%20lifetimes");
+        assert_eq!(get_search_url("https://example.com/search/%s", "a/b&c=ü"),
+                   "https://example.com/search/a%2Fb%26c%3D%C3%BC");
+    }
+
+    #[test]
+    #[cfg(feature = "launcher")]
+    fn test_get_openable_target() {
+        let home = std::env::temp_dir().join(format!("i3-dmenu-desktop-rs-openable-{}", std::process::id()));
+        fs::create_dir_all(home.join("Downloads")).unwrap();
+        fs::write(home.join("Downloads/report.pdf"), "").unwrap();
+        let home = home.to_str().unwrap().to_string();
+        let mgr = XDGManager::new(|key| match key {
+            "HOME" => Ok(home.clone()),
+            _ => Err(VarError::NotPresent),
+        });
+        let target = |input: &str| mgr.get_openable_target(input);
+        assert_eq!(target("https://example.com/a?b=c").as_deref(), Some("https://example.com/a?b=c"));
+        assert_eq!(target("mailto:someone@example.com").as_deref(), Some("mailto:someone@example.com"));
+        assert_eq!(target("~/Downloads/report.pdf"), Some(format!("{home}/Downloads/report.pdf")));
+        assert_eq!(target(&format!("{home}/Downloads")), Some(format!("{home}/Downloads")));
+        assert_eq!(target("~/Downloads/missing.pdf"), None);
+        assert_eq!(target("/bin/sh"), None);
+        assert_eq!(target("firefox https://example.com"), None);
+        assert_eq!(target("Downloads"), None);
+        fs::remove_dir_all(&home).unwrap();
+    }
+
+    #[test]
+    fn test_get_data_dirs_extra() {
+        let home = "/home/max";
+        let mut mgr = XDGManager::new(
+            |s| match s {
+                "HOME" => Ok(home.to_string()),
+                "XDG_DATA_DIRS" => Ok("/usr/share".to_string()),
+                _ => Err(VarError::NotPresent),
+            }
+        );
+        mgr.add_extra_data_dirs(["~/Applications/share".to_string(), "/opt/apps".to_string()]);
+        assert_eq!(
+            mgr.get_data_dirs(),
+            vec![
+                format!("{home}/.local/share"),
+                "/usr/share".to_string(),
+                format!("{home}/Applications/share"),
+                "/opt/apps".to_string(),
+            ]
+        );
+        mgr.skip_data_dirs(["/usr/share/".to_string(), "~/Applications/share".to_string()]);
+        assert_eq!(mgr.get_data_dirs(), vec![format!("{home}/.local/share"), "/opt/apps".to_string()]);
+    }
+
     #[test]
     fn test_locale_keys() {
         let test_cases = vec![
@@ -276,5 +2237,30 @@ mod tests {
         for (lc_messages, locale_keys) in test_cases {
             assert_eq!(get_locale_keys(lc_messages), locale_keys);
         }
+        assert_eq!(get_locale_keys_for_languages(&["de_AT", "en_GB", "de"]), ["de_AT", "de", "en_GB", "en"]);
+    }
+
+    #[test]
+    fn test_language() {
+        let env = |lang: &'static str, language: &'static str| move |s: &str| match s {
+            "HOME" => Ok("/home/user".to_string()),
+            "LANG" => Ok(lang.to_string()),
+            "LANGUAGE" if !language.is_empty() => Ok(language.to_string()),
+            _ => Err(VarError::NotPresent),
+        };
+        assert_eq!(XDGManager::new(env("fr_FR.UTF-8", "")).get_locale_keys(), ["fr_FR", "fr"]);
+        assert_eq!(XDGManager::new(env("fr_FR.UTF-8", "de:en")).get_locale_keys(), ["de", "en"]);
+        // LANGUAGE is ignored with the C locale
+        assert_eq!(XDGManager::new(env("C.UTF-8", "de:en")).get_locale_keys(), ["C"]);
+        let mut mgr = XDGManager::new(env("fr_FR.UTF-8", "de:en"));
+        mgr.set_locales(vec!["nl_BE".to_string()]);
+        assert_eq!(mgr.get_locale_keys(), ["nl_BE", "nl"]);
+    }
+
+    #[test]
+    #[cfg(feature = "launcher")]
+    fn test_unique_names() {
+        assert_eq!(unique_names(["Open", "New Window", "Open", "Open"]), ["Open", "New Window", "Open (2)", "Open (3)"]);
+        assert!(unique_names([]).is_empty());
     }
 }
@@ -0,0 +1,257 @@
+//! Checks desktop entry files for violations of
+//! https://specifications.freedesktop.org/desktop-entry-spec/latest/ which
+//! the parser tolerates.
+
+use std::collections::HashSet;
+use std::fs;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
+
+use super::desktop_entry::DesktopEntry;
+use super::utils::find_executable;
+
+const BOOLEAN_KEYS: [&str; 6] = ["NoDisplay", "Hidden", "DBusActivatable", "Terminal", "StartupNotify", "PrefersNonDefaultGPU"];
+const VALID_TYPES: [&str; 3] = ["Application", "Link", "Directory"];
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The file violates the spec, and other implementations may reject it
+    Error,
+    /// The file is valid, but probably does not do what its author intended
+    Warning,
+}
+
+/// A problem with a desktop entry file.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct LintIssue {
+    pub path: String,
+    /// The 1-based line number, if the problem is on a specific line
+    pub line: Option<usize>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl LintIssue {
+    /// Returns this issue as a single line of JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+struct Linter<'a> {
+    path: &'a str,
+    issues: Vec<LintIssue>,
+}
+
+impl Linter<'_> {
+    fn report(&mut self, line: Option<usize>, severity: Severity, message: String) {
+        self.issues.push(LintIssue { path: self.path.to_string(), line, severity, message });
+    }
+
+    fn check_exec(&mut self, line: usize, key: &str, value: &str) {
+        lazy_static! {
+            static ref FIELD_CODE: Regex = Regex::new("%(.?)").unwrap();
+        }
+        let mut file_codes = 0;
+        for captures in FIELD_CODE.captures_iter(value) {
+            let code = captures.get(1).unwrap().as_str();
+            match code {
+                "f" | "F" | "u" | "U" => file_codes += 1,
+                "i" | "c" | "k" | "%" => (),
+                "d" | "D" | "n" | "N" | "v" | "m" => self.report(
+                    Some(line), Severity::Warning, format!("{key} uses the deprecated field code %{code}")),
+                _ => self.report(Some(line), Severity::Error, format!("{key} uses the invalid field code %{code}")),
+            }
+        }
+        if file_codes > 1 {
+            self.report(Some(line), Severity::Error,
+                        format!("{key} has more than one of the field codes %f, %F, %u and %U"));
+        }
+        // Field codes are only expanded when they are arguments on their own
+        for word in value.split_whitespace() {
+            let embedded = FIELD_CODE.captures_iter(word)
+                .map(|captures| captures.get(0).unwrap().as_str())
+                .find(|code| *code != "%%" && *code != word);
+            if let Some(code) = embedded {
+                self.report(Some(line), Severity::Warning,
+                            format!("{key} has the field code {code} inside of the argument {word:?}, where it is not expanded"));
+            }
+        }
+        // Undo the general escape rule for strings first, which turns "\\\\"
+        // into the single backslash that escapes characters inside of quotes
+        let unescaped = value.replace(r"\\", "\\");
+        let mut in_quotes = false;
+        let mut chars = unescaped.chars();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '"' => in_quotes = !in_quotes,
+                '\\' if in_quotes => match chars.next() {
+                    Some('"' | '`' | '$' | '\\') => (),
+                    _ => {
+                        self.report(Some(line), Severity::Error,
+                                    format!("{key} has an escape sequence which is not allowed inside of quotes"));
+                        return;
+                    },
+                },
+                '`' | '$' if in_quotes => {
+                    self.report(Some(line), Severity::Error,
+                                format!("{key} has an unescaped '{ch}' inside of quotes"));
+                    return;
+                },
+                _ => (),
+            }
+        }
+        if in_quotes {
+            self.report(Some(line), Severity::Error, format!("{key} has an unterminated quoted argument"));
+        }
+    }
+}
+
+/// Returns the problems with the desktop entry file at `path`.
+///
+/// # Arguments
+///
+/// * `env_paths`: the directories in PATH, to check whether TryExec exists
+pub fn lint_file(path: &str, env_paths: &[String]) -> Vec<LintIssue> {
+    match fs::read_to_string(path) {
+        Ok(contents) => lint_contents(path, &contents, env_paths),
+        Err(err) => {
+            let mut linter = Linter { path, issues: Vec::new() };
+            linter.report(None, Severity::Error, format!("could not read file: {err}"));
+            linter.issues
+        },
+    }
+}
+
+/// Returns the problems with `contents`, which were read from `path`.
+fn lint_contents(path: &str, contents: &str, env_paths: &[String]) -> Vec<LintIssue> {
+    lazy_static! {
+        static ref KV_PAIR: Regex = Regex::new(r"^([A-Za-z0-9-]+)(\[[^]]+\])?\s*=\s*(.*)$").unwrap();
+    }
+    let mut linter = Linter { path, issues: Vec::new() };
+    let mut groups = HashSet::new();
+    let mut current_group: Option<String> = None;
+    let mut keys = HashSet::new();
+    // The values of the keys in the [Desktop Entry] group, with their line numbers
+    let mut main_keys: Vec<(String, String, usize)> = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line_no = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(group) = line.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            if current_group.is_none() && group != "Desktop Entry" {
+                linter.report(Some(line_no), Severity::Error,
+                              "the first group must be [Desktop Entry]".to_string());
+            }
+            if !groups.insert(group.to_string()) {
+                linter.report(Some(line_no), Severity::Error, format!("duplicate group [{group}]"));
+            }
+            current_group = Some(group.to_string());
+            keys.clear();
+            continue;
+        }
+        let Some(group) = &current_group else {
+            linter.report(Some(line_no), Severity::Error, "key outside of any group".to_string());
+            continue;
+        };
+        let Some(captures) = KV_PAIR.captures(line) else {
+            linter.report(Some(line_no), Severity::Error, "line is not a key-value pair".to_string());
+            continue;
+        };
+        let key = captures.get(1).unwrap().as_str();
+        let full_key = format!("{key}{}", captures.get(2).map_or("", |m| m.as_str()));
+        let value = captures.get(3).unwrap().as_str();
+        if !keys.insert(full_key.clone()) {
+            linter.report(Some(line_no), Severity::Error, format!("duplicate key {full_key}"));
+        }
+        if BOOLEAN_KEYS.contains(&key) && value != "true" && value != "false" {
+            linter.report(Some(line_no), Severity::Error,
+                          format!("{key} must be \"true\" or \"false\", not {value:?}"));
+        }
+        if key == "Exec" {
+            linter.check_exec(line_no, key, value);
+        }
+        if group == "Desktop Entry" && captures.get(2).is_none() {
+            main_keys.push((key.to_string(), value.to_string(), line_no));
+        }
+    }
+    if !groups.contains("Desktop Entry") {
+        linter.report(None, Severity::Error, "missing [Desktop Entry] group".to_string());
+        return linter.issues;
+    }
+    let get = |key: &str| main_keys.iter().find(|(k, _, _)| k == key).map(|(_, v, l)| (v.as_str(), *l));
+    for key in ["Type", "Name"] {
+        if get(key).is_none() {
+            linter.report(None, Severity::Error, format!("missing {key} key"));
+        }
+    }
+    match get("Type") {
+        Some((entry_type, line_no)) if !VALID_TYPES.contains(&entry_type) => {
+            linter.report(Some(line_no), Severity::Error, format!("unknown Type {entry_type:?}"));
+        },
+        // D-Bus activatable applications do not need an Exec key
+        Some(("Application", _)) if get("Exec").is_none() && get("DBusActivatable").map(|(v, _)| v) != Some("true") => {
+            linter.report(None, Severity::Error, "missing Exec key".to_string());
+        },
+        Some(("Link", _)) if get("URL").is_none() => {
+            linter.report(None, Severity::Error, "missing URL key".to_string());
+        },
+        _ => (),
+    }
+    if let Some((try_exec, line_no)) = get("TryExec") {
+        let arg0 = DesktopEntry::get_arg0(try_exec);
+        if !find_executable(&arg0, env_paths) {
+            linter.report(Some(line_no), Severity::Warning,
+                          format!("TryExec {arg0:?} is not installed, so the entry is ignored"));
+        }
+    }
+    linter.issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lint_str(contents: &str) -> Vec<(Option<usize>, Severity, String)> {
+        lint_contents("test.desktop", contents, &[])
+            .into_iter().map(|issue| (issue.line, issue.severity, issue.message)).collect()
+    }
+
+    #[test]
+    fn test_valid_entry() {
+        assert!(lint_str("[Desktop Entry]\nType=Application\nName=Foo\nExec=\"/opt/foo bar/foo\" \\\\$HOME %F\n").is_empty());
+    }
+
+    #[test]
+    fn test_embedded_field_code() {
+        assert_eq!(lint_str("[Desktop Entry]\nType=Application\nName=Foo\nExec=foo 100%% --file=%f\n"), [
+            (Some(4), Severity::Warning,
+             "Exec has the field code %f inside of the argument \"--file=%f\", where it is not expanded".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_lint_issues() {
+        let issues = lint_str("[Desktop Entry]
+Type=Application
+Exec=foo %x %u %F \"$HOME
+Terminal=yes
+Terminal=false
+TryExec=/nonexistent/foo
+");
+        assert_eq!(issues, [
+            (Some(3), Severity::Error, "Exec uses the invalid field code %x".to_string()),
+            (Some(3), Severity::Error, "Exec has more than one of the field codes %f, %F, %u and %U".to_string()),
+            (Some(3), Severity::Error, "Exec has an unescaped '$' inside of quotes".to_string()),
+            (Some(4), Severity::Error, "Terminal must be \"true\" or \"false\", not \"yes\"".to_string()),
+            (Some(5), Severity::Error, "duplicate key Terminal".to_string()),
+            (None, Severity::Error, "missing Name key".to_string()),
+            (Some(6), Severity::Warning, "TryExec \"/nonexistent/foo\" is not installed, so the entry is ignored".to_string()),
+        ]);
+    }
+}
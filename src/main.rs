@@ -1,8 +1,141 @@
+use std::fs;
+use std::io;
+use std::thread;
+use std::time::Instant;
+
+use clap::{CommandFactory, Parser};
+use signal_hook::consts::SIGUSR1;
+use signal_hook::iterator::Signals;
+
+use i3_dmenu_desktop_rs::app_launcher::ChildProcessError;
+use i3_dmenu_desktop_rs::cli::Cli;
+use i3_dmenu_desktop_rs::daemon::{self, Request, PROTOCOL_VERSION};
+use i3_dmenu_desktop_rs::extra_items::parse_extra_items;
+use i3_dmenu_desktop_rs::i18n::{self, tr};
+use i3_dmenu_desktop_rs::lint::Severity;
+use i3_dmenu_desktop_rs::session::SessionType;
 use i3_dmenu_desktop_rs::XDGManager;
 
 fn main() {
-    let mgr = XDGManager::new(|s| std::env::var(s));
-    if let Err(err) = mgr.start_app_launcher() {
-        eprintln!("{:?}", err);
+    let start = Instant::now();
+    i18n::init();
+    let mut cli = Cli::parse();
+    if let Some(arg0) = std::env::args_os().next().and_then(|arg0| arg0.into_string().ok()) {
+        cli.apply_program_name(&arg0);
+    }
+    let (dump_cache, lint, profile) = (cli.dump_cache, cli.lint, cli.profile);
+    // Items from stdin cannot be passed on to the daemon
+    let (run_daemon, client) = (cli.daemon, cli.client && !cli.stdin);
+    let (list_json, history_json, clear_history) = (cli.list_json, cli.history_json, cli.clear_history);
+    let diagnostics_json = cli.diagnostics_json;
+    if let Some(shell) = cli.completions {
+        clap_complete::generate(shell, &mut Cli::command(), "i3-dmenu-desktop-rs", &mut io::stdout());
+        return;
+    }
+    if let Some(ref key) = cli.print_keybinding {
+        let program = std::env::current_exe().ok()
+            .and_then(|path| path.to_str().map(String::from))
+            .unwrap_or_else(|| "i3-dmenu-desktop-rs".to_string());
+        println!("{}", cli.keybinding(key, &program, SessionType::detect(|s| std::env::var(s))));
+        return;
+    }
+    let mut mgr = XDGManager::new(|s| std::env::var(s));
+    if cli.stdin {
+        match io::read_to_string(io::stdin()) {
+            Ok(input) => mgr.add_extra_items(parse_extra_items(&input)),
+            Err(err) => eprintln!("i3-dmenu-desktop-rs: {}", tr("could not read stdin: {}", &[&err])),
+        }
+    }
+    let mut config = mgr.load_config();
+    let args = cli.to_args();
+    cli.apply_to(&mut config);
+    mgr.add_extra_data_dirs(config.extra_data_dirs.clone());
+    mgr.skip_data_dirs(config.skip_data_dirs.clone());
+    mgr.skip_origins(config.skip_origins.iter().copied());
+    mgr.set_locales(config.locales.clone());
+    mgr.add_appimage_dirs(config.appimage_dirs.clone());
+    mgr.set_persistence(config.persistence());
+    mgr.set_cache_key(config.cache_key);
+    mgr.set_check_try_exec(config.check_try_exec);
+    mgr.set_verify_exec(config.verify_exec);
+    if dump_cache {
+        print!("{}", mgr.dump_cache());
+        return;
+    }
+    if list_json {
+        for app in mgr.list_desktop_entries() {
+            println!("{}", app.to_json());
+        }
+        return;
+    }
+    if diagnostics_json {
+        for diagnostic in mgr.diagnose_desktop_entries(&config) {
+            println!("{}", diagnostic.to_json());
+        }
+        return;
+    }
+    if history_json {
+        for record in mgr.load_history().records() {
+            println!("{}", record.to_json());
+        }
+        return;
+    }
+    if clear_history {
+        if let Err(err) = mgr.clear_history() {
+            eprintln!("i3-dmenu-desktop-rs: {}", tr("could not delete the launch history: {}", &[&err]));
+            std::process::exit(1);
+        }
+        return;
+    }
+    if lint {
+        let issues = mgr.lint_desktop_entries();
+        for issue in &issues {
+            println!("{}", issue.to_json());
+        }
+        if issues.iter().any(|issue| issue.severity == Severity::Error) {
+            std::process::exit(1);
+        }
+        return;
+    }
+    if run_daemon {
+        if let Err(err) = daemon::run(mgr, config) {
+            eprintln!("i3-dmenu-desktop-rs: {}", tr("could not run the daemon: {}", &[&err]));
+            std::process::exit(1);
+        }
+        return;
+    }
+    if client {
+        // Without a daemon, the menu is shown directly below
+        let request = Request { version: PROTOCOL_VERSION, args };
+        if let Ok(response) = daemon::send_request(&mgr.get_daemon_socket_path(), &request) {
+            if let Some(error) = response.error {
+                eprintln!("i3-dmenu-desktop-rs: {}", error);
+            }
+            std::process::exit(response.status);
+        }
+    }
+    // Instead of being killed by SIGUSR1 (which reloads the daemon), make the
+    // next run parse all desktop entry files again
+    let cache_file = mgr.get_cache_file_path();
+    match Signals::new([SIGUSR1]) {
+        Ok(mut signals) => {
+            thread::spawn(move || {
+                for _ in signals.forever() {
+                    let _ = fs::remove_file(&cache_file);
+                }
+            });
+        },
+        Err(err) => eprintln!("i3-dmenu-desktop-rs: {}", tr("could not handle SIGUSR1: {}", &[&err])),
+    }
+    let result = mgr.start_app_launcher(&config);
+    if profile {
+        eprintln!("{}", mgr.profile());
+        eprintln!("total time:          {:?}", start.elapsed());
+    }
+    if let Err(err) = result {
+        if !matches!(err, ChildProcessError::Cancelled) {
+            eprintln!("i3-dmenu-desktop-rs: {}", err);
+        }
+        std::process::exit(err.exit_code());
     }
 }
@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+use std::process::Command;
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use super::config::Config;
+use super::i18n::tr;
+use super::utils::{find_executable, log_info};
+pub use super::utils::sanitize;
+
+/// A single row of the menu.
+pub struct MenuItem<'a> {
+    /// Which provider the row comes from, e.g. "run", shown before the name
+    pub prefix: Option<&'a str>,
+    pub name: &'a str,
+    pub comment: Option<&'a str>,
+    pub icon: Option<&'a str>,
+    /// A short marker shown after the name, e.g. how often the entry was
+    /// launched
+    pub badge: Option<&'a str>,
+}
+
+/// A program which reads newline-separated choices from stdin and prints the
+/// selected one to stdout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MenuProgram {
+    Dmenu,
+    Rofi,
+    Bemenu,
+    Fuzzel,
+    Wofi,
+    Tofi,
+}
+
+impl MenuProgram {
+    /// The name of the executable.
+    pub fn program_name(self) -> &'static str {
+        match self {
+            Self::Dmenu => "dmenu",
+            Self::Rofi => "rofi",
+            Self::Bemenu => "bemenu",
+            Self::Fuzzel => "fuzzel",
+            Self::Wofi => "wofi",
+            Self::Tofi => "tofi",
+        }
+    }
+
+    /// Returns the first program in `candidates` which is installed.
+    pub fn find_installed(candidates: &[Self], env_paths: &[String]) -> Option<Self> {
+        let menu = candidates.iter()
+            .find(|menu| find_executable(menu.program_name(), env_paths))
+            .copied()?;
+        if Some(&menu) != candidates.first() {
+            log_info(&tr("{} is not installed, using {} instead",
+                         &[&candidates[0].program_name(), &menu.program_name()]));
+        }
+        Some(menu)
+    }
+
+    /// Returns a command which runs the program in its dmenu-compatible mode,
+    /// matching case-insensitively unless `config.case_sensitive` is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `config`: the settings for the menu program
+    /// * `prompt`: the prompt to show, which depends on what the user is
+    ///   being asked to choose
+    pub fn command(self, config: &Config, prompt: Option<&str>) -> Command {
+        let mut command = Command::new(self.program_name());
+        match self {
+            Self::Dmenu | Self::Bemenu => &mut command,
+            // Print the index of the selected row, see Self::prints_index
+            Self::Rofi => command.args(["-dmenu", "-format", "i s"]),
+            Self::Fuzzel => command.arg("--dmenu"),
+            Self::Wofi => command.args(["--dmenu", "--allow-images"]),
+            // tofi reads from stdin by default, but unlike the other menus,
+            // it does not print the input if it does not match any entry.
+            Self::Tofi => command.arg(format!("--require-match={}", !config.allow_arbitrary_input)),
+        };
+        match self {
+            Self::Dmenu | Self::Bemenu | Self::Rofi | Self::Wofi if !config.case_sensitive => command.arg("-i"),
+            // rofi's default can be changed in its config file
+            Self::Rofi => command.arg("-case-sensitive"),
+            // fuzzel and tofi always match case-insensitively
+            _ => &mut command,
+        };
+        if self == Self::Rofi && !config.allow_arbitrary_input {
+            command.arg("-no-custom");
+        }
+        if let Some(prompt) = prompt {
+            match self {
+                Self::Dmenu | Self::Rofi | Self::Bemenu => command.arg("-p").arg(prompt),
+                Self::Fuzzel | Self::Wofi => command.arg(format!("--prompt={prompt}")),
+                Self::Tofi => command.arg(format!("--prompt-text={prompt}")),
+            };
+        }
+        command.args(self.layout_args(config.lines, config.bottom));
+        command.args(&config.menu_args);
+        command
+    }
+
+    /// Returns the arguments which make the menu show `lines` rows (if set)
+    /// as a vertical list, at the bottom of the screen if `bottom` is true.
+    pub fn layout_args(self, lines: Option<usize>, bottom: bool) -> Vec<String> {
+        let mut args = Vec::new();
+        match self {
+            Self::Dmenu | Self::Bemenu => {
+                if let Some(lines) = lines {
+                    args.extend(["-l".to_string(), lines.to_string()]);
+                }
+                if bottom {
+                    args.push("-b".to_string());
+                }
+            },
+            Self::Rofi => {
+                // -lines and -location are deprecated in favor of the theme
+                let mut theme = Vec::new();
+                if let Some(lines) = lines {
+                    theme.push(format!("listview {{ lines: {}; }}", lines));
+                }
+                if bottom {
+                    theme.push("window { location: south; anchor: south; }".to_string());
+                }
+                if !theme.is_empty() {
+                    args.extend(["-theme-str".to_string(), theme.join(" ")]);
+                }
+            },
+            Self::Fuzzel | Self::Wofi | Self::Tofi => {
+                if let Some(lines) = lines {
+                    let option = if self == Self::Tofi { "--num-results" } else { "--lines" };
+                    args.push(format!("{}={}", option, lines));
+                }
+                if bottom {
+                    let option = if self == Self::Wofi { "--location" } else { "--anchor" };
+                    args.push(format!("{}=bottom", option));
+                }
+            },
+        }
+        args
+    }
+
+    /// Whether the menu prints the index of the selected row followed by a
+    /// space and its text, rather than just the text. The index is -1 if
+    /// the user typed something which is not in the list.
+    ///
+    /// Matching the selection by index keeps working no matter how the rows
+    /// are decorated (comments, icons, markup).
+    pub fn prints_index(self) -> bool {
+        self == Self::Rofi
+    }
+
+    /// Returns the arguments which make the menu highlight the rows which
+    /// have a badge. Only rofi supports this (as "active" rows, which
+    /// themes can style with `element normal.active`).
+    pub fn highlight_args(self, items: &[MenuItem]) -> Vec<String> {
+        let rows: Vec<_> = items.iter().enumerate()
+            .filter(|(_, item)| item.badge.is_some())
+            .map(|(i, _)| i.to_string())
+            .collect();
+        if self != Self::Rofi || rows.is_empty() {
+            return Vec::new();
+        }
+        vec!["-a".to_string(), rows.join(",")]
+    }
+
+    /// Whether [`MenuItem::icon`] must be the path of an image file rather
+    /// than an icon name.
+    pub fn needs_icon_paths(self) -> bool {
+        self == Self::Wofi
+    }
+
+    /// Returns the line which should be written to the menu's stdin for
+    /// `item`, along with the text which the menu prints when that row is
+    /// selected.
+    pub fn format_item(self, item: &MenuItem) -> (String, String) {
+        let name = match item.prefix {
+            Some(prefix) => format!("{}: {}", sanitize(prefix), sanitize(item.name)),
+            None => sanitize(item.name),
+        };
+        let name = &match item.badge {
+            Some(badge) => format!("{} [{}]", name, sanitize(badge)),
+            None => name,
+        };
+        let comment = item.comment.map(sanitize);
+        let icon = item.icon.map(sanitize);
+        match self {
+            Self::Fuzzel => {
+                let label = match comment {
+                    Some(comment) => format!("{} - {}", name, comment),
+                    None => name.to_string(),
+                };
+                // See the "dmenu" section in man:fuzzel(1)
+                let line = match icon {
+                    Some(icon) => format!("{}\0icon\x1f{}", label, icon),
+                    None => label.clone(),
+                };
+                (line, label)
+            },
+            Self::Wofi => {
+                // wofi only accepts image paths, not icon names, and prints
+                // the whole line (including the markup) when it is selected.
+                let line = match icon {
+                    Some(icon) => format!("img:{}:text:{}", icon, name),
+                    None => name.to_string(),
+                };
+                (line.clone(), line)
+            },
+            _ => (name.to_string(), name.to_string()),
+        }
+    }
+
+    /// Returns the menu's input, along with a map from the text which the
+    /// menu prints for each row back to the name of the item.
+    pub fn format_items<'a>(self, items: &[MenuItem<'a>]) -> (String, HashMap<String, &'a str>) {
+        let mut lines = Vec::new();
+        let mut names_by_output = HashMap::new();
+        for item in items {
+            let (line, output) = self.format_item(item);
+            lines.push(line);
+            names_by_output.insert(output, item.name);
+        }
+        (lines.join("\n"), names_by_output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_prompt() {
+        let config = Config::default();
+        let command = MenuProgram::Dmenu.command(&config, Some("run:"));
+        assert_eq!(command.get_args().collect::<Vec<_>>(), ["-i", "-p", "run:"]);
+        let command = MenuProgram::Tofi.command(&config, Some("run:"));
+        assert_eq!(command.get_args().collect::<Vec<_>>(), ["--require-match=false", "--prompt-text=run:"]);
+    }
+
+    #[test]
+    fn test_command_case_sensitive() {
+        let config = Config { case_sensitive: true, ..Config::default() };
+        let command = MenuProgram::Dmenu.command(&config, None);
+        assert_eq!(command.get_args().count(), 0);
+        let command = MenuProgram::Rofi.command(&config, None);
+        assert_eq!(command.get_args().collect::<Vec<_>>(), ["-dmenu", "-format", "i s", "-case-sensitive"]);
+    }
+
+    #[test]
+    fn test_layout_args() {
+        assert_eq!(MenuProgram::Dmenu.layout_args(Some(20), true), ["-l", "20", "-b"]);
+        assert_eq!(MenuProgram::Rofi.layout_args(Some(20), true),
+                   ["-theme-str", "listview { lines: 20; } window { location: south; anchor: south; }"]);
+        assert_eq!(MenuProgram::Tofi.layout_args(Some(20), false), ["--num-results=20"]);
+        assert_eq!(MenuProgram::Wofi.layout_args(None, true), ["--location=bottom"]);
+        assert!(MenuProgram::Fuzzel.layout_args(None, false).is_empty());
+        let config = Config { lines: Some(5), menu_args: vec!["-l".to_string(), "10".to_string()], ..Config::default() };
+        // The menu arguments come last, so that they take precedence
+        let command = MenuProgram::Bemenu.command(&config, None);
+        assert_eq!(command.get_args().collect::<Vec<_>>(), ["-i", "-l", "5", "-l", "10"]);
+    }
+
+    #[test]
+    fn test_format_items_prefix() {
+        let items = [
+            MenuItem { prefix: Some("drun"), name: "Firefox", comment: None, icon: None, badge: Some("12") },
+            MenuItem { prefix: Some("run"), name: "firefox", comment: None, icon: None, badge: None },
+        ];
+        let (input, names_by_output) = MenuProgram::Dmenu.format_items(&items);
+        assert_eq!(input, "drun: Firefox [12]\nrun: firefox");
+        assert_eq!(names_by_output["drun: Firefox [12]"], "Firefox");
+        assert_eq!(names_by_output["run: firefox"], "firefox");
+    }
+
+    #[test]
+    fn test_format_items_fuzzel() {
+        let items = [
+            MenuItem { prefix: None, name: "Firefox", comment: Some("Browse the Web"), icon: Some("firefox"), badge: None },
+            MenuItem { prefix: None, name: "xterm", comment: None, icon: None, badge: None },
+        ];
+        let (input, names_by_output) = MenuProgram::Fuzzel.format_items(&items);
+        assert_eq!(input, "Firefox - Browse the Web\0icon\x1ffirefox\nxterm");
+        assert_eq!(names_by_output["Firefox - Browse the Web"], "Firefox");
+        assert_eq!(names_by_output["xterm"], "xterm");
+    }
+
+    #[test]
+    fn test_format_items_wofi() {
+        let items = [
+            MenuItem { prefix: None, name: "Firefox", comment: None, icon: Some("/usr/share/pixmaps/firefox.png"), badge: None },
+            MenuItem { prefix: None, name: "xterm", comment: None, icon: None, badge: None },
+        ];
+        let (input, names_by_output) = MenuProgram::Wofi.format_items(&items);
+        assert_eq!(input, "img:/usr/share/pixmaps/firefox.png:text:Firefox\nxterm");
+        assert_eq!(names_by_output["img:/usr/share/pixmaps/firefox.png:text:Firefox"], "Firefox");
+        assert_eq!(names_by_output["xterm"], "xterm");
+    }
+
+    #[test]
+    fn test_format_items_sanitized() {
+        let items = [
+            MenuItem { prefix: None, name: "Evil\nxterm", comment: Some("a\x1fb\0"), icon: None, badge: None },
+        ];
+        let (input, _) = MenuProgram::Fuzzel.format_items(&items);
+        assert_eq!(input, "Evil xterm - a b");
+        let (input, names_by_output) = MenuProgram::Dmenu.format_items(&items);
+        assert_eq!(input, "Evil xterm");
+        assert_eq!(names_by_output["Evil xterm"], "Evil\nxterm");
+    }
+
+    #[test]
+    fn test_format_items_badge() {
+        let items = [
+            MenuItem { prefix: None, name: "Firefox", comment: None, icon: None, badge: Some("12") },
+            MenuItem { prefix: None, name: "xterm", comment: None, icon: None, badge: None },
+        ];
+        let (input, names_by_output) = MenuProgram::Dmenu.format_items(&items);
+        assert_eq!(input, "Firefox [12]\nxterm");
+        assert_eq!(names_by_output["Firefox [12]"], "Firefox");
+        assert!(MenuProgram::Dmenu.highlight_args(&items).is_empty());
+        assert_eq!(MenuProgram::Rofi.highlight_args(&items), ["-a", "0"]);
+    }
+}
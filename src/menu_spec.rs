@@ -0,0 +1,397 @@
+//! Support for the hierarchical application menus described in
+//! https://specifications.freedesktop.org/menu-spec/latest/.
+//!
+//! Only the parts of the spec which affect which entries appear in which
+//! menu are implemented. In particular, `<Move>` and `<Layout>` are ignored,
+//! and the entries always come from the regular XDG data directories rather
+//! than from `<AppDir>`.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::DesktopEntry;
+use super::utils::join_path;
+
+// Guards against MergeFile loops
+const MAX_MERGE_DEPTH: u32 = 8;
+
+#[derive(Debug)]
+pub enum MenuSpecError {
+    IoError(io::Error),
+    ParseError(String),
+}
+
+impl From<io::Error> for MenuSpecError {
+    fn from(error: io::Error) -> Self { Self::IoError(error) }
+}
+
+impl From<roxmltree::Error> for MenuSpecError {
+    fn from(error: roxmltree::Error) -> Self { Self::ParseError(error.to_string()) }
+}
+
+impl fmt::Display for MenuSpecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::IoError(err) => write!(f, "{err}"),
+            Self::ParseError(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+/// A matching rule from an `<Include>` or `<Exclude>` element.
+#[derive(Debug, Clone, PartialEq)]
+enum Rule {
+    Filename(String),
+    Category(String),
+    All,
+    And(Vec<Rule>),
+    Or(Vec<Rule>),
+    Not(Vec<Rule>),
+}
+
+impl Rule {
+    fn matches(&self, app: &DesktopEntry) -> bool {
+        match self {
+            Self::Filename(id) => app.source.id == *id,
+            Self::Category(category) => app.keys.Categories.contains(category),
+            Self::All => true,
+            Self::And(rules) => rules.iter().all(|rule| rule.matches(app)),
+            Self::Or(rules) => rules.iter().any(|rule| rule.matches(app)),
+            Self::Not(rules) => !rules.iter().any(|rule| rule.matches(app)),
+        }
+    }
+
+    fn parse(node: roxmltree::Node) -> Option<Self> {
+        let children = || node.children().filter(|n| n.is_element()).filter_map(Self::parse).collect();
+        match node.tag_name().name() {
+            "Filename" => Some(Self::Filename(node.text()?.trim().to_string())),
+            "Category" => Some(Self::Category(node.text()?.trim().to_string())),
+            "All" => Some(Self::All),
+            "And" => Some(Self::And(children())),
+            "Or" => Some(Self::Or(children())),
+            "Not" => Some(Self::Not(children())),
+            _ => None,
+        }
+    }
+}
+
+/// A `<Menu>` element, after all of its `<MergeFile>` and `<MergeDir>`
+/// elements have been resolved.
+#[derive(Debug, Default)]
+pub struct Menu {
+    pub name: String,
+    /// The value of the last `<Directory>` element, i.e. the name of a
+    /// `.directory` file which describes this menu
+    pub directory: Option<String>,
+    pub submenus: Vec<Menu>,
+    /// The desktop file IDs of the entries in this menu, filled in by
+    /// [`Menu::allocate`]
+    pub app_ids: Vec<String>,
+    // (true for <Include>, false for <Exclude>, rule), in document order
+    rules: Vec<(bool, Rule)>,
+    deleted: bool,
+    only_unallocated: bool,
+}
+
+impl Menu {
+    /// Reads the menu file at `path`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: the path of the menu file, e.g. /etc/xdg/menus/applications.menu
+    /// * `merge_dirs`: the directories which `<DefaultMergeDirs>` refers to
+    pub fn load(path: &str, merge_dirs: &[String]) -> Result<Self, MenuSpecError> {
+        let mut menu = Self::default();
+        Self::merge_file(&mut menu, path, merge_dirs, 0)?;
+        menu.consolidate();
+        Ok(menu)
+    }
+
+    /// Parses the file at `path` and merges the contents of its root `<Menu>`
+    /// into `menu`.
+    fn merge_file(menu: &mut Self, path: &str, merge_dirs: &[String], depth: u32) -> Result<(), MenuSpecError> {
+        if depth > MAX_MERGE_DEPTH {
+            return Err(MenuSpecError::ParseError(format!("{path}: too many nested MergeFile elements")));
+        }
+        let contents = fs::read_to_string(path)?;
+        let doc = roxmltree::Document::parse(&contents)?;
+        let root = doc.root_element();
+        if root.tag_name().name() != "Menu" {
+            return Err(MenuSpecError::ParseError(format!("{path}: root element is not <Menu>")));
+        }
+        let base_dir = Path::new(path).parent().and_then(|p| p.to_str()).unwrap_or("/");
+        // The <Name> of a merged file's root menu is ignored
+        let name = std::mem::take(&mut menu.name);
+        menu.merge_element(root, base_dir, merge_dirs, depth)?;
+        if depth > 0 {
+            menu.name = name;
+        }
+        Ok(())
+    }
+
+    fn merge_dir(&mut self, dir: &str, merge_dirs: &[String], depth: u32) -> Result<(), MenuSpecError> {
+        let mut paths: Vec<_> = match fs::read_dir(dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok()?.path().to_str().map(|s| s.to_string()))
+                .filter(|path| path.ends_with(".menu"))
+                .collect(),
+            Err(_) => return Ok(()),
+        };
+        paths.sort();
+        for path in paths {
+            Self::merge_file(self, &path, merge_dirs, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    fn merge_element(
+        &mut self,
+        node: roxmltree::Node,
+        base_dir: &str,
+        merge_dirs: &[String],
+        depth: u32,
+    ) -> Result<(), MenuSpecError> {
+        let resolve = |path: &str| if path.starts_with('/') { path.to_string() } else { join_path(base_dir, path) };
+        for child in node.children().filter(|n| n.is_element()) {
+            let text = child.text().map(str::trim).unwrap_or("");
+            match child.tag_name().name() {
+                "Name" => self.name = text.to_string(),
+                "Directory" => self.directory = Some(text.to_string()),
+                "Deleted" => self.deleted = true,
+                "NotDeleted" => self.deleted = false,
+                "OnlyUnallocated" => self.only_unallocated = true,
+                "NotOnlyUnallocated" => self.only_unallocated = false,
+                "Include" | "Exclude" => {
+                    let include = child.tag_name().name() == "Include";
+                    // The children of <Include> and <Exclude> are implicitly OR'ed
+                    for rule in child.children().filter(|n| n.is_element()).filter_map(Rule::parse) {
+                        self.rules.push((include, rule));
+                    }
+                },
+                "Menu" => {
+                    let mut submenu = Self::default();
+                    submenu.merge_element(child, base_dir, merge_dirs, depth)?;
+                    self.submenus.push(submenu);
+                },
+                // type="parent" refers to the same file in a lower-precedence
+                // config directory, which we do not support.
+                "MergeFile" if child.attribute("type") != Some("parent") => {
+                    Self::merge_file(self, &resolve(text), merge_dirs, depth + 1)?;
+                },
+                "MergeDir" => self.merge_dir(&resolve(text), merge_dirs, depth)?,
+                "DefaultMergeDirs" => {
+                    for dir in merge_dirs {
+                        self.merge_dir(dir, merge_dirs, depth)?;
+                    }
+                },
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Merges submenus which have the same name, as required by the spec.
+    fn consolidate(&mut self) {
+        let mut merged: Vec<Menu> = Vec::new();
+        for submenu in self.submenus.drain(..) {
+            match merged.iter_mut().find(|m| m.name == submenu.name) {
+                Some(existing) => {
+                    // Later elements take precedence
+                    if submenu.directory.is_some() {
+                        existing.directory = submenu.directory;
+                    }
+                    existing.deleted = submenu.deleted;
+                    existing.only_unallocated = submenu.only_unallocated;
+                    existing.rules.extend(submenu.rules);
+                    existing.submenus.extend(submenu.submenus);
+                },
+                None => merged.push(submenu),
+            }
+        }
+        for submenu in &mut merged {
+            submenu.consolidate();
+        }
+        self.submenus = merged;
+    }
+
+    /// Fills in the entries of this menu and its submenus, then removes
+    /// deleted and empty submenus.
+    ///
+    /// # Arguments
+    ///
+    /// * `apps`: the entries which may appear in the menu
+    pub fn allocate(&mut self, apps: &[&DesktopEntry]) {
+        let mut allocated = HashSet::new();
+        self.allocate_pass(apps, false, &mut allocated);
+        self.allocate_pass(apps, true, &mut allocated);
+        self.prune();
+    }
+
+    fn allocate_pass(&mut self, apps: &[&DesktopEntry], only_unallocated: bool, allocated: &mut HashSet<String>) {
+        if self.only_unallocated == only_unallocated {
+            let mut ids = Vec::new();
+            for app in apps {
+                if only_unallocated && allocated.contains(&app.source.id) {
+                    continue;
+                }
+                // Later rules override earlier ones
+                let mut included = false;
+                for (include, rule) in &self.rules {
+                    if included != *include && rule.matches(app) {
+                        included = *include;
+                    }
+                }
+                if included {
+                    ids.push(app.source.id.clone());
+                }
+            }
+            if !only_unallocated {
+                allocated.extend(ids.iter().cloned());
+            }
+            self.app_ids = ids;
+        }
+        for submenu in &mut self.submenus {
+            submenu.allocate_pass(apps, only_unallocated, allocated);
+        }
+    }
+
+    fn prune(&mut self) {
+        for submenu in &mut self.submenus {
+            submenu.prune();
+        }
+        self.submenus.retain(|m| !m.deleted && !m.is_empty());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.app_ids.is_empty() && self.submenus.is_empty()
+    }
+}
+
+/// Returns a map of desktop file IDs to entries, for looking up the entries
+/// of a [`Menu`].
+pub fn index_by_id<'a>(apps: &[&'a DesktopEntry]) -> HashMap<&'a str, &'a DesktopEntry> {
+    apps.iter().map(|app| (app.source.id.as_str(), *app)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::desktop_entry::{DesktopEntryKeys, EntrySource, Origin};
+
+    fn parse_menu(xml: &str) -> Menu {
+        let doc = roxmltree::Document::parse(xml).unwrap();
+        let mut menu = Menu::default();
+        menu.merge_element(doc.root_element(), "/", &[], 0).unwrap();
+        menu.consolidate();
+        menu
+    }
+
+    #[test]
+    fn test_consolidate_and_rules() {
+        let menu = parse_menu(r#"
+            <Menu>
+              <Name>Applications</Name>
+              <Menu>
+                <Name>Development</Name>
+                <Include><Category>Development</Category></Include>
+              </Menu>
+              <Menu>
+                <Name>Development</Name>
+                <Directory>Development.directory</Directory>
+                <Exclude><And><Category>Qt</Category><Not><Filename>a.desktop</Filename></Not></And></Exclude>
+              </Menu>
+              <Menu>
+                <Name>Other</Name>
+                <OnlyUnallocated/>
+                <Include><All/></Include>
+              </Menu>
+            </Menu>
+        "#);
+        assert_eq!(menu.name, "Applications");
+        assert_eq!(menu.submenus.len(), 2);
+        let dev = &menu.submenus[0];
+        assert_eq!(dev.directory.as_deref(), Some("Development.directory"));
+        assert_eq!(dev.rules, vec![
+            (true, Rule::Category("Development".to_string())),
+            (false, Rule::And(vec![
+                Rule::Category("Qt".to_string()),
+                Rule::Not(vec![Rule::Filename("a.desktop".to_string())]),
+            ])),
+        ]);
+        assert!(menu.submenus[1].only_unallocated);
+    }
+
+    fn app(id: &str, categories: &[&str]) -> DesktopEntry {
+        DesktopEntry {
+            keys: DesktopEntryKeys {
+                Name: id.to_string(),
+                GenericName: None,
+                Exec: Some(id.to_string()),
+                TryExec: None,
+                Path: None,
+                Type: "Application".to_string(),
+                Comment: None,
+                Icon: None,
+                Categories: categories.iter().map(|s| s.to_string()).collect(),
+                Keywords: Vec::new(),
+                OnlyShowIn: Vec::new(),
+                NotShowIn: Vec::new(),
+                NoDisplay: false,
+                Hidden: false,
+                StartupNotify: None,
+                Terminal: false,
+                PrefersNonDefaultGPU: false,
+                SingleMainWindow: false,
+                StartupWMClass: None,
+                Actions: Vec::new(),
+            },
+            source: EntrySource {
+                location: format!("/usr/share/applications/{id}"),
+                id: id.to_string(),
+                mtime: std::time::SystemTime::UNIX_EPOCH,
+                size: 0,
+                inode: 0,
+                data_dir: String::new(),
+                origin: Origin::Unknown,
+            },
+            action: None,
+        }
+    }
+
+    #[test]
+    fn test_allocate() {
+        let mut menu = parse_menu(r#"
+            <Menu>
+              <Name>Applications</Name>
+              <Menu>
+                <Name>Development</Name>
+                <Include><Category>Development</Category></Include>
+                <Exclude><Filename>b.desktop</Filename></Exclude>
+              </Menu>
+              <Menu>
+                <Name>Empty</Name>
+                <Include><Category>Nothing</Category></Include>
+              </Menu>
+              <Menu>
+                <Name>Deleted</Name>
+                <Include><Filename>a.desktop</Filename></Include>
+                <Deleted/>
+              </Menu>
+              <Menu>
+                <Name>Other</Name>
+                <OnlyUnallocated/>
+                <Include><All/></Include>
+              </Menu>
+            </Menu>
+        "#);
+        let (a, b, c) = (app("a.desktop", &["Development"]), app("b.desktop", &["Development"]), app("c.desktop", &[]));
+        menu.allocate(&[&a, &b, &c]);
+        let names: Vec<_> = menu.submenus.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["Development", "Other"]);
+        assert_eq!(menu.submenus[0].app_ids, ["a.desktop"]);
+        assert_eq!(menu.submenus[1].app_ids, ["b.desktop", "c.desktop"]);
+    }
+}
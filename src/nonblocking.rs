@@ -0,0 +1,124 @@
+//! Async variants of scanning for and launching desktop entries, for
+//! embedding into programs which run on tokio. Parsing and the preparation
+//! of launches are shared with the blocking API; only the file system access
+//! and the waiting for processes differ.
+
+use std::collections::HashSet;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+
+#[cfg(feature = "launcher")]
+use super::app_launcher::{self, LaunchBackend};
+#[cfg(feature = "launcher")]
+use super::config::Config;
+use super::desktop_entry::{DesktopEntry, DesktopEntryError};
+use super::i18n::tr;
+use super::utils::{join_path, log_warn};
+
+/// Like the blocking `find_desktop_files`: returns the desktop file IDs and
+/// paths of the desktop entries in `app_dir` and its subdirectories, sorted
+/// by path. The walk itself is the blocking one, run on tokio's blocking
+/// thread pool like `tokio::fs` does for every call.
+pub async fn find_desktop_files(app_dir: &str) -> io::Result<Vec<(String, PathBuf)>> {
+    let app_dir = app_dir.to_string();
+    tokio::task::spawn_blocking(move || super::find_desktop_files(&app_dir)).await?
+}
+
+/// Like [`DesktopEntry::parse`], but reads the file asynchronously.
+pub async fn parse_desktop_entry(filepath: &str, locale_keys: &[String]) -> Result<DesktopEntry, DesktopEntryError> {
+    let contents = tokio::fs::read_to_string(filepath).await?;
+    let metadata = tokio::fs::metadata(filepath).await?;
+    let mut entry = DesktopEntry::parse_contents(&contents, filepath, metadata.modified()?, locale_keys)?;
+    entry.source.size = metadata.len();
+    entry.source.inode = metadata.ino();
+    Ok(entry)
+}
+
+/// Returns the desktop entries in the applications subdirectories of
+/// `data_dirs` (highest precedence first), except for those which are masked
+/// by an entry with the same desktop file ID. Unlike
+/// [`crate::XDGManager::get_desktop_entry_map`], this does not use the
+/// cache.
+///
+/// # Arguments
+///
+/// * `locale_keys`: the locales whose localized values are used, highest
+///   priority first
+pub async fn get_desktop_entries(
+    data_dirs: &[String],
+    locale_keys: &[String],
+) -> Vec<DesktopEntry> {
+    let mut apps = Vec::new();
+    let mut seen_ids = HashSet::new();
+    for data_dir in data_dirs {
+        let Ok(files) = find_desktop_files(&join_path(data_dir, "applications")).await else {
+            continue;
+        };
+        for (id, path) in files {
+            let Some(path_str) = path.to_str() else {
+                continue;
+            };
+            if seen_ids.contains(&id) {
+                continue;
+            }
+            match parse_desktop_entry(path_str, locale_keys).await {
+                Ok(mut app) => {
+                    app.escape_chars_for_exec_keys();
+                    app.source.id = id.clone();
+                    // Only the caller knows which one is $XDG_DATA_HOME,
+                    // which the origin depends on
+                    app.source.data_dir = data_dir.clone();
+                    seen_ids.insert(id);
+                    apps.push(app);
+                },
+                Err(err) => log_warn(&tr("Could not parse {}: {}", &[&path_str, &err])),
+            }
+        }
+    }
+    apps
+}
+
+/// Like [`app_launcher::launch_desktop_entry`], but waits for the helper
+/// which starts the application asynchronously.
+#[cfg(feature = "launcher")]
+pub async fn launch_desktop_entry(
+    app: &DesktopEntry,
+    extra_args: &[&str],
+    backend: LaunchBackend,
+    config: &Config,
+) -> Result<(), io::Error> {
+    app_launcher::prepare_desktop_entry(app, extra_args, backend, config).run_async().await
+}
+
+/// Like [`app_launcher::launch_cmd_without_desktop_entry`], but waits for
+/// the helper which starts the command asynchronously.
+#[cfg(feature = "launcher")]
+pub async fn launch_cmd_without_desktop_entry(cmd: &str, backend: LaunchBackend, config: &Config) -> Result<(), io::Error> {
+    app_launcher::prepare_cmd_without_desktop_entry(cmd, backend, config).run_async().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_get_desktop_entries() {
+        let root = std::env::temp_dir().join(format!("i3-dmenu-desktop-rs-nonblocking-{}", std::process::id()));
+        let (user_dir, system_dir) = (root.join("user"), root.join("system"));
+        for dir in [&user_dir, &system_dir] {
+            std::fs::create_dir_all(dir.join("applications/sub")).unwrap();
+        }
+        std::fs::write(user_dir.join("applications/a.desktop"),
+                       "[Desktop Entry]\nType=Application\nName=User A\nExec=a\n").unwrap();
+        std::fs::write(system_dir.join("applications/a.desktop"),
+                       "[Desktop Entry]\nType=Application\nName=System A\nExec=a\n").unwrap();
+        std::fs::write(system_dir.join("applications/sub/b.desktop"),
+                       "[Desktop Entry]\nType=Application\nName=B\nExec=b\n").unwrap();
+        let data_dirs = [user_dir, system_dir].map(|dir| dir.to_str().unwrap().to_string());
+        let apps = get_desktop_entries(&data_dirs, &[]).await;
+        std::fs::remove_dir_all(&root).unwrap();
+        let apps: Vec<_> = apps.iter().map(|app| (app.source.id.as_str(), app.keys.Name.as_str())).collect();
+        assert_eq!(apps, [("a.desktop", "User A"), ("sub-b.desktop", "B")]);
+    }
+}
@@ -0,0 +1,142 @@
+//! Writing user-level desktop entries, either new ones or copies which take
+//! precedence over the system-wide entries with the same desktop file ID.
+
+use std::fs;
+use std::io;
+
+use super::desktop_entry::DesktopEntry;
+use super::utils::join_path;
+
+/// Returns `contents` (the text of a desktop entry file) with `key` in the
+/// [Desktop Entry] group set to `value`. The key is added at the end of the
+/// group if it is not present yet.
+pub fn set_key(contents: &str, key: &str, value: &str) -> String {
+    let new_line = format!("{key}={value}");
+    let mut lines: Vec<String> = Vec::new();
+    let mut in_desktop_entry_section = false;
+    let mut replaced = false;
+    for line in contents.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            if in_desktop_entry_section && !replaced {
+                insert_before_blank_lines(&mut lines, new_line.clone());
+                replaced = true;
+            }
+            in_desktop_entry_section = trimmed == "[Desktop Entry]";
+        } else if in_desktop_entry_section
+            && trimmed.split_once('=').is_some_and(|(k, _)| k.trim_end() == key) {
+            if !replaced {
+                lines.push(new_line.clone());
+                replaced = true;
+            }
+            continue;
+        }
+        lines.push(line.to_string());
+    }
+    if !replaced {
+        if !in_desktop_entry_section {
+            lines.push("[Desktop Entry]".to_string());
+        }
+        insert_before_blank_lines(&mut lines, new_line);
+    }
+    lines.join("\n") + "\n"
+}
+
+// Keeps the blank lines which separate the group from the next one at the end
+fn insert_before_blank_lines(lines: &mut Vec<String>, line: String) {
+    let idx = lines.iter().rposition(|l| !l.trim().is_empty()).map_or(0, |i| i + 1);
+    lines.insert(idx, line);
+}
+
+/// Hides or unhides an entry for the current user by writing a copy of it,
+/// with the Hidden key set, to `user_dir` (e.g. ~/.local/share/applications
+/// or ~/.config/autostart).
+///
+/// Returns the path of the file which was written.
+pub fn set_hidden(app: &DesktopEntry, hidden: bool, user_dir: &str) -> io::Result<String> {
+    let contents = fs::read_to_string(&app.source.location)?;
+    let contents = set_key(&contents, "Hidden", if hidden { "true" } else { "false" });
+    fs::create_dir_all(user_dir)?;
+    let path = join_path(user_dir, &app.source.id);
+    fs::write(&path, contents)?;
+    Ok(path)
+}
+
+/// Returns `arg` as a quoted argument for the value of an Exec key.
+pub fn quote_exec_arg(arg: &str) -> String {
+    // See https://specifications.freedesktop.org/desktop-entry-spec/latest/exec-variables.html.
+    // Inside of a quoted argument, these characters must be escaped with a
+    // backslash, and then the backslashes must be escaped again because of
+    // the general escape rule for strings.
+    let mut quoted = String::from("\"");
+    for ch in arg.chars() {
+        match ch {
+            '"' | '`' | '$' => {
+                quoted.push_str(r"\\");
+                quoted.push(ch);
+            },
+            '\\' => quoted.push_str(r"\\\\"),
+            '%' => quoted.push_str("%%"),
+            '\n' => quoted.push_str(r"\n"),
+            _ => quoted.push(ch),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Returns the value of an Exec key which runs the shell command `cmd`.
+pub fn quote_exec_command(cmd: &str) -> String {
+    format!("sh -c {}", quote_exec_arg(cmd))
+}
+
+/// Writes a new entry named `name` which runs the shell command `cmd` to
+/// `user_dir`. The file name is derived from `name`, without overwriting
+/// any existing file.
+///
+/// Returns the path of the file which was written.
+pub fn create_entry(name: &str, cmd: &str, user_dir: &str) -> io::Result<String> {
+    let slug = name.to_ascii_lowercase()
+        .split(|ch: char| !ch.is_ascii_alphanumeric())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    let slug = if slug.is_empty() { "command" } else { &slug };
+    fs::create_dir_all(user_dir)?;
+    let mut path = join_path(user_dir, &format!("{slug}.desktop"));
+    let mut n = 2;
+    while fs::exists(&path)? {
+        path = join_path(user_dir, &format!("{slug}-{n}.desktop"));
+        n += 1;
+    }
+    let name = name.replace('\\', r"\\").replace('\n', r"\n");
+    let contents = format!("[Desktop Entry]\nType=Application\nName={name}\nExec={}\n", quote_exec_command(cmd));
+    fs::write(&path, contents)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_key() {
+        let contents = "[Desktop Entry]\nName=Applet\nHidden = false\n\n[Desktop Action foo]\nHidden=false\n";
+        assert_eq!(
+            set_key(contents, "Hidden", "true"),
+            "[Desktop Entry]\nName=Applet\nHidden=true\n\n[Desktop Action foo]\nHidden=false\n",
+        );
+        let contents = "[Desktop Entry]\nName=Applet\n\n[Desktop Action foo]\nName=Foo\n";
+        assert_eq!(
+            set_key(contents, "Hidden", "true"),
+            "[Desktop Entry]\nName=Applet\nHidden=true\n\n[Desktop Action foo]\nName=Foo\n",
+        );
+        assert_eq!(set_key("[Desktop Entry]\nName=Applet", "Hidden", "true"), "[Desktop Entry]\nName=Applet\nHidden=true\n");
+    }
+
+    #[test]
+    fn test_quote_exec_command() {
+        assert_eq!(quote_exec_command("echo hi"), r#"sh -c "echo hi""#);
+        assert_eq!(quote_exec_command(r#"echo "$HOME" 100% \n"#), r#"sh -c "echo \\"\\$HOME\\" 100%% \\\\n""#);
+    }
+}
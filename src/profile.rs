@@ -0,0 +1,92 @@
+use std::fmt;
+use std::time::Duration;
+
+/// Counters from scanning the applications directories.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// The number of applications directories which were read
+    pub dirs_scanned: usize,
+    /// The number of desktop entry files which were found
+    pub entries_scanned: usize,
+    /// The number of desktop entries which were up to date in the cache
+    pub cache_hits: usize,
+    /// The number of desktop entry files which had to be parsed
+    pub cache_misses: usize,
+    /// The number of desktop entry files which could not be parsed
+    pub parse_failures: usize,
+    pub cache: CacheStats,
+}
+
+impl Stats {
+    /// Adds the counters from `other` to these ones.
+    pub fn add(&mut self, other: &Stats) {
+        self.dirs_scanned += other.dirs_scanned;
+        self.entries_scanned += other.entries_scanned;
+        self.cache_hits += other.cache_hits;
+        self.cache_misses += other.cache_misses;
+        self.parse_failures += other.parse_failures;
+        self.cache.add(&other.cache);
+    }
+}
+
+/// How useful the desktop entry cache file was.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    /// The number of entries read from the cache file (not counting the
+    /// ones which the daemon keeps in memory)
+    pub entries_loaded: usize,
+    /// The number of times the cache file was ignored because it was
+    /// written in another format version
+    pub version_mismatches: usize,
+    /// The number of cached entries whose file changed since, so it had to
+    /// be parsed again
+    pub stale_entries: usize,
+    /// The number of cached entries whose file no longer exists (or is no
+    /// longer in a data directory)
+    pub deleted_entries: usize,
+    /// The size of the cache file, each time it was read
+    pub bytes_read: u64,
+}
+
+impl CacheStats {
+    /// Adds the counters from `other` to these ones.
+    pub fn add(&mut self, other: &CacheStats) {
+        self.entries_loaded += other.entries_loaded;
+        self.version_mismatches += other.version_mismatches;
+        self.stale_entries += other.stale_entries;
+        self.deleted_entries += other.deleted_entries;
+        self.bytes_read += other.bytes_read;
+    }
+}
+
+/// Where the time of a run was spent, for `--profile`.
+#[derive(Debug, Default, Clone)]
+pub struct Profile {
+    pub stats: Stats,
+    pub parse_time: Duration,
+    pub cache_read_time: Duration,
+    pub cache_write_time: Duration,
+    /// The time spent waiting for the user to choose something in the menu
+    pub menu_time: Duration,
+    /// The time spent starting the selected application
+    pub launch_time: Duration,
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "directories scanned: {}", self.stats.dirs_scanned)?;
+        writeln!(f, "files parsed:        {}", self.stats.cache_misses)?;
+        writeln!(f, "files from cache:    {}", self.stats.cache_hits)?;
+        writeln!(f, "parse failures:      {}", self.stats.parse_failures)?;
+        writeln!(f, "cache entries read:  {}", self.stats.cache.entries_loaded)?;
+        writeln!(f, "stale cache entries: {}", self.stats.cache.stale_entries)?;
+        writeln!(f, "deleted entries:     {}", self.stats.cache.deleted_entries)?;
+        writeln!(f, "version mismatches:  {}", self.stats.cache.version_mismatches)?;
+        writeln!(f, "cache bytes read:    {}", self.stats.cache.bytes_read)?;
+        writeln!(f, "parse time:          {:?}", self.parse_time)?;
+        writeln!(f, "cache read time:     {:?}", self.cache_read_time)?;
+        writeln!(f, "cache write time:    {:?}", self.cache_write_time)?;
+        writeln!(f, "menu wait time:      {:?}", self.menu_time)?;
+        write!(f, "launch time:         {:?}", self.launch_time)
+    }
+}
@@ -0,0 +1,405 @@
+//! Sources of menu rows besides the desktop entries, e.g. the executables in
+//! PATH. Each provider contributes rows which know what to do when they are
+//! selected, so that adding one does not require changes to the launcher.
+
+use std::fs;
+use std::io;
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime};
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::desktop_entry::quote_for_sh;
+use super::extra_items::ExtraItem;
+use super::i18n::{gettext, tr};
+use super::session::SessionType;
+use super::utils::{join_path, log_warn, sanitize, PathExecutables};
+
+/// The sources of menu rows which can be enabled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProviderKind {
+    /// The desktop entries of the installed applications
+    Drun,
+    /// The executables in PATH, like dmenu_run
+    Run,
+    /// The open windows (i3 and sway only), which get focused
+    Window,
+    /// The hosts in ~/.ssh/config, which are connected to in a terminal
+    Ssh,
+}
+
+impl ProviderKind {
+    /// Returns the provider of this kind. The desktop entries are listed by
+    /// the launcher itself, since typed names, aliases and the history refer
+    /// to them.
+    pub fn provider(self) -> Option<Box<dyn Provider>> {
+        match self {
+            Self::Drun => None,
+            Self::Run => Some(Box::new(Executables)),
+            Self::Window => Some(Box::new(Windows)),
+            Self::Ssh => Some(Box::new(SshHosts)),
+        }
+    }
+}
+
+/// What happens when a row is selected.
+pub enum Launch {
+    /// Run a shell command, like typed commands are run
+    Command(String),
+    /// Do something other than launching, e.g. focus a window
+    Action(Box<dyn Fn() -> io::Result<()>>),
+}
+
+/// A row of the menu which a provider contributes.
+pub struct ProvidedItem {
+    pub name: String,
+    pub comment: Option<String>,
+    pub icon: Option<String>,
+    pub launch: Launch,
+}
+
+/// What providers may need to know about the environment.
+pub struct ProviderContext<'a> {
+    pub home: &'a str,
+    pub env_paths: &'a [String],
+    pub session: SessionType,
+    /// The $XDG_CACHE_HOME directory
+    pub cache_dir: &'a str,
+    /// Whether providers may write to the cache (it is still read)
+    pub write_cache: bool,
+}
+
+/// The prefix of the rows of the desktop entries, see [`Provider::prefix`].
+pub const DRUN_PREFIX: &str = "drun";
+
+/// A source of menu rows.
+pub trait Provider {
+    /// Returns the prefix which tells the rows of this provider apart from
+    /// others, e.g. "run" for "run: htop", like in the combi mode of rofi.
+    fn prefix(&self) -> String;
+
+    fn items(&self, context: &ProviderContext) -> Vec<ProvidedItem>;
+}
+
+/// The items which were passed in with `--stdin`.
+pub struct ExtraItems<'a>(pub &'a [ExtraItem]);
+
+impl Provider for ExtraItems<'_> {
+    fn prefix(&self) -> String {
+        "item".to_string()
+    }
+
+    fn items(&self, _context: &ProviderContext) -> Vec<ProvidedItem> {
+        self.0.iter()
+            .map(|item| ProvidedItem {
+                name: item.name.clone(),
+                comment: item.comment.clone(),
+                icon: item.icon.clone(),
+                launch: Launch::Command(item.exec.clone()),
+            })
+            .collect()
+    }
+}
+
+/// The executables in PATH, by name. Ones which appear in several
+/// directories are listed once, and run whichever comes first in PATH.
+pub struct Executables;
+
+impl Provider for Executables {
+    fn prefix(&self) -> String {
+        "run".to_string()
+    }
+
+    fn items(&self, context: &ProviderContext) -> Vec<ProvidedItem> {
+        PathExecutables::new(context.env_paths).names()
+            .filter(|name| !name.chars().any(char::is_control))
+            .cloned()
+            .map(|name| ProvidedItem {
+                launch: Launch::Command(quote_for_sh(&name)),
+                name,
+                comment: None,
+                icon: Some("application-x-executable".to_string()),
+            })
+            .collect()
+    }
+}
+
+/// The open windows, from the layout tree of i3 or sway.
+pub struct Windows;
+
+impl Windows {
+    fn ipc_program(session: SessionType) -> Option<&'static str> {
+        match session {
+            SessionType::I3 => Some("i3-msg"),
+            SessionType::Sway => Some("swaymsg"),
+            SessionType::Wayland | SessionType::X11 => None,
+        }
+    }
+
+    /// Adds the windows in the subtree of `node` to `windows`, as their ID,
+    /// title and class (or app_id).
+    fn collect(node: &Value, windows: &mut Vec<(i64, String, Option<String>)>) {
+        let is_window = !node["window"].is_null() || !node["app_id"].is_null();
+        if let (true, Some(id), Some(title)) = (is_window, node["id"].as_i64(), node["name"].as_str()) {
+            let class = node["app_id"].as_str().or_else(|| node["window_properties"]["class"].as_str());
+            windows.push((id, title.to_string(), class.map(String::from)));
+        }
+        for key in ["nodes", "floating_nodes"] {
+            for child in node[key].as_array().into_iter().flatten() {
+                Self::collect(child, windows);
+            }
+        }
+    }
+}
+
+impl Provider for Windows {
+    fn prefix(&self) -> String {
+        "win".to_string()
+    }
+
+    fn items(&self, context: &ProviderContext) -> Vec<ProvidedItem> {
+        let Some(program) = Self::ipc_program(context.session) else {
+            log_warn(&gettext("Listing windows is only supported in i3 and sway"));
+            return Vec::new();
+        };
+        let output = Command::new(program).args(["-t", "get_tree"]).stderr(Stdio::inherit()).output();
+        let tree: Value = match output.map(|output| serde_json::from_slice(&output.stdout)) {
+            Ok(Ok(tree)) => tree,
+            Ok(Err(err)) => {
+                log_warn(&tr("Could not parse the tree of windows: {}", &[&err]));
+                return Vec::new();
+            },
+            Err(err) => {
+                log_warn(&tr("Could not run {}: {}", &[&program, &err]));
+                return Vec::new();
+            },
+        };
+        let mut windows = Vec::new();
+        Self::collect(&tree, &mut windows);
+        windows.into_iter()
+            .map(|(id, title, class)| ProvidedItem {
+                name: title,
+                icon: class.as_ref().map(|class| class.to_lowercase()),
+                comment: class,
+                launch: Launch::Action(Box::new(move || {
+                    let status = Command::new(program).arg(format!("[con_id={id}] focus"))
+                        .stdout(Stdio::null())
+                        .status()?;
+                    if !status.success() {
+                        return Err(io::Error::other(format!("{program} failed with {status}")));
+                    }
+                    Ok(())
+                })),
+            })
+            .collect()
+    }
+}
+
+/// The hosts in ~/.ssh/config, as "ssh <host>" rows which open in
+/// i3-sensible-terminal.
+pub struct SshHosts;
+
+impl SshHosts {
+    /// Returns the hosts of the `Host` lines in the contents of an ssh
+    /// config file, leaving out patterns (e.g. "*.example.com"). Included
+    /// files are not read.
+    fn parse_hosts(contents: &str) -> Vec<String> {
+        let mut hosts = Vec::new();
+        for line in contents.lines() {
+            let line = line.trim();
+            // The keyword is case-insensitive and may be followed by "="
+            let Some((keyword, args)) = line.split_once(|ch: char| ch.is_whitespace() || ch == '=') else {
+                continue;
+            };
+            if !keyword.eq_ignore_ascii_case("host") {
+                continue;
+            }
+            let args = args.trim_start_matches(|ch: char| ch.is_whitespace() || ch == '=');
+            for host in args.split_whitespace() {
+                if !host.is_empty() && !host.contains(['*', '?', '!']) && !hosts.iter().any(|other| other == host) {
+                    hosts.push(host.to_string());
+                }
+            }
+        }
+        hosts
+    }
+}
+
+impl Provider for SshHosts {
+    fn prefix(&self) -> String {
+        "ssh".to_string()
+    }
+
+    fn items(&self, context: &ProviderContext) -> Vec<ProvidedItem> {
+        let path = join_path(context.home, ".ssh/config");
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Vec::new(),
+            Err(err) => {
+                log_warn(&tr("Could not read {}: {}", &[&path, &err]));
+                return Vec::new();
+            },
+        };
+        Self::parse_hosts(&contents).into_iter()
+            .map(|host| {
+                // Like terminal entries, the terminal gets the whole command
+                // line as a single argument
+                let cmd = format!("ssh {}", quote_for_sh(&host));
+                ProvidedItem {
+                    launch: Launch::Command(format!("i3-sensible-terminal -e {}", quote_for_sh(&cmd))),
+                    name: cmd,
+                    comment: None,
+                    icon: Some("utilities-terminal".to_string()),
+                }
+            })
+            .collect()
+    }
+}
+
+/// An external program which lists rows, one per line, and gets run with
+/// the selected one as its last argument, e.g. to switch projects.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Script {
+    /// The command line for sh which prints the rows
+    pub command: String,
+    /// For how many seconds the rows are reused instead of running the
+    /// command again. Zero means that they are not cached.
+    #[serde(default)]
+    pub cache_secs: u64,
+    /// The icon of all rows
+    #[serde(default)]
+    pub icon: Option<String>,
+}
+
+/// The rows of a [`Script`] with the given name.
+pub struct ScriptProvider<'a> {
+    pub name: &'a str,
+    pub script: &'a Script,
+}
+
+impl ScriptProvider<'_> {
+    /// Returns the output of the script, from the cache if it is recent
+    /// enough.
+    fn get_output(&self, context: &ProviderContext) -> io::Result<String> {
+        let cache_path = join_path(context.cache_dir, &format!("i3-dmenu-desktop-rs/scripts/{}", self.name));
+        if self.script.cache_secs > 0 {
+            let max_age = Duration::from_secs(self.script.cache_secs);
+            let is_recent = fs::metadata(&cache_path).and_then(|metadata| metadata.modified())
+                .is_ok_and(|mtime| SystemTime::now().duration_since(mtime).is_ok_and(|age| age < max_age));
+            if is_recent {
+                if let Ok(output) = fs::read_to_string(&cache_path) {
+                    return Ok(output);
+                }
+            }
+        }
+        let output = Command::new("sh").arg("-c").arg(&self.script.command).stderr(Stdio::inherit()).output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!("the command failed with {}", output.status)));
+        }
+        let output = String::from_utf8_lossy(&output.stdout).into_owned();
+        if self.script.cache_secs > 0 && context.write_cache {
+            let result = fs::create_dir_all(join_path(context.cache_dir, "i3-dmenu-desktop-rs/scripts"))
+                .and_then(|_| fs::write(&cache_path, &output));
+            if let Err(err) = result {
+                log_warn(&tr("Could not write {}: {}", &[&cache_path, &err]));
+            }
+        }
+        Ok(output)
+    }
+}
+
+impl Provider for ScriptProvider<'_> {
+    fn prefix(&self) -> String {
+        self.name.to_string()
+    }
+
+    fn items(&self, context: &ProviderContext) -> Vec<ProvidedItem> {
+        let output = match self.get_output(context) {
+            Ok(output) => output,
+            Err(err) => {
+                log_warn(&tr("Ignoring the script {}: {}", &[&format!("{:?}", self.name), &err]));
+                return Vec::new();
+            },
+        };
+        output.lines()
+            .map(sanitize)
+            .filter(|line| !line.is_empty())
+            .map(|line| ProvidedItem {
+                launch: Launch::Command(format!("{} {}", self.script.command, quote_for_sh(&line))),
+                name: line,
+                comment: None,
+                icon: self.script.icon.clone(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect_windows() {
+        let tree = serde_json::json!({
+            "id": 1, "type": "root", "name": "root", "window": null,
+            "nodes": [{
+                "id": 2, "type": "workspace", "name": "1", "window": null,
+                "nodes": [
+                    {"id": 3, "type": "con", "name": "Mozilla Firefox", "window": 4194307,
+                     "window_properties": {"class": "firefox"}, "nodes": []},
+                    {"id": 4, "type": "con", "name": null, "window": null, "nodes": [
+                        {"id": 5, "type": "con", "name": "~", "app_id": "foot", "nodes": []},
+                    ]},
+                ],
+                "floating_nodes": [
+                    {"id": 6, "type": "floating_con", "name": "Calculator", "window": 4194400, "nodes": []},
+                ],
+            }],
+        });
+        let mut windows = Vec::new();
+        Windows::collect(&tree, &mut windows);
+        assert_eq!(windows, [
+            (3, "Mozilla Firefox".to_string(), Some("firefox".to_string())),
+            (5, "~".to_string(), Some("foot".to_string())),
+            (6, "Calculator".to_string(), None),
+        ]);
+    }
+
+    #[test]
+    fn test_script_provider() {
+        let cache_dir = std::env::temp_dir().join(format!("i3-dmenu-desktop-rs-scripts-{}", std::process::id()));
+        let cache_dir = cache_dir.to_str().unwrap();
+        let context = ProviderContext { home: "/nonexistent", env_paths: &[], session: SessionType::X11, cache_dir,
+                                        write_cache: true };
+        let script = Script { command: "printf 'a b\\n\\nc\\n'; date +%N".to_string(), cache_secs: 60, icon: None };
+        let provider = ScriptProvider { name: "test", script: &script };
+        let items = provider.items(&context);
+        let names: Vec<_> = items.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(names[..2], ["a b", "c"]);
+        assert!(matches!(&items[0].launch, Launch::Command(cmd) if *cmd == format!("{} 'a b'", script.command)));
+        // The output of date is cached too
+        let cached: Vec<_> = provider.items(&context).into_iter().map(|item| item.name).collect();
+        assert_eq!(cached, names);
+        fs::remove_dir_all(cache_dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_ssh_hosts() {
+        let hosts = SshHosts::parse_hosts(concat!(
+            "# Personal\n",
+            "Host example.com www\n",
+            "    HostName example.com\n",
+            "    User max\n",
+            "host=laptop\n",
+            "Host *.internal !bastion router\n",
+            "Host *\n",
+            "    ServerAliveInterval 60\n",
+            "  HOST www\n",
+        ));
+        assert_eq!(hosts, ["example.com", "www", "laptop", "router"]);
+    }
+}
@@ -0,0 +1,87 @@
+use std::env::VarError;
+
+use super::app_launcher::LaunchBackend;
+use super::menu::MenuProgram;
+
+/// The kind of graphical session the launcher is running in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionType {
+    I3,
+    Sway,
+    /// Some other Wayland compositor
+    Wayland,
+    /// Some other X11 window manager
+    X11,
+}
+
+impl SessionType {
+    /// Inspects the environment to find out which session we are running in.
+    pub fn detect<F>(get_env: F) -> Self
+    where
+        F: Fn(&str) -> Result<String, VarError>
+    {
+        // sway sets I3SOCK too, for compatibility with i3-msg
+        if get_env("SWAYSOCK").is_ok() {
+            Self::Sway
+        } else if get_env("I3SOCK").is_ok() {
+            // i3 exports I3SOCK to all of its child processes.
+            Self::I3
+        } else if get_env("WAYLAND_DISPLAY").is_ok()
+            || get_env("XDG_SESSION_TYPE").is_ok_and(|val| val == "wayland")
+        {
+            Self::Wayland
+        } else {
+            Self::X11
+        }
+    }
+
+    pub fn is_wayland(self) -> bool {
+        matches!(self, Self::Sway | Self::Wayland)
+    }
+
+    /// The menu programs to try if none was configured, in order of
+    /// preference. Wayland sessions prefer a native menu over running dmenu
+    /// under XWayland.
+    pub fn default_menu_programs(self) -> Vec<MenuProgram> {
+        use MenuProgram::*;
+        if self.is_wayland() {
+            vec![Fuzzel, Wofi, Tofi, Bemenu, Rofi, Dmenu]
+        } else {
+            vec![Dmenu, Rofi, Bemenu, Fuzzel, Wofi]
+        }
+    }
+
+    /// The launch backend which should be used if none was configured.
+    pub fn default_launch_backend(self) -> LaunchBackend {
+        match self {
+            Self::I3 => LaunchBackend::I3,
+            Self::Sway | Self::Wayland | Self::X11 => LaunchBackend::Exec,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect(vars: &[(&str, &str)]) -> SessionType {
+        SessionType::detect(|key| {
+            vars.iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.to_string())
+                .ok_or(VarError::NotPresent)
+        })
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect(&[]), SessionType::X11);
+        assert_eq!(detect(&[("I3SOCK", "/run/user/1000/i3/ipc-socket.1")]), SessionType::I3);
+        assert_eq!(
+            detect(&[("I3SOCK", "/run/user/1000/sway-ipc.sock"), ("SWAYSOCK", "/run/user/1000/sway-ipc.sock")]),
+            SessionType::Sway
+        );
+        assert_eq!(detect(&[("WAYLAND_DISPLAY", "wayland-1")]), SessionType::Wayland);
+        assert_eq!(detect(&[("XDG_SESSION_TYPE", "wayland")]), SessionType::Wayland);
+    }
+}
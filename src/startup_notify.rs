@@ -0,0 +1,153 @@
+use std::process;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use super::DesktopEntry;
+#[cfg(feature = "x11")]
+use super::i18n::tr;
+use super::utils::get_hostname;
+#[cfg(feature = "x11")]
+use super::utils::log_warn;
+
+const LAUNCHER_NAME: &str = "i3-dmenu-desktop-rs";
+
+// Distinguishes multiple sequences started by the same process (e.g. in
+// daemon mode).
+static SEQUENCE_NUMBER: AtomicU32 = AtomicU32::new(0);
+
+/// The launcher side of a startup notification sequence.
+///
+/// See https://specifications.freedesktop.org/startup-notification-spec/startup-notification-latest.txt.
+/// The launcher broadcasts a "new" message before spawning the application;
+/// the application is then responsible for completing the sequence once its
+/// window is mapped. If the launch fails, the launcher must abort the sequence
+/// itself by calling [`StartupSequence::remove`].
+pub struct StartupSequence {
+    id: String,
+}
+
+impl StartupSequence {
+    /// Generates a new startup ID for `app` and broadcasts the "new" message.
+    pub fn start(app: &DesktopEntry) -> Self {
+        let id = Self::generate_id(app, process::id(), &get_hostname());
+        let seq = Self { id };
+        let bin = app.get_program().unwrap_or_default();
+        broadcast("new", &[
+            ("ID", &seq.id),
+            ("NAME", &app.keys.Name),
+            ("BIN", &bin),
+            ("DESCRIPTION", &format!("Launching {}", app.keys.Name)),
+        ]);
+        seq
+    }
+
+    fn generate_id(app: &DesktopEntry, pid: u32, hostname: &str) -> String {
+        // The format is only a convention, but following it makes the IDs
+        // easier to recognize when debugging.
+        let seq_num = SEQUENCE_NUMBER.fetch_add(1, Ordering::Relaxed);
+        let app_name: String = app.keys.Name.chars()
+            .map(|ch| if ch.is_ascii_alphanumeric() { ch } else { '_' })
+            .collect();
+        format!("{LAUNCHER_NAME}-{pid}-{hostname}-{app_name}-{seq_num}")
+    }
+
+    /// The value which should be passed to the application via the
+    /// DESKTOP_STARTUP_ID environment variable.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Ends the sequence by broadcasting the "remove" message.
+    pub fn remove(self) {
+        broadcast("remove", &[("ID", &self.id)]);
+    }
+}
+
+#[cfg(any(feature = "x11", test))]
+fn format_message(kind: &str, fields: &[(&str, &str)]) -> String {
+    let mut message = format!("{kind}:");
+    for (key, value) in fields {
+        message.push(' ');
+        message.push_str(key);
+        message.push_str("=\"");
+        for ch in value.chars() {
+            if ch == '"' || ch == '\\' {
+                message.push('\\');
+            }
+            message.push(ch);
+        }
+        message.push('"');
+    }
+    message
+}
+
+#[cfg(feature = "x11")]
+fn broadcast(kind: &str, fields: &[(&str, &str)]) {
+    // Startup notification messages are only meaningful on X11.
+    if std::env::var_os("DISPLAY").is_none() {
+        return;
+    }
+    if let Err(err) = x11::broadcast(kind, fields) {
+        log_warn(&tr("Could not send startup notification: {}", &[&err]));
+    }
+}
+
+#[cfg(not(feature = "x11"))]
+fn broadcast(_kind: &str, _fields: &[(&str, &str)]) {}
+
+#[cfg(feature = "x11")]
+mod x11 {
+    use std::error::Error;
+
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{
+        ClientMessageEvent, ConnectionExt, CreateWindowAux, EventMask, WindowClass,
+    };
+    use x11rb::COPY_DEPTH_FROM_PARENT;
+
+    use super::format_message;
+
+    pub fn broadcast(kind: &str, fields: &[(&str, &str)]) -> Result<(), Box<dyn Error>> {
+        let (conn, screen_num) = x11rb::connect(None)?;
+        let screen = &conn.setup().roots[screen_num];
+        let mut message = format_message(kind, fields);
+        if kind == "new" {
+            message.push_str(&format!(" SCREEN={screen_num}"));
+        }
+        // The message is sent as a sequence of ClientMessage events from a
+        // dummy window, 20 bytes at a time, terminated by a nul byte.
+        let window = conn.generate_id()?;
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT, window, screen.root, -100, -100, 1, 1, 0,
+            WindowClass::INPUT_OUTPUT, screen.root_visual,
+            &CreateWindowAux::new().override_redirect(1),
+        )?;
+        let begin_atom = conn.intern_atom(false, b"_NET_STARTUP_INFO_BEGIN")?.reply()?.atom;
+        let info_atom = conn.intern_atom(false, b"_NET_STARTUP_INFO")?.reply()?.atom;
+        let mut bytes = message.into_bytes();
+        bytes.push(0);
+        for (i, chunk) in bytes.chunks(20).enumerate() {
+            let mut data = [0u8; 20];
+            data[..chunk.len()].copy_from_slice(chunk);
+            let atom = if i == 0 { begin_atom } else { info_atom };
+            let event = ClientMessageEvent::new(8, window, atom, data);
+            conn.send_event(false, screen.root, EventMask::PROPERTY_CHANGE, event)?;
+        }
+        conn.destroy_window(window)?;
+        conn.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_message() {
+        assert_eq!(
+            format_message("new", &[("ID", "abc"), ("NAME", r#"Say "hi" \o/"#)]),
+            r#"new: ID="abc" NAME="Say \"hi\" \\o/""#
+        );
+        assert_eq!(format_message("remove", &[("ID", "abc")]), r#"remove: ID="abc""#);
+    }
+}
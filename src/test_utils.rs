@@ -0,0 +1,82 @@
+//! Temporary XDG trees for tests. This file is shared by the unit tests and
+//! the integration tests, which include it with `#[path]`, so it only refers
+//! to the crate through `super`.
+
+// Each test binary only uses some of the helpers
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::env::VarError;
+use std::fs;
+use std::path::PathBuf;
+
+use super::XDGManager;
+
+/// A temporary home directory with a user data directory, a cache directory
+/// and two system data directories, which is deleted when dropped.
+pub struct Fixture {
+    root: PathBuf,
+}
+
+impl Fixture {
+    pub fn new(name: &str) -> Self {
+        let root = std::env::temp_dir().join(format!("i3-dmenu-desktop-rs-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for dir in ["home/.cache", "home/.local/share/applications", "usr/local/share/applications", "usr/share/applications", "bin"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        Self { root }
+    }
+
+    pub fn path(&self, relative: &str) -> String {
+        self.root.join(relative).to_str().unwrap().to_string()
+    }
+
+    pub fn write(&self, relative: &str, contents: &str) {
+        let path = self.root.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[cfg(feature = "launcher")]
+    pub fn write_script(&self, name: &str, contents: &str) {
+        let path = self.root.join("bin").join(name);
+        fs::write(&path, contents).unwrap();
+        fs::set_permissions(&path, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+    }
+
+    pub fn env(&self, lang: &str) -> HashMap<String, String> {
+        HashMap::from([
+            ("HOME".to_string(), self.path("home")),
+            ("XDG_DATA_HOME".to_string(), self.path("home/.local/share")),
+            ("XDG_DATA_DIRS".to_string(), format!("{}:{}", self.path("usr/local/share"), self.path("usr/share"))),
+            ("XDG_CACHE_HOME".to_string(), self.path("home/.cache")),
+            ("XDG_CONFIG_HOME".to_string(), self.path("home/.config")),
+            ("XDG_STATE_HOME".to_string(), self.path("home/.local/state")),
+            ("PATH".to_string(), format!("{}:/usr/bin:/bin", self.path("bin"))),
+            ("LANG".to_string(), lang.to_string()),
+        ])
+    }
+
+    pub fn manager(&self, lang: &str) -> XDGManager<impl Fn(&str) -> Result<String, VarError>> {
+        Self::manager_with_env(self.env(lang))
+    }
+
+    /// Returns a manager which sees `env` as its environment, e.g. the one
+    /// from [`Fixture::env`] with some variables changed.
+    pub fn manager_with_env(env: HashMap<String, String>) -> XDGManager<impl Fn(&str) -> Result<String, VarError>> {
+        XDGManager::new(move |key| env.get(key).cloned().ok_or(VarError::NotPresent))
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+/// Returns the contents of a desktop entry file with the keys that every
+/// application needs, followed by `extra` (lines ending with newlines).
+pub fn entry(name: &str, exec: &str, extra: &str) -> String {
+    format!("[Desktop Entry]\nType=Application\nName={}\nExec={}\n{}", name, exec, extra)
+}
@@ -1,4 +1,8 @@
+use std::collections::BTreeSet;
 use std::fmt::Debug;
+use std::fs;
+use std::hash::Hasher;
+use std::os::unix::fs::PermissionsExt;
 
 pub fn join_path(s1: &str, s2: &str) -> String {
     if s1.ends_with('/') {
@@ -11,3 +15,118 @@ pub fn join_path(s1: &str, s2: &str) -> String {
 pub fn log_warn(msg: &dyn Debug) {
     eprintln!("WARN: {:?}", msg);
 }
+
+#[cfg(feature = "launcher")]
+pub fn log_info(msg: &dyn Debug) {
+    eprintln!("INFO: {:?}", msg);
+}
+
+/// Returns the hostname, or an empty string if it cannot be read.
+pub fn get_hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|s| s.trim().to_string())
+        .unwrap_or_default()
+}
+
+/// The 64-bit FNV-1a hash. Unlike `DefaultHasher`, it stays the same
+/// across Rust releases, so it can be used for names of files.
+pub struct Fnv1aHasher(u64);
+
+impl Default for Fnv1aHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv1aHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+pub fn is_executable(path: &str) -> bool {
+    fs::metadata(path).is_ok_and(|m| m.permissions().mode() & 0o111 == 0o111)
+}
+
+/// The names of the executables in the directories of PATH, for checking
+/// whether many programs exist without searching PATH for each of them.
+pub struct PathExecutables(BTreeSet<String>);
+
+impl PathExecutables {
+    pub fn new(env_paths: &[String]) -> Self {
+        let mut names = BTreeSet::new();
+        for dir in env_paths {
+            let Ok(entries) = fs::read_dir(dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let Ok(name) = entry.file_name().into_string() else {
+                    continue;
+                };
+                if !names.contains(&name) && is_executable(&join_path(dir, &name)) {
+                    names.insert(name);
+                }
+            }
+        }
+        Self(names)
+    }
+
+    /// Returns the names of the executables, sorted.
+    #[cfg(feature = "launcher")]
+    pub fn names(&self) -> impl Iterator<Item=&String> {
+        self.0.iter()
+    }
+
+    /// Like [`find_executable`]: returns true if `program` is a path to an
+    /// executable, or the name of one in PATH.
+    pub fn contains(&self, program: &str) -> bool {
+        if program.contains('/') {
+            is_executable(program)
+        } else {
+            self.0.contains(program)
+        }
+    }
+}
+
+/// Returns true if `program` is an absolute path to an executable, or if
+/// an executable with that name exists in one of `env_paths`.
+pub fn find_executable(program: &str, env_paths: &[String]) -> bool {
+    if program.contains('/') {
+        is_executable(program)
+    } else {
+        env_paths.iter().any(|path| is_executable(&join_path(path, program)))
+    }
+}
+
+/// Returns `text` with control characters (which could e.g. inject extra
+/// rows into the menu) replaced by spaces, and without the surrounding
+/// whitespace which menus would strip from their output.
+pub fn sanitize(text: &str) -> String {
+    text.chars()
+        .map(|ch| if ch.is_control() { ' ' } else { ch })
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Returns `text` without diacritics (e.g. "Éditeur" becomes "Editeur"),
+/// and in lowercase unless `case_sensitive` is true, for matching typed
+/// text against names.
+#[cfg(feature = "launcher")]
+pub fn fold_for_matching(text: &str, case_sensitive: bool) -> String {
+    use unicode_normalization::char::is_combining_mark;
+    use unicode_normalization::UnicodeNormalization;
+
+    let folded: String = text.nfd().filter(|ch| !is_combining_mark(*ch)).collect();
+    if case_sensitive {
+        folded
+    } else {
+        folded.to_lowercase()
+    }
+}
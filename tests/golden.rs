@@ -0,0 +1,68 @@
+//! Parses the desktop entry files in tests/golden, which are taken from real
+//! applications, and compares the results with the expected ones next to
+//! them (e.g. steam.json for steam.desktop). Run with UPDATE_GOLDEN=1 to
+//! write the current results instead, and review the diff.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+use serde_json::json;
+
+use i3_dmenu_desktop_rs::desktop_entry::DesktopEntry;
+
+/// The files or URLs which the field codes in Exec are expanded with.
+const EXTRA_ARGS: [&str; 2] = ["/home/user/file name.txt", "https://example.com/?q=a b"];
+
+/// Returns the parse results for the desktop entry file at `path`, as
+/// pretty-printed JSON.
+fn get_results(path: &Path) -> String {
+    let contents = fs::read_to_string(path).unwrap();
+    let file_name = path.file_name().unwrap().to_str().unwrap();
+    let location = format!("/usr/share/applications/{}", file_name);
+    let locale_keys = ["de_DE".to_string(), "de".to_string()];
+    let mut app = DesktopEntry::parse_contents(&contents, &location, SystemTime::UNIX_EPOCH, &locale_keys).unwrap();
+    app.escape_chars_for_exec_keys();
+    let commands: BTreeMap<_, _> = std::iter::once((String::new(), app.clone()))
+        .chain(app.keys.Actions.iter().map(|action| (action.id.clone(), app.for_action(action))))
+        .map(|(id, app)| {
+            let exec = app.keys.Exec.as_deref().unwrap_or_default();
+            (id, app.replace_field_codes(exec, &EXTRA_ARGS))
+        })
+        .collect();
+    let results = json!({
+        "keys": app.keys,
+        "is_wine": app.is_wine(),
+        // The command lines for sh, by action ID ("" is the main Exec key)
+        "commands": commands,
+    });
+    serde_json::to_string_pretty(&results).unwrap() + "\n"
+}
+
+#[test]
+fn test_golden() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let mut paths: Vec<_> = fs::read_dir(&dir).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "desktop"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty());
+    let mut mismatches = Vec::new();
+    for path in paths {
+        let results = get_results(&path);
+        let expected_path = path.with_extension("json");
+        if update {
+            fs::write(&expected_path, &results).unwrap();
+            continue;
+        }
+        let expected = fs::read_to_string(&expected_path).unwrap_or_default();
+        if results != expected {
+            eprintln!("{} differs from the expected results:\n{}", path.display(), results);
+            mismatches.push(path);
+        }
+    }
+    assert!(mismatches.is_empty(), "unexpected results for {:?}", mismatches);
+}
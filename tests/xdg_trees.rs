@@ -0,0 +1,366 @@
+//! Runs the scanning of data directories and the launcher as a whole against
+//! temporary XDG trees.
+
+use std::env::VarError;
+#[cfg(feature = "launcher")]
+use std::fs;
+
+use i3_dmenu_desktop_rs::{Persistence, XDGManager};
+
+#[path = "../src/test_utils.rs"]
+mod test_utils;
+
+use test_utils::{entry, Fixture};
+
+/// Returns the manager of `fixture`, which neither reads nor writes the
+/// cache, so that each test sees what is on disk.
+fn manager(fixture: &Fixture, lang: &str) -> XDGManager<impl Fn(&str) -> Result<String, VarError>> {
+    let mut mgr = fixture.manager(lang);
+    mgr.set_persistence(Persistence { cache: false, history: false });
+    mgr
+}
+
+#[test]
+fn test_collisions() {
+    let fixture = Fixture::new("collisions");
+    fixture.write("usr/share/applications/editor.desktop", &entry("System Editor", "editor", ""));
+    fixture.write("usr/local/share/applications/editor.desktop", &entry("Local Editor", "editor", ""));
+    fixture.write("home/.local/share/applications/editor.desktop", &entry("User Editor", "editor", ""));
+    // Two entries with different IDs but the same name are both listed
+    fixture.write("usr/share/applications/term-a.desktop", &entry("Terminal", "term-a", ""));
+    fixture.write("usr/share/applications/term-b.desktop", &entry("Terminal", "term-b", ""));
+    // Entries in subdirectories get the directory as a prefix of their ID
+    fixture.write("usr/share/applications/kde/editor.desktop", &entry("KDE Editor", "kate", ""));
+
+    let apps = manager(&fixture, "C").list_desktop_entries();
+    let ids: Vec<_> = apps.iter().map(|app| (app.source.id.as_str(), app.keys.Name.as_str())).collect();
+    assert_eq!(ids, [
+        ("editor.desktop", "User Editor"),
+        ("kde-editor.desktop", "KDE Editor"),
+        ("term-a.desktop", "Terminal"),
+        ("term-b.desktop", "Terminal"),
+    ]);
+    let (app_map, _) = manager(&fixture, "C").get_desktop_entry_map();
+    let mut names: Vec<_> = app_map.keys().map(String::as_str).collect();
+    names.sort();
+    assert_eq!(names, ["KDE Editor", "Terminal", "Terminal (2)", "User Editor"]);
+}
+
+#[test]
+fn test_hidden_override() {
+    let fixture = Fixture::new("hidden");
+    fixture.write("usr/share/applications/ads.desktop", &entry("Ads", "ads", ""));
+    fixture.write("home/.local/share/applications/ads.desktop", &entry("Ads", "ads", "Hidden=true\n"));
+    fixture.write("usr/share/applications/broken.desktop", "[Desktop Entry]\nName=Broken\n");
+
+    let (app_map, stats) = manager(&fixture, "C").get_desktop_entry_map();
+    assert_eq!(app_map.len(), 1);
+    assert!(app_map["Ads [Hidden]"].keys.Hidden);
+    assert_eq!(stats.parse_failures, 1);
+}
+
+#[test]
+fn test_locales() {
+    let fixture = Fixture::new("locales");
+    fixture.write("usr/share/applications/files.desktop",
+                  &entry("Files", "files", "Name[de]=Dateien\nName[de_AT]=Dateien (AT)\nComment=Browse\nComment[de]=Durchsuchen\n"));
+
+    let name_for = |lang| manager(&fixture, lang).list_desktop_entries()[0].keys.Name.clone();
+    assert_eq!(name_for("C"), "Files");
+    assert_eq!(name_for("de_DE.UTF-8"), "Dateien");
+    assert_eq!(name_for("de_AT.UTF-8"), "Dateien (AT)");
+    let app = &manager(&fixture, "de_DE.UTF-8").list_desktop_entries()[0];
+    assert_eq!(app.keys.Comment.as_deref(), Some("Durchsuchen"));
+
+    // LANGUAGE takes precedence over the locale, and entries cached for
+    // another locale are parsed again
+    let cached_name_for = |language: &str| {
+        let mut env = fixture.env("de_DE.UTF-8");
+        env.insert("LANGUAGE".to_string(), language.to_string());
+        let mut mgr = XDGManager::new(move |key| env.get(key).cloned().ok_or(VarError::NotPresent));
+        mgr.set_persistence(Persistence { cache: true, history: false });
+        mgr.list_desktop_entries()[0].keys.Name.clone()
+    };
+    assert_eq!(cached_name_for("de"), "Dateien");
+    assert_eq!(cached_name_for("fr:de_AT"), "Dateien (AT)");
+}
+
+#[cfg(feature = "launcher")]
+#[test]
+fn test_diagnostics() {
+    use i3_dmenu_desktop_rs::config::{Config, Mode, TerminalFilter, WineMode};
+    use i3_dmenu_desktop_rs::diagnostics::Reason;
+
+    let fixture = Fixture::new("diagnostics");
+    fixture.write("home/.local/share/applications/editor.desktop", &entry("User Editor", "editor", ""));
+    fixture.write("usr/share/applications/editor.desktop", &entry("Editor", "editor", ""));
+    fixture.write("usr/share/applications/broken.desktop", "[Desktop Entry]\nName=Broken\n");
+    fixture.write("usr/share/applications/site.desktop", "[Desktop Entry]\nType=Link\nName=Site\nURL=https://example.com\n");
+    fixture.write("usr/share/applications/hidden.desktop", &entry("Hidden", "hidden", "Hidden=true\n"));
+    fixture.write("usr/share/applications/helper.desktop", &entry("Helper", "helper", "NoDisplay=true\n"));
+    fixture.write("usr/share/applications/missing.desktop", &entry("Missing", "missing", "TryExec=not-installed\n"));
+    fixture.write("usr/share/applications/viewer.desktop", &entry("Viewer", "viewer", ""));
+    fixture.write("usr/share/applications/top.desktop", &entry("Top", "top", "Terminal=true\n"));
+    fixture.write("usr/share/applications/wine-notepad.desktop", &entry("Notepad", "wine notepad.exe", ""));
+    fixture.write("home/.config/autostart/kde-only.desktop", &entry("KDE Only", "kde", "OnlyShowIn=KDE;\n"));
+
+    let mgr = manager(&fixture, "C");
+    let reasons = |config: &Config| {
+        mgr.diagnose_desktop_entries(config).into_iter()
+            .filter(|diagnostic| diagnostic.path.starts_with(&fixture.path("")))
+            .map(|diagnostic| (diagnostic.id, diagnostic.reason))
+            .collect::<Vec<_>>()
+    };
+    let mut config = Config::default();
+    assert_eq!(reasons(&config), [
+        ("broken.desktop".to_string(), Reason::ParseError),
+        ("editor.desktop".to_string(), Reason::Masked),
+        ("helper.desktop".to_string(), Reason::NoDisplay),
+        ("hidden.desktop".to_string(), Reason::Hidden),
+        ("missing.desktop".to_string(), Reason::TryExecMissing),
+        ("site.desktop".to_string(), Reason::NotApplication),
+    ]);
+    config.terminal_apps = TerminalFilter::Exclude;
+    let terminal_apps: Vec<_> = reasons(&config).into_iter().filter(|(_, reason)| *reason == Reason::TerminalApp).collect();
+    assert_eq!(terminal_apps, [("top.desktop".to_string(), Reason::TerminalApp)]);
+    config.terminal_apps = TerminalFilter::Only;
+    assert!(reasons(&config).contains(&("viewer.desktop".to_string(), Reason::TerminalApp)));
+    config.terminal_apps = TerminalFilter::Include;
+    config.wine_apps = WineMode::Exclude;
+    let wine_apps: Vec<_> = reasons(&config).into_iter().filter(|(_, reason)| *reason == Reason::WineApp).collect();
+    assert_eq!(wine_apps, [("wine-notepad.desktop".to_string(), Reason::WineApp)]);
+    config.wine_apps = WineMode::Show;
+    config.include_nodisplay = true;
+    assert!(!reasons(&config).iter().any(|(_, reason)| *reason == Reason::NoDisplay));
+    config.mode = Mode::Autostart;
+    assert_eq!(reasons(&config), [("kde-only.desktop".to_string(), Reason::NotShownIn)]);
+}
+
+/// Runs the launcher with a fake dmenu which selects `choice`, and returns
+/// the rows which it was given and what got launched.
+#[cfg(feature = "launcher")]
+fn run_launcher(fixture: &Fixture, lang: &str, choice: &str) -> (Vec<String>, Option<String>) {
+    use std::process::Command;
+    use std::time::{Duration, Instant};
+
+    let menu_input = fixture.path("menu_input");
+    let launch_log = fixture.path("launch_log");
+    let _ = fs::remove_file(&launch_log);
+    fixture.write_script("dmenu", &format!("#!/bin/sh\ncat > '{}'\nprintf '%s\\n' \"$CHOICE\"\n", menu_input));
+    fixture.write_script("record", &format!("#!/bin/sh\necho \"$@\" > '{}.tmp'\nmv '{0}.tmp' '{0}'\n", launch_log));
+    let status = Command::new(env!("CARGO_BIN_EXE_i3-dmenu-desktop-rs"))
+        .args(["--menu", "dmenu", "--launch-backend", "exec", "--ephemeral"])
+        .env_clear()
+        .envs(fixture.env(lang))
+        .env("CHOICE", choice)
+        .status()
+        .unwrap();
+    assert!(status.success(), "the launcher exited with {}", status);
+    let rows = fs::read_to_string(&menu_input).unwrap().lines().map(String::from).collect();
+    // The launched command runs in the background
+    let start = Instant::now();
+    while !fs::exists(&launch_log).unwrap() && start.elapsed() < Duration::from_secs(5) {
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    (rows, fs::read_to_string(&launch_log).ok().map(|log| log.trim_end().to_string()))
+}
+
+#[cfg(feature = "launcher")]
+#[test]
+fn test_launch_entry() {
+    let fixture = Fixture::new("launch");
+    fixture.write("usr/share/applications/viewer.desktop", &entry("Viewer", "record system", "Name[de]=Betrachter\n"));
+    fixture.write("home/.local/share/applications/viewer.desktop", &entry("Viewer", "record user \"a b\"", "Name[de]=Betrachter\n"));
+    fixture.write("usr/share/applications/ads.desktop", &entry("Ads", "record ads", ""));
+    fixture.write("home/.local/share/applications/ads.desktop", &entry("Ads", "record ads", "Hidden=true\n"));
+
+    let (rows, launched) = run_launcher(&fixture, "de_DE.UTF-8", "Betrachter");
+    assert_eq!(rows, ["Betrachter"]);
+    assert_eq!(launched.as_deref(), Some("user a b"));
+}
+
+#[cfg(feature = "launcher")]
+#[test]
+fn test_launch_missing_program() {
+    use std::process::{Command, Stdio};
+
+    let fixture = Fixture::new("missing-program");
+    fixture.write("usr/share/applications/broken.desktop", &entry("Broken", "no-such-program", ""));
+    fixture.write_script("dmenu", "#!/bin/sh\ncat > /dev/null\necho Broken\n");
+    let status = Command::new(env!("CARGO_BIN_EXE_i3-dmenu-desktop-rs"))
+        .args(["--menu", "dmenu", "--launch-backend", "exec", "--ephemeral"])
+        .env_clear()
+        .envs(fixture.env("C"))
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(4));
+}
+
+#[cfg(feature = "launcher")]
+#[test]
+fn test_launch_command() {
+    let fixture = Fixture::new("command");
+    fixture.write("usr/share/applications/viewer.desktop", &entry("Viewer", "record viewer", ""));
+
+    let (_, launched) = run_launcher(&fixture, "C", "record typed 'c d'");
+    assert_eq!(launched.as_deref(), Some("typed c d"));
+}
+
+#[cfg(feature = "launcher")]
+#[test]
+fn test_launch_alias() {
+    let fixture = Fixture::new("alias");
+    fixture.write("usr/share/applications/org.mozilla.firefox.desktop", &entry("Firefox", "record firefox", ""));
+    fixture.write("usr/share/applications/settings.desktop", &entry("Settings", "record settings", "NoDisplay=true\n"));
+    fixture.write("home/.config/i3-dmenu-desktop-rs/config.toml",
+                  "aliases = { ff = \"org.mozilla.firefox.desktop\", prefs = \"settings\" }\nlist_aliases = true\n");
+
+    let (rows, launched) = run_launcher(&fixture, "C", "FF https://example.com");
+    assert_eq!(rows, ["Firefox", "ff", "prefs"]);
+    assert_eq!(launched.as_deref(), Some("firefox"));
+    let (_, launched) = run_launcher(&fixture, "C", "prefs");
+    assert_eq!(launched.as_deref(), Some("settings"));
+}
+
+#[cfg(feature = "launcher")]
+#[test]
+fn test_run_provider() {
+    let fixture = Fixture::new("run");
+    fixture.write("usr/share/applications/viewer.desktop", &entry("Viewer", "record viewer", ""));
+    fixture.write("home/.config/i3-dmenu-desktop-rs/config.toml", "providers = [\"run\"]\n");
+
+    let (rows, launched) = run_launcher(&fixture, "C", "record");
+    assert!(rows.iter().any(|row| row == "record"));
+    assert!(!rows.iter().any(|row| row == "Viewer"));
+    assert_eq!(launched.as_deref(), Some(""));
+}
+
+#[cfg(feature = "launcher")]
+#[test]
+fn test_provider_prefixes() {
+    let fixture = Fixture::new("prefixes");
+    fixture.write("usr/share/applications/viewer.desktop", &entry("Viewer", "record viewer", ""));
+    fixture.write("home/.config/i3-dmenu-desktop-rs/config.toml",
+                  "providers = [\"drun\", \"run\"]\nprovider_prefixes = true\n");
+
+    let (rows, launched) = run_launcher(&fixture, "C", "drun: Viewer");
+    assert!(rows.iter().any(|row| row == "drun: Viewer"));
+    assert!(rows.iter().any(|row| row == "run: record"));
+    assert_eq!(launched.as_deref(), Some("viewer"));
+    // Typed text with a prefix
+    let (_, launched) = run_launcher(&fixture, "C", "run: record typed words");
+    assert_eq!(launched.as_deref(), Some("typed words"));
+}
+
+#[cfg(feature = "launcher")]
+#[test]
+fn test_warn_once() {
+    use std::process::Command;
+    use std::time::{Duration, SystemTime};
+
+    let fixture = Fixture::new("warn-once");
+    fs::create_dir_all(fixture.path("home/.cache")).unwrap();
+    let path = fixture.path("usr/share/applications/broken.desktop");
+    fixture.write("usr/share/applications/broken.desktop", "[Desktop Entry]\nName=Broken\n");
+    let warnings = || {
+        let output = Command::new(env!("CARGO_BIN_EXE_i3-dmenu-desktop-rs"))
+            .arg("--list-json")
+            .env_clear()
+            .envs(fixture.env("C"))
+            .output()
+            .unwrap();
+        String::from_utf8(output.stderr).unwrap()
+    };
+    assert!(warnings().contains(&format!("Could not parse {}", path)));
+    assert_eq!(warnings(), "");
+    // Until the file is modified
+    fs::File::options().write(true).open(&path).unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(10)).unwrap();
+    assert!(warnings().contains(&format!("Could not parse {}", path)));
+    assert_eq!(warnings(), "");
+
+    // Directories whose mtime did not change are not read again, but the
+    // files in them which were warned about are still remembered
+    let backdate = || {
+        let dir = fixture.path("usr/share/applications");
+        fs::File::open(&dir).unwrap().set_modified(SystemTime::now() - Duration::from_secs(60)).unwrap();
+    };
+    backdate();
+    assert_eq!(warnings(), "");
+    assert_eq!(warnings(), "");
+    fixture.write("usr/share/applications/viewer.desktop", &entry("Viewer", "viewer", ""));
+    backdate();
+    assert_eq!(warnings(), "");
+}
+
+#[cfg(feature = "launcher")]
+#[test]
+fn test_daemon() {
+    use std::process::{Child, Command};
+    use std::time::{Duration, Instant};
+
+    /// Stops the daemon even if an assertion fails
+    struct Daemon(Child);
+
+    impl Drop for Daemon {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    let fixture = Fixture::new("daemon");
+    fixture.write("usr/share/applications/viewer.desktop", &entry("Viewer", "record viewer", ""));
+    let menu_input = fixture.path("menu_input");
+    let launch_log = fixture.path("launch_log");
+    // Selects the first row
+    fixture.write_script("dmenu", &format!("#!/bin/sh\ncat > '{0}'\nhead -n 1 '{0}'\n", menu_input));
+    fixture.write_script("record", &format!("#!/bin/sh\necho \"$@\" > '{}'\n", launch_log));
+    let daemon = Daemon(Command::new(env!("CARGO_BIN_EXE_i3-dmenu-desktop-rs"))
+        .args(["--daemon", "--menu", "dmenu", "--launch-backend", "exec", "--ephemeral"])
+        .env_clear()
+        .envs(fixture.env("C"))
+        .env("XDG_RUNTIME_DIR", fixture.path("run"))
+        .spawn()
+        .unwrap());
+    let start = Instant::now();
+    while !fs::exists(fixture.path("run/i3-dmenu-desktop-rs.sock")).unwrap() && start.elapsed() < Duration::from_secs(5) {
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    let run_client = || {
+        Command::new(env!("CARGO_BIN_EXE_i3-dmenu-desktop-rs"))
+            .args(["--client", "--provider", "drun"])
+            .env_clear()
+            .envs(fixture.env("C"))
+            .env("XDG_RUNTIME_DIR", fixture.path("run"))
+            .status()
+            .unwrap()
+    };
+    let status = run_client();
+    assert!(status.success(), "the client exited with {}", status);
+    assert_eq!(fs::read_to_string(&menu_input).unwrap(), "Viewer");
+    // The daemon notices entries which were added since the last request
+    fixture.write("usr/share/applications/editor.desktop", &entry("Editor", "record editor", ""));
+    assert!(run_client().success());
+    assert_eq!(fs::read_to_string(&menu_input).unwrap(), "Editor\nViewer");
+    // Files whose mtime, size and inode did not change are only parsed again
+    // after SIGUSR1
+    let path = fixture.path("usr/share/applications/viewer.desktop");
+    let mtime = fs::metadata(&path).unwrap().modified().unwrap();
+    fixture.write("usr/share/applications/viewer.desktop", &entry("Apollo", "record viewer", ""));
+    fs::File::options().write(true).open(&path).unwrap().set_modified(mtime).unwrap();
+    run_client();
+    assert_eq!(fs::read_to_string(&menu_input).unwrap(), "Editor\nViewer");
+    Command::new("kill").args(["-USR1", &daemon.0.id().to_string()]).status().unwrap();
+    std::thread::sleep(Duration::from_millis(200));
+    run_client();
+    assert_eq!(fs::read_to_string(&menu_input).unwrap(), "Apollo\nEditor");
+    drop(daemon);
+    // The launched command runs in the background
+    let start = Instant::now();
+    while fs::read_to_string(&launch_log).ok().as_deref() != Some("viewer\n") && start.elapsed() < Duration::from_secs(5) {
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    assert_eq!(fs::read_to_string(&launch_log).unwrap(), "viewer\n");
+}